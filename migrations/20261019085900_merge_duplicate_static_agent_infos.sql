@@ -0,0 +1,34 @@
+-- Add migration script here
+
+-- merge duplicate agents of a reset into the oldest entry before enforcing uniqueness
+with canonical as (select id
+                        , min(id) over (partition by reset_id, agent_symbol) as canonical_id
+                     from static_agent_info)
+delete
+  from agent_log
+ where rowid in (select al.rowid
+                   from agent_log al
+                            join canonical c on al.agent_id = c.id
+                  where c.id != c.canonical_id
+                    and exists (select 1
+                                  from agent_log kept
+                                 where kept.agent_id = c.canonical_id
+                                   and kept.job_id = al.job_id))
+;
+
+with canonical as (select id
+                        , min(id) over (partition by reset_id, agent_symbol) as canonical_id
+                     from static_agent_info)
+update agent_log
+   set agent_id = c.canonical_id
+  from canonical c
+ where agent_log.agent_id = c.id
+   and c.id != c.canonical_id
+;
+
+delete
+  from static_agent_info
+ where id not in (select min(id)
+                    from static_agent_info
+                   group by reset_id, agent_symbol)
+;
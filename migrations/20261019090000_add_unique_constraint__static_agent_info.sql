@@ -0,0 +1,40 @@
+-- Add migration script here
+
+-- duplicates got merged by 20261019085900_merge_duplicate_static_agent_infos.sql
+create unique index ux_static_agent_info__reset_id_agent_symbol on static_agent_info (reset_id, agent_symbol);
+
+
+-- previous values of static_agent_info, written whenever HQ, faction or construction site of an agent change
+create table static_agent_info_history
+(
+    id                                 integer  not null primary key,
+    static_agent_info_id               integer  not null,
+    agent_headquarters_waypoint_symbol text     not null,
+    construction_site_id               integer  not null,
+    starting_faction                   text     not null,
+    replaced_at                        datetime not null,
+    foreign key (static_agent_info_id) references static_agent_info (id),
+    foreign key (construction_site_id) references construction_site (id)
+);
+
+create index ix_static_agent_info_history__static_agent_info_id on static_agent_info_history (static_agent_info_id);
+
+create trigger tr_static_agent_info__history
+    after update of agent_headquarters_waypoint_symbol, construction_site_id, starting_faction
+    on static_agent_info
+    for each row
+    when old.agent_headquarters_waypoint_symbol is not new.agent_headquarters_waypoint_symbol
+        or old.construction_site_id is not new.construction_site_id
+        or old.starting_faction is not new.starting_faction
+begin
+    insert into static_agent_info_history (static_agent_info_id,
+                                           agent_headquarters_waypoint_symbol,
+                                           construction_site_id,
+                                           starting_faction,
+                                           replaced_at)
+    values (old.id,
+            old.agent_headquarters_waypoint_symbol,
+            old.construction_site_id,
+            old.starting_faction,
+            datetime('now'));
+end;
@@ -0,0 +1,11 @@
+-- Add migration script here
+
+-- tokens for the admin api. Only the sha256 of a token is stored.
+create table api_token
+(
+    id           integer  not null primary key,
+    name         text     not null unique,
+    token_sha256 text     not null unique,
+    created_at   datetime not null,
+    revoked_at   datetime
+);
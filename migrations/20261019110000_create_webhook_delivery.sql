@@ -0,0 +1,20 @@
+-- Add migration script here
+
+-- delivery log of the leaderboard events to the configured webhooks.
+-- The unique key prevents sending an event twice to the same webhook.
+create table webhook_delivery
+(
+    id               integer  not null primary key,
+    webhook_name     text     not null,
+    event_key        text     not null,
+    event_kind       text     not null,
+    payload          text     not null,
+    -- pending, delivered or failed
+    status           text     not null,
+    attempts         integer  not null,
+    last_http_status integer,
+    last_error       text,
+    created_at       datetime not null,
+    updated_at       datetime not null,
+    unique (webhook_name, event_key)
+);
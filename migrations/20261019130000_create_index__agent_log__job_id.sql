@@ -0,0 +1,3 @@
+-- Add migration script here
+-- the leaderboard of a job run gets filtered and paged in sql - ix_agent_log__agent_id_job_id can't serve "where job_id = ?"
+create index if not exists ix_agent_log__job_id on agent_log (job_id);
@@ -0,0 +1,13 @@
+-- Add migration script here
+
+-- bumped whenever existing data changes outside of a collector tick (import, repair, refresh of the materialized views).
+-- part of the ETags of the read endpoints
+create table data_generation
+(
+    id         integer  not null primary key check (id = 1),
+    generation integer  not null,
+    updated_at datetime not null
+);
+
+insert into data_generation (id, generation, updated_at)
+values (1, 0, datetime('now'));
@@ -0,0 +1,321 @@
+use axum::body::Body;
+use axum::extract::{FromRef, FromRequestParts, State};
+use axum::http::request::Parts;
+use axum::http::{header, HeaderMap};
+use axum::response::{IntoResponse, Response};
+use std::time::Instant;
+
+use axum::{async_trait, routing, Json, Router};
+use serde::Serialize;
+use sqlx::{Pool, Sqlite};
+use tokio_util::io::ReaderStream;
+use tracing::{event, Level};
+
+use crate::api_error::{ApiError, ApiPath};
+use crate::api_token::is_valid_api_token;
+use crate::backup::{backup_file_path, create_backup, BackupResult, BackupSettings};
+use crate::collector_control::{CollectorControl, CollectorStatus, TickError};
+use crate::db::{
+    bump_data_generation, force_wal_checkpoint, refresh_fake_materialized_view,
+    select_has_active_api_tokens,
+};
+use crate::leaderboard_collector::TickCompleted;
+use crate::server::{AppState, QueryCache, QueryCacheStatsSnapshot};
+
+/// Settings for the operator endpoints below `/api/admin`.
+/// The admin api is disabled if neither `admin_token` nor a token in the `api_token` table is configured.
+#[derive(Debug, Clone, Default)]
+pub(crate) struct AdminSettings {
+    pub(crate) admin_token: Option<String>,
+    pub(crate) backup_settings: Option<BackupSettings>,
+}
+
+pub(crate) fn admin_router() -> Router<AppState> {
+    Router::new()
+        .route("/api/admin/backup", routing::post(post_backup))
+        .route(
+            "/api/admin/backups/:file_name",
+            routing::get(download_backup),
+        )
+        .route(
+            "/api/admin/query-cache",
+            routing::get(get_query_cache_stats),
+        )
+        .route("/api/admin/collector", routing::get(get_collector_status))
+        .route("/api/admin/collector/tick", routing::post(post_tick))
+        .route("/api/admin/collector/pause", routing::post(post_pause))
+        .route("/api/admin/collector/resume", routing::post(post_resume))
+        .route(
+            "/api/admin/materialized-views/refresh",
+            routing::post(post_refresh_materialized_views),
+        )
+        .route(
+            "/api/admin/wal-checkpoint",
+            routing::post(post_wal_checkpoint),
+        )
+}
+
+#[derive(Serialize, Debug)]
+#[serde(rename_all = "camelCase")]
+struct MaintenanceResult {
+    duration_millis: u128,
+}
+
+/// Extractor that only succeeds if the request carries the configured admin token or an active
+/// token of the `api_token` table as bearer token.
+pub(crate) struct AdminAuth;
+
+#[async_trait]
+impl<S> FromRequestParts<S> for AdminAuth
+where
+    AdminSettings: FromRef<S>,
+    Pool<Sqlite>: FromRef<S>,
+    S: Send + Sync,
+{
+    type Rejection = ApiError;
+
+    async fn from_request_parts(parts: &mut Parts, state: &S) -> Result<Self, Self::Rejection> {
+        let settings = AdminSettings::from_ref(state);
+        let pool = Pool::<Sqlite>::from_ref(state);
+
+        if let Some(token) = bearer_token(&parts.headers) {
+            if is_valid_api_token(&pool, settings.admin_token.as_deref(), token).await? {
+                return Ok(AdminAuth);
+            }
+        }
+
+        if settings.admin_token.is_none() && !select_has_active_api_tokens(&pool).await? {
+            return Err(ApiError::NotFound("admin api is disabled".to_string()));
+        }
+        Err(ApiError::Unauthorized(
+            "missing or invalid admin token".to_string(),
+        ))
+    }
+}
+
+pub(crate) fn bearer_token(headers: &HeaderMap) -> Option<&str> {
+    headers
+        .get(header::AUTHORIZATION)
+        .and_then(|value| value.to_str().ok())
+        .and_then(|value| value.strip_prefix("Bearer "))
+}
+
+async fn post_backup(
+    _auth: AdminAuth,
+    State(pool): State<Pool<Sqlite>>,
+    State(settings): State<AdminSettings>,
+) -> Result<Json<BackupResult>, ApiError> {
+    let backup_settings = settings
+        .backup_settings
+        .ok_or_else(|| ApiError::Conflict("no backup dir configured".to_string()))?;
+
+    create_backup(&pool, &backup_settings)
+        .await
+        .map(Json)
+        .map_err(|err| {
+            event!(Level::ERROR, "Error creating backup: {err:?}");
+            err.into()
+        })
+}
+
+async fn get_query_cache_stats(
+    _auth: AdminAuth,
+    State(query_cache): State<QueryCache>,
+) -> Json<QueryCacheStatsSnapshot> {
+    Json(query_cache.stats())
+}
+
+async fn get_collector_status(
+    _auth: AdminAuth,
+    State(collector_control): State<CollectorControl>,
+) -> Json<CollectorStatus> {
+    Json(collector_control.status())
+}
+
+/// Runs a tick right away - also while the scheduler is paused. Responds after the tick is done.
+/// The tick runs in its own task, so that a client disconnecting doesn't cancel it halfway.
+async fn post_tick(
+    _auth: AdminAuth,
+    State(collector_control): State<CollectorControl>,
+) -> Result<Json<TickCompleted>, ApiError> {
+    tokio::spawn(async move { collector_control.tick().await })
+        .await
+        .map_err(|err| ApiError::Internal(format!("tick task failed: {err}")))?
+        .map(Json)
+        .map_err(|err| match err {
+            TickError::AlreadyRunning => {
+                ApiError::Conflict("a tick is already running".to_string())
+            }
+            TickError::Failed(err) => ApiError::Internal(format!("tick failed: {err:#}")),
+        })
+}
+
+async fn post_pause(
+    _auth: AdminAuth,
+    State(collector_control): State<CollectorControl>,
+) -> Json<CollectorStatus> {
+    collector_control.set_paused(true);
+    Json(collector_control.status())
+}
+
+async fn post_resume(
+    _auth: AdminAuth,
+    State(collector_control): State<CollectorControl>,
+) -> Json<CollectorStatus> {
+    collector_control.set_paused(false);
+    Json(collector_control.status())
+}
+
+async fn post_refresh_materialized_views(
+    _auth: AdminAuth,
+    State(pool): State<Pool<Sqlite>>,
+    State(query_cache): State<QueryCache>,
+) -> Result<Json<MaintenanceResult>, ApiError> {
+    let start = Instant::now();
+    refresh_fake_materialized_view(&pool).await.map_err(|err| {
+        ApiError::Internal(format!("refreshing materialized views failed: {err:#}"))
+    })?;
+    // cached responses might be based on the old views
+    bump_data_generation(&mut *pool.acquire().await?).await?;
+    query_cache.clear();
+
+    Ok(Json(MaintenanceResult {
+        duration_millis: start.elapsed().as_millis(),
+    }))
+}
+
+async fn post_wal_checkpoint(
+    _auth: AdminAuth,
+    State(pool): State<Pool<Sqlite>>,
+) -> Result<Json<MaintenanceResult>, ApiError> {
+    let start = Instant::now();
+    force_wal_checkpoint(&pool).await?;
+
+    Ok(Json(MaintenanceResult {
+        duration_millis: start.elapsed().as_millis(),
+    }))
+}
+
+async fn download_backup(
+    _auth: AdminAuth,
+    State(settings): State<AdminSettings>,
+    ApiPath(file_name): ApiPath<String>,
+) -> Result<Response, ApiError> {
+    let backup_settings = settings
+        .backup_settings
+        .ok_or_else(|| ApiError::Conflict("no backup dir configured".to_string()))?;
+
+    let path = backup_file_path(&backup_settings, &file_name)
+        .ok_or_else(|| ApiError::BadRequest("invalid backup file name".to_string()))?;
+
+    let file = tokio::fs::File::open(path)
+        .await
+        .map_err(|_| ApiError::NotFound(format!("backup {file_name} not found")))?;
+
+    Ok((
+        [
+            (header::CONTENT_TYPE, "application/gzip".to_string()),
+            (
+                header::CONTENT_DISPOSITION,
+                format!("attachment; filename=\"{file_name}\""),
+            ),
+        ],
+        Body::from_stream(ReaderStream::new(file)),
+    )
+        .into_response())
+}
+
+#[cfg(test)]
+mod tests {
+    use axum::body::Body;
+    use axum::http::{Request, StatusCode};
+    use tower::ServiceExt;
+
+    use crate::db::seeded_test_pool;
+
+    use super::*;
+
+    #[derive(Clone)]
+    struct TestState {
+        pool: Pool<Sqlite>,
+        admin_settings: AdminSettings,
+        query_cache: QueryCache,
+    }
+
+    impl FromRef<TestState> for Pool<Sqlite> {
+        fn from_ref(state: &TestState) -> Self {
+            state.pool.clone()
+        }
+    }
+
+    impl FromRef<TestState> for AdminSettings {
+        fn from_ref(state: &TestState) -> Self {
+            state.admin_settings.clone()
+        }
+    }
+
+    impl FromRef<TestState> for QueryCache {
+        fn from_ref(state: &TestState) -> Self {
+            state.query_cache.clone()
+        }
+    }
+
+    async fn query_cache_stats_status(
+        admin_token: Option<&str>,
+        authorization: Option<&str>,
+    ) -> StatusCode {
+        let router = Router::new()
+            .route(
+                "/api/admin/query-cache",
+                routing::get(get_query_cache_stats),
+            )
+            .with_state(TestState {
+                pool: seeded_test_pool("").await,
+                admin_settings: AdminSettings {
+                    admin_token: admin_token.map(str::to_string),
+                    backup_settings: None,
+                },
+                query_cache: QueryCache::default(),
+            });
+
+        let mut request = Request::get("/api/admin/query-cache");
+        if let Some(authorization) = authorization {
+            request = request.header(header::AUTHORIZATION, authorization);
+        }
+        router
+            .oneshot(request.body(Body::empty()).unwrap())
+            .await
+            .unwrap()
+            .status()
+    }
+
+    #[tokio::test]
+    async fn admin_endpoints_require_the_token() {
+        let admin_token = Some("secret");
+
+        assert_eq!(
+            query_cache_stats_status(admin_token, None).await,
+            StatusCode::UNAUTHORIZED
+        );
+        assert_eq!(
+            query_cache_stats_status(admin_token, Some("Bearer wrong")).await,
+            StatusCode::UNAUTHORIZED
+        );
+        assert_eq!(
+            query_cache_stats_status(admin_token, Some("secret")).await,
+            StatusCode::UNAUTHORIZED
+        );
+        assert_eq!(
+            query_cache_stats_status(admin_token, Some("Bearer secret")).await,
+            StatusCode::OK
+        );
+    }
+
+    #[tokio::test]
+    async fn admin_api_is_disabled_without_tokens() {
+        assert_eq!(
+            query_cache_stats_status(None, Some("Bearer secret")).await,
+            StatusCode::NOT_FOUND
+        );
+    }
+}
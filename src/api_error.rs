@@ -0,0 +1,279 @@
+use std::collections::BTreeMap;
+
+use axum::extract::{FromRequest, FromRequestParts, Path, Query, Request};
+use axum::http::request::Parts;
+use axum::http::{header, StatusCode};
+use axum::response::{IntoResponse, Response};
+use axum::{async_trait, Json};
+use serde::de::DeserializeOwned;
+use serde::{Deserialize, Serialize};
+use tracing::{event, Level};
+use utoipa::openapi::{ContentBuilder, Ref, RefOr, ResponseBuilder};
+use utoipa::{IntoResponses, ToSchema};
+
+pub(crate) const PROBLEM_JSON_CONTENT_TYPE: &str = "application/problem+json";
+
+/// seconds a client should wait before retrying when the database is busy
+const RETRY_AFTER_SECONDS_DB_BUSY: u32 = 5;
+
+/// Error of the http api. Gets rendered as RFC 7807 problem+json.
+#[derive(Debug)]
+pub(crate) enum ApiError {
+    BadRequest(String),
+    Unauthorized(String),
+    NotFound(String),
+    Conflict(String),
+    /// detail and the number of seconds until the client may retry
+    TooManyRequests(String, u64),
+    ServiceUnavailable(String),
+    Internal(String),
+}
+
+/// RFC 7807 problem details
+#[derive(Serialize, Deserialize, ToSchema, Debug)]
+pub(crate) struct ProblemDetails {
+    /// always `about:blank` - the status code describes the problem
+    #[serde(rename = "type")]
+    problem_type: String,
+    title: String,
+    status: u16,
+    detail: String,
+}
+
+impl ApiError {
+    pub(crate) fn status(&self) -> StatusCode {
+        match self {
+            ApiError::BadRequest(_) => StatusCode::BAD_REQUEST,
+            ApiError::Unauthorized(_) => StatusCode::UNAUTHORIZED,
+            ApiError::NotFound(_) => StatusCode::NOT_FOUND,
+            ApiError::Conflict(_) => StatusCode::CONFLICT,
+            ApiError::TooManyRequests(_, _) => StatusCode::TOO_MANY_REQUESTS,
+            ApiError::ServiceUnavailable(_) => StatusCode::SERVICE_UNAVAILABLE,
+            ApiError::Internal(_) => StatusCode::INTERNAL_SERVER_ERROR,
+        }
+    }
+
+    fn detail(&self) -> &str {
+        match self {
+            ApiError::BadRequest(detail)
+            | ApiError::Unauthorized(detail)
+            | ApiError::NotFound(detail)
+            | ApiError::Conflict(detail)
+            | ApiError::TooManyRequests(detail, _)
+            | ApiError::ServiceUnavailable(detail)
+            | ApiError::Internal(detail) => detail,
+        }
+    }
+}
+
+impl IntoResponse for ApiError {
+    fn into_response(self) -> Response {
+        let status = self.status();
+        match &self {
+            ApiError::Internal(detail) => event!(Level::ERROR, "Internal error: {detail}"),
+            ApiError::ServiceUnavailable(detail) => {
+                event!(Level::WARN, "Service unavailable: {detail}")
+            }
+            _ => {}
+        }
+
+        let problem = ProblemDetails {
+            problem_type: "about:blank".to_string(),
+            title: status.canonical_reason().unwrap_or_default().to_string(),
+            status: status.as_u16(),
+            detail: self.detail().to_string(),
+        };
+
+        let mut response = (
+            status,
+            [(header::CONTENT_TYPE, PROBLEM_JSON_CONTENT_TYPE)],
+            Json(problem),
+        )
+            .into_response();
+
+        let maybe_retry_after_seconds = match self {
+            ApiError::ServiceUnavailable(_) => Some(RETRY_AFTER_SECONDS_DB_BUSY.into()),
+            ApiError::TooManyRequests(_, retry_after_seconds) => Some(retry_after_seconds),
+            _ => None,
+        };
+        if let Some(retry_after_seconds) = maybe_retry_after_seconds {
+            response
+                .headers_mut()
+                .insert(header::RETRY_AFTER, retry_after_seconds.into());
+        }
+        response
+    }
+}
+
+impl From<sqlx::Error> for ApiError {
+    fn from(err: sqlx::Error) -> Self {
+        match &err {
+            sqlx::Error::PoolTimedOut => {
+                ApiError::ServiceUnavailable("database is busy".to_string())
+            }
+            // SQLITE_BUSY (5) and SQLITE_LOCKED (6) - extended result codes keep the primary code in the lowest byte
+            sqlx::Error::Database(db_err)
+                if db_err
+                    .code()
+                    .and_then(|code| code.parse::<i32>().ok())
+                    .is_some_and(|code| matches!(code & 0xff, 5 | 6)) =>
+            {
+                ApiError::ServiceUnavailable("database is busy".to_string())
+            }
+            _ => {
+                // the message of sqlx may contain sql and schema details - keep it in the log
+                event!(Level::ERROR, "Database error: {err}");
+                ApiError::Internal("database error".to_string())
+            }
+        }
+    }
+}
+
+impl From<anyhow::Error> for ApiError {
+    fn from(err: anyhow::Error) -> Self {
+        match err.downcast::<sqlx::Error>() {
+            Ok(sqlx_err) => sqlx_err.into(),
+            Err(err) => ApiError::Internal(format!("{err:#}")),
+        }
+    }
+}
+
+/// Like [Path], but rejects invalid values with a problem+json body
+pub(crate) struct ApiPath<T>(pub(crate) T);
+
+#[async_trait]
+impl<S, T> FromRequestParts<S> for ApiPath<T>
+where
+    T: DeserializeOwned + Send,
+    S: Send + Sync,
+{
+    type Rejection = ApiError;
+
+    async fn from_request_parts(parts: &mut Parts, state: &S) -> Result<Self, Self::Rejection> {
+        Path::<T>::from_request_parts(parts, state)
+            .await
+            .map(|Path(value)| ApiPath(value))
+            .map_err(|rejection| ApiError::BadRequest(rejection.body_text()))
+    }
+}
+
+/// Like [Query], but rejects invalid values with a problem+json body
+pub(crate) struct ApiQuery<T>(pub(crate) T);
+
+#[async_trait]
+impl<S, T> FromRequestParts<S> for ApiQuery<T>
+where
+    T: DeserializeOwned,
+    S: Send + Sync,
+{
+    type Rejection = ApiError;
+
+    async fn from_request_parts(parts: &mut Parts, state: &S) -> Result<Self, Self::Rejection> {
+        Query::<T>::from_request_parts(parts, state)
+            .await
+            .map(|Query(value)| ApiQuery(value))
+            .map_err(|rejection| ApiError::BadRequest(rejection.body_text()))
+    }
+}
+
+/// Like [Json], but rejects invalid bodies with a problem+json body
+pub(crate) struct ApiJson<T>(pub(crate) T);
+
+#[async_trait]
+impl<S, T> FromRequest<S> for ApiJson<T>
+where
+    T: DeserializeOwned,
+    S: Send + Sync,
+{
+    type Rejection = ApiError;
+
+    async fn from_request(req: Request, state: &S) -> Result<Self, Self::Rejection> {
+        Json::<T>::from_request(req, state)
+            .await
+            .map(|Json(value)| ApiJson(value))
+            .map_err(|rejection| ApiError::BadRequest(rejection.body_text()))
+    }
+}
+
+/// Error responses every endpoint can produce
+pub(crate) struct ApiErrorResponses;
+
+impl IntoResponses for ApiErrorResponses {
+    fn responses() -> BTreeMap<String, RefOr<utoipa::openapi::response::Response>> {
+        problem_responses(&[
+            (StatusCode::INTERNAL_SERVER_ERROR, "unexpected error"),
+            (
+                StatusCode::TOO_MANY_REQUESTS,
+                "rate limit exceeded - retry after the time given in the Retry-After header",
+            ),
+            (
+                StatusCode::SERVICE_UNAVAILABLE,
+                "database is busy - retry after the time given in the Retry-After header",
+            ),
+        ])
+    }
+}
+
+/// Error responses of endpoints with query parameters
+pub(crate) struct QueryApiErrorResponses;
+
+impl IntoResponses for QueryApiErrorResponses {
+    fn responses() -> BTreeMap<String, RefOr<utoipa::openapi::response::Response>> {
+        let mut responses =
+            problem_responses(&[(StatusCode::BAD_REQUEST, "invalid query parameters")]);
+        responses.append(&mut ApiErrorResponses::responses());
+        responses
+    }
+}
+
+/// Error responses of endpoints for a single reset
+pub(crate) struct ResetApiErrorResponses;
+
+impl IntoResponses for ResetApiErrorResponses {
+    fn responses() -> BTreeMap<String, RefOr<utoipa::openapi::response::Response>> {
+        let mut responses = problem_responses(&[
+            (
+                StatusCode::BAD_REQUEST,
+                "invalid reset date or invalid filter parameters",
+            ),
+            (StatusCode::NOT_FOUND, "unknown reset date"),
+        ]);
+        responses.append(&mut ApiErrorResponses::responses());
+        responses
+    }
+}
+
+fn problem_responses(
+    responses: &[(StatusCode, &str)],
+) -> BTreeMap<String, RefOr<utoipa::openapi::response::Response>> {
+    responses
+        .iter()
+        .map(|(status, description)| {
+            let response = ResponseBuilder::new()
+                .description(*description)
+                .content(
+                    PROBLEM_JSON_CONTENT_TYPE,
+                    ContentBuilder::new()
+                        .schema(Ref::from_schema_name("ProblemDetails"))
+                        .build(),
+                )
+                .build();
+            (status.as_u16().to_string(), response.into())
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn database_errors_dont_leak_into_the_response() {
+        let err: ApiError = sqlx::Error::ColumnNotFound("token_sha256".to_string()).into();
+        assert_eq!(err.status(), StatusCode::INTERNAL_SERVER_ERROR);
+        assert_eq!(err.detail(), "database error");
+
+        let err: ApiError = anyhow::Error::from(sqlx::Error::RowNotFound).into();
+        assert_eq!(err.detail(), "database error");
+    }
+}
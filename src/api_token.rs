@@ -0,0 +1,103 @@
+use anyhow::{anyhow, Result};
+use chrono::Utc;
+use rand::rngs::OsRng;
+use rand::RngCore;
+use sha2::{Digest, Sha256};
+use sqlx::{Pool, Sqlite};
+
+use crate::db::{insert_api_token, revoke_api_token, select_active_api_token_name};
+
+const TOKEN_PREFIX: &str = "stlb_";
+
+/// Creates a token stored in the `api_token` table and returns it.
+/// Only its hash is stored - the token can't be shown again.
+pub(crate) async fn create_api_token(pool: &Pool<Sqlite>, name: &str) -> Result<String> {
+    let mut bytes = [0u8; 32];
+    OsRng.fill_bytes(&mut bytes);
+    let token = format!("{TOKEN_PREFIX}{}", hex::encode(bytes));
+
+    insert_api_token(pool, name, &hash_token(&token), Utc::now().naive_utc()).await?;
+    Ok(token)
+}
+
+pub(crate) async fn revoke_api_token_by_name(pool: &Pool<Sqlite>, name: &str) -> Result<()> {
+    match revoke_api_token(pool, name, Utc::now().naive_utc()).await? {
+        0 => Err(anyhow!("No active api token named '{name}'")),
+        _ => Ok(()),
+    }
+}
+
+/// Checks the token against the token configured via env and the active tokens of the `api_token` table.
+pub(crate) async fn is_valid_api_token(
+    pool: &Pool<Sqlite>,
+    configured_token: Option<&str>,
+    token: &str,
+) -> Result<bool, sqlx::Error> {
+    if configured_token
+        .is_some_and(|expected| constant_time_eq(token.as_bytes(), expected.as_bytes()))
+    {
+        return Ok(true);
+    }
+
+    // comparing hashes in sql doesn't leak the token through timing
+    Ok(select_active_api_token_name(pool, &hash_token(token))
+        .await?
+        .is_some())
+}
+
+fn hash_token(token: &str) -> String {
+    hex::encode(Sha256::digest(token.as_bytes()))
+}
+
+fn constant_time_eq(a: &[u8], b: &[u8]) -> bool {
+    a.len() == b.len() && a.iter().zip(b).fold(0u8, |acc, (x, y)| acc | (x ^ y)) == 0
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::db::seeded_test_pool;
+
+    use super::*;
+
+    #[tokio::test]
+    async fn configured_token_is_valid() {
+        let pool = seeded_test_pool("").await;
+
+        assert!(is_valid_api_token(&pool, Some("secret"), "secret")
+            .await
+            .unwrap());
+        assert!(!is_valid_api_token(&pool, Some("secret"), "secreT")
+            .await
+            .unwrap());
+        assert!(!is_valid_api_token(&pool, Some("secret"), "").await.unwrap());
+        assert!(!is_valid_api_token(&pool, None, "secret").await.unwrap());
+    }
+
+    #[tokio::test]
+    async fn created_tokens_are_valid_until_revoked() {
+        let pool = seeded_test_pool("").await;
+        let token = create_api_token(&pool, "ci").await.unwrap();
+        let other_token = create_api_token(&pool, "grafana").await.unwrap();
+
+        // only the hash is stored
+        let stored: Vec<String> = sqlx::query_scalar("select token_sha256 from api_token")
+            .fetch_all(&pool)
+            .await
+            .unwrap();
+        assert!(!stored.contains(&token));
+        assert!(stored.contains(&hash_token(&token)));
+
+        assert!(is_valid_api_token(&pool, None, &token).await.unwrap());
+        assert!(is_valid_api_token(&pool, Some("secret"), &token)
+            .await
+            .unwrap());
+        assert!(!is_valid_api_token(&pool, None, &hash_token(&token))
+            .await
+            .unwrap());
+
+        revoke_api_token_by_name(&pool, "ci").await.unwrap();
+        assert!(!is_valid_api_token(&pool, None, &token).await.unwrap());
+        assert!(is_valid_api_token(&pool, None, &other_token).await.unwrap());
+        assert!(revoke_api_token_by_name(&pool, "ci").await.is_err());
+    }
+}
@@ -0,0 +1,140 @@
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+
+use axum::extract::{MatchedPath, Request, State};
+use axum::http::header;
+use axum::middleware::Next;
+use axum::response::{IntoResponse, Response};
+use axum::{routing, Router};
+use metrics::{counter, gauge, histogram};
+use metrics_exporter_prometheus::{PrometheusBuilder, PrometheusHandle};
+use sqlx::{Pool, Sqlite};
+
+use crate::db::InsertedRows;
+use crate::server::{AppState, QueryCache};
+
+/// buckets of all duration histograms - from fast http requests to slow ticks
+const DURATION_BUCKETS_SECONDS: &[f64] = &[
+    0.005, 0.01, 0.025, 0.05, 0.1, 0.25, 0.5, 1.0, 2.5, 5.0, 10.0, 30.0, 60.0, 120.0,
+];
+
+const PROMETHEUS_CONTENT_TYPE: &str = "text/plain; version=0.0.4";
+
+/// Renders the collected metrics for `/metrics`.
+/// Gauges of the connection pools and the query cache are sampled on each scrape.
+#[derive(Clone)]
+pub(crate) struct MetricsExporter {
+    handle: PrometheusHandle,
+    pools: Arc<Vec<(&'static str, Pool<Sqlite>)>>,
+}
+
+impl MetricsExporter {
+    /// Installs the global recorder. Without it (e.g. in the cli commands) all metrics are no-ops.
+    pub(crate) fn install(pools: Vec<(&'static str, Pool<Sqlite>)>) -> anyhow::Result<Self> {
+        let handle = PrometheusBuilder::new()
+            .set_buckets(DURATION_BUCKETS_SECONDS)?
+            .install_recorder()?;
+        Ok(Self {
+            handle,
+            pools: Arc::new(pools),
+        })
+    }
+
+    fn sample_gauges(&self, query_cache: &QueryCache) {
+        for (name, pool) in self.pools.iter() {
+            let size = pool.size();
+            let num_idle = pool.num_idle() as u32;
+            gauge!("sqlite_pool_connections", "pool" => *name, "state" => "idle").set(num_idle);
+            gauge!("sqlite_pool_connections", "pool" => *name, "state" => "in_use")
+                .set(size.saturating_sub(num_idle));
+            gauge!("sqlite_pool_max_connections", "pool" => *name)
+                .set(pool.options().get_max_connections());
+        }
+
+        let stats = query_cache.stats();
+        counter!("query_cache_hits_total").absolute(stats.hits);
+        counter!("query_cache_misses_total").absolute(stats.misses);
+        counter!("query_cache_invalidated_entries_total").absolute(stats.invalidated_entries);
+        counter!("query_cache_evicted_entries_total").absolute(stats.evicted_entries);
+        gauge!("query_cache_entries").set(stats.entries as f64);
+    }
+}
+
+pub(crate) fn metrics_router() -> Router<AppState> {
+    Router::new().route("/metrics", routing::get(get_metrics))
+}
+
+async fn get_metrics(
+    State(exporter): State<MetricsExporter>,
+    State(query_cache): State<QueryCache>,
+) -> Response {
+    exporter.sample_gauges(&query_cache);
+    (
+        [(header::CONTENT_TYPE, PROMETHEUS_CONTENT_TYPE)],
+        exporter.handle.render(),
+    )
+        .into_response()
+}
+
+/// Records the latency of the http requests per route template (e.g. `/api/leaderboard/:reset_date`)
+pub(crate) async fn track_http_requests(request: Request, next: Next) -> Response {
+    let route = request
+        .extensions()
+        .get::<MatchedPath>()
+        .map(|path| path.as_str().to_string())
+        .unwrap_or_else(|| "unmatched".to_string());
+    let method = request.method().to_string();
+
+    let start = Instant::now();
+    let response = next.run(request).await;
+    let labels = [
+        ("method", method),
+        ("route", route),
+        ("status", response.status().as_u16().to_string()),
+    ];
+    histogram!("http_request_duration_seconds", &labels).record(start.elapsed());
+    response
+}
+
+pub(crate) fn record_tick(outcome: &'static str, duration: Duration) {
+    counter!("leaderboard_ticks_total", "outcome" => outcome).increment(1);
+    histogram!("leaderboard_tick_duration_seconds", "outcome" => outcome).record(duration);
+}
+
+pub(crate) fn record_skipped_tick(reason: &'static str) {
+    counter!("leaderboard_ticks_skipped_total", "reason" => reason).increment(1);
+}
+
+pub(crate) fn record_inserted_rows(inserted_rows: &InsertedRows) {
+    for (table, num_rows) in [
+        ("agent_log", inserted_rows.agent_log),
+        ("construction_log", inserted_rows.construction_log),
+        (
+            "construction_material_log",
+            inserted_rows.construction_material_log,
+        ),
+    ] {
+        counter!("leaderboard_rows_inserted_total", "table" => table).increment(num_rows);
+        gauge!("leaderboard_last_tick_rows_inserted", "table" => table).set(num_rows as f64);
+    }
+}
+
+pub(crate) fn record_materialized_view_refresh(duration: Duration) {
+    histogram!("leaderboard_materialized_view_refresh_seconds").record(duration);
+}
+
+/// `status` is the http status or `error` if no response was received
+pub(crate) fn record_spacetraders_request(endpoint: String, status: String, duration: Duration) {
+    histogram!("spacetraders_request_duration_seconds", "endpoint" => endpoint, "status" => status)
+        .record(duration);
+}
+
+/// `outcome` is `delivered` or `failed` (after all retries)
+pub(crate) fn record_webhook_delivery(webhook: &str, outcome: &'static str) {
+    counter!("leaderboard_webhook_deliveries_total", "webhook" => webhook.to_string(), "outcome" => outcome)
+        .increment(1);
+}
+
+pub(crate) fn record_spacetraders_rate_limiter_wait(duration: Duration) {
+    histogram!("spacetraders_rate_limiter_wait_seconds").record(duration);
+}
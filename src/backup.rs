@@ -0,0 +1,269 @@
+use std::fs;
+use std::fs::{File, OpenOptions};
+use std::io;
+use std::path::{Path, PathBuf};
+
+use anyhow::{Context, Result};
+use chrono::Utc;
+use flate2::write::GzEncoder;
+use flate2::Compression;
+use serde::Serialize;
+use sqlx::{Pool, Sqlite};
+use tracing::{event, Level};
+
+const BACKUP_FILE_PREFIX: &str = "flwi-leaderboard-";
+const BACKUP_FILE_SUFFIX: &str = ".db.gz";
+
+#[derive(Debug, Clone)]
+pub(crate) struct BackupSettings {
+    pub(crate) backup_dir: PathBuf,
+    /// number of snapshots to keep. Older ones get deleted after a successful backup.
+    pub(crate) keep: usize,
+}
+
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub(crate) struct BackupResult {
+    pub(crate) file_name: String,
+    pub(crate) size_bytes: u64,
+    pub(crate) removed_file_names: Vec<String>,
+}
+
+/// Creates a consistent, compressed snapshot of the live database.
+///
+/// `VACUUM INTO` reads the database through a regular connection of the pool, so it is safe to run
+/// while the collector is writing (WAL mode) and produces a defragmented copy without the wal-file.
+pub(crate) async fn create_backup(
+    pool: &Pool<Sqlite>,
+    settings: &BackupSettings,
+) -> Result<BackupResult> {
+    let timestamp = Utc::now().format("%Y%m%dT%H%M%SZ").to_string();
+    create_backup_with_timestamp(pool, settings, &timestamp).await
+}
+
+async fn create_backup_with_timestamp(
+    pool: &Pool<Sqlite>,
+    settings: &BackupSettings,
+    timestamp: &str,
+) -> Result<BackupResult> {
+    fs::create_dir_all(&settings.backup_dir).with_context(|| {
+        format!(
+            "failed to create backup dir {}",
+            settings.backup_dir.display()
+        )
+    })?;
+
+    let (file_name, snapshot_path) = claim_backup_file_name(&settings.backup_dir, timestamp)?;
+    let target_path = settings.backup_dir.join(&file_name);
+    let compressed_tmp_path = settings.backup_dir.join(format!(".{file_name}.tmp"));
+
+    event!(
+        Level::INFO,
+        "Creating database snapshot {}",
+        snapshot_path.display()
+    );
+
+    let vacuum_result = sqlx::query("VACUUM INTO ?")
+        .bind(snapshot_path.to_string_lossy().to_string())
+        .execute(pool)
+        .await
+        .context("failed at VACUUM INTO");
+    if let Err(err) = vacuum_result {
+        let _ = fs::remove_file(&snapshot_path);
+        return Err(err);
+    }
+
+    // compress into a temp file first, so that a failed compression doesn't leave a truncated
+    // backup behind that rotate_backups would count as a valid one
+    let compress_result = {
+        let snapshot_path = snapshot_path.clone();
+        let compressed_tmp_path = compressed_tmp_path.clone();
+        tokio::task::spawn_blocking(move || compress_file(&snapshot_path, &compressed_tmp_path))
+            .await?
+    };
+    let _ = fs::remove_file(&snapshot_path);
+    let size_bytes = compress_result
+        .and_then(|size_bytes| fs::rename(&compressed_tmp_path, &target_path).map(|_| size_bytes))
+        .inspect_err(|_| {
+            let _ = fs::remove_file(&compressed_tmp_path);
+        })
+        .context("failed to compress snapshot")?;
+
+    let removed_file_names = rotate_backups(&settings.backup_dir, settings.keep)?;
+
+    event!(
+        Level::INFO,
+        "Done creating backup {} ({size_bytes} bytes). Removed {} old backups",
+        target_path.display(),
+        removed_file_names.len()
+    );
+
+    Ok(BackupResult {
+        file_name,
+        size_bytes,
+        removed_file_names,
+    })
+}
+
+/// Resolves the path of a backup file in the backup dir. Returns None for anything that doesn't look
+/// like a file created by [create_backup] to prevent path traversal.
+pub(crate) fn backup_file_path(settings: &BackupSettings, file_name: &str) -> Option<PathBuf> {
+    is_backup_file_name(file_name).then(|| settings.backup_dir.join(file_name))
+}
+
+/// Picks the file name of a new backup and reserves it by creating the (empty) snapshot file.
+///
+/// Backups created within the same second get a `_<n>` suffix, which sorts after the unsuffixed
+/// name, so [rotate_backups] still sees them as newer. The suffix continues after the highest one
+/// in use - a name freed by the rotation would sort as the oldest backup. `VACUUM INTO` accepts
+/// the empty file.
+fn claim_backup_file_name(backup_dir: &Path, timestamp: &str) -> Result<(String, PathBuf)> {
+    let stem_of_timestamp = format!("{BACKUP_FILE_PREFIX}{timestamp}");
+    let first_attempt = fs::read_dir(backup_dir)?
+        .filter_map(|entry| entry.ok())
+        .filter_map(|entry| entry.file_name().into_string().ok())
+        .filter_map(|file_name| {
+            // snapshots and compressed temp files start with a dot
+            let rest = file_name
+                .trim_start_matches('.')
+                .strip_prefix(&stem_of_timestamp)?;
+            match rest.strip_prefix('_') {
+                Some(suffix) => suffix.get(..2)?.parse::<u32>().ok(),
+                None => rest.starts_with('.').then_some(0),
+            }
+        })
+        .max()
+        .map_or(0, |highest| highest + 1);
+
+    for attempt in first_attempt..100 {
+        let stem = match attempt {
+            0 => stem_of_timestamp.clone(),
+            n => format!("{stem_of_timestamp}_{n:02}"),
+        };
+        let file_name = format!("{stem}{BACKUP_FILE_SUFFIX}");
+        if backup_dir.join(&file_name).exists() {
+            continue;
+        }
+        let snapshot_path = backup_dir.join(format!(".{stem}.db.tmp"));
+        match OpenOptions::new()
+            .write(true)
+            .create_new(true)
+            .open(&snapshot_path)
+        {
+            Ok(_) => return Ok((file_name, snapshot_path)),
+            Err(err) if err.kind() == io::ErrorKind::AlreadyExists => continue,
+            Err(err) => {
+                return Err(err).with_context(|| {
+                    format!("failed to create snapshot file {}", snapshot_path.display())
+                })
+            }
+        }
+    }
+    anyhow::bail!("failed to find an unused backup file name for {timestamp}")
+}
+
+fn compress_file(source: &Path, target: &Path) -> io::Result<u64> {
+    let mut input = File::open(source)?;
+    let output = File::create(target)?;
+    let mut encoder = GzEncoder::new(output, Compression::default());
+    io::copy(&mut input, &mut encoder)?;
+    let output = encoder.finish()?;
+    output.sync_all()?;
+    Ok(output.metadata()?.len())
+}
+
+fn rotate_backups(backup_dir: &Path, keep: usize) -> Result<Vec<String>> {
+    let mut backup_file_names: Vec<String> = fs::read_dir(backup_dir)?
+        .filter_map(|entry| entry.ok())
+        .filter_map(|entry| entry.file_name().into_string().ok())
+        .filter(|file_name| is_backup_file_name(file_name))
+        .collect();
+
+    // the timestamp format sorts lexicographically - newest first
+    backup_file_names.sort_unstable_by(|a, b| b.cmp(a));
+
+    let mut removed = vec![];
+    for file_name in backup_file_names.into_iter().skip(keep.max(1)) {
+        fs::remove_file(backup_dir.join(&file_name))
+            .with_context(|| format!("failed to remove old backup {file_name}"))?;
+        removed.push(file_name);
+    }
+    Ok(removed)
+}
+
+fn is_backup_file_name(file_name: &str) -> bool {
+    file_name.starts_with(BACKUP_FILE_PREFIX)
+        && file_name.ends_with(BACKUP_FILE_SUFFIX)
+        && !file_name.contains(['/', '\\'])
+        && !file_name.contains("..")
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::db::seeded_test_pool;
+
+    use super::*;
+
+    fn file_names_in(dir: &Path) -> Vec<String> {
+        let mut file_names: Vec<String> = fs::read_dir(dir)
+            .unwrap()
+            .map(|entry| entry.unwrap().file_name().into_string().unwrap())
+            .collect();
+        file_names.sort();
+        file_names
+    }
+
+    #[tokio::test]
+    async fn backups_within_the_same_second_are_kept_apart_and_rotated() {
+        let pool = seeded_test_pool("").await;
+        let settings = BackupSettings {
+            backup_dir: std::env::temp_dir().join(format!(
+                "flwi-leaderboard-backup-test-{}",
+                std::process::id()
+            )),
+            keep: 3,
+        };
+        let timestamp = "20240324T150000Z";
+
+        let mut results = vec![];
+        for _ in 0..5 {
+            results.push(
+                create_backup_with_timestamp(&pool, &settings, timestamp)
+                    .await
+                    .unwrap(),
+            );
+        }
+
+        let file_names: Vec<&str> = results.iter().map(|r| r.file_name.as_str()).collect();
+        assert_eq!(
+            file_names,
+            vec![
+                "flwi-leaderboard-20240324T150000Z.db.gz",
+                "flwi-leaderboard-20240324T150000Z_01.db.gz",
+                "flwi-leaderboard-20240324T150000Z_02.db.gz",
+                "flwi-leaderboard-20240324T150000Z_03.db.gz",
+                "flwi-leaderboard-20240324T150000Z_04.db.gz",
+            ]
+        );
+        // the oldest ones get removed once there are more than `keep`
+        assert_eq!(
+            results[3].removed_file_names,
+            vec!["flwi-leaderboard-20240324T150000Z.db.gz"]
+        );
+        assert_eq!(
+            results[4].removed_file_names,
+            vec!["flwi-leaderboard-20240324T150000Z_01.db.gz"]
+        );
+        // no leftover snapshots or temp files
+        assert_eq!(
+            file_names_in(&settings.backup_dir),
+            vec![
+                "flwi-leaderboard-20240324T150000Z_02.db.gz",
+                "flwi-leaderboard-20240324T150000Z_03.db.gz",
+                "flwi-leaderboard-20240324T150000Z_04.db.gz",
+            ]
+        );
+
+        fs::remove_dir_all(&settings.backup_dir).unwrap();
+    }
+}
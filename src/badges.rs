@@ -0,0 +1,405 @@
+use std::sync::{Arc, OnceLock};
+
+use axum::extract::State;
+use axum::http::header;
+use axum::response::{IntoResponse, Response};
+use axum::{routing, Router};
+use chrono::NaiveDate;
+use itertools::Itertools;
+use resvg::tiny_skia::{Pixmap, Transform};
+use resvg::usvg::{fontdb, Options, Tree};
+use sqlx::{Pool, Sqlite};
+
+use crate::api_error::{ApiError, ApiPath};
+use crate::db::{
+    load_leaderboard_for_reset, select_agent_history,
+    select_most_recent_construction_progress_for_reset, LeaderboardEntry,
+};
+use crate::server::leaderboard::load_existing_reset;
+use crate::server::{extract_reset_period, AppState, ResetWindow};
+
+const FONT_FAMILY: &str = "DejaVu Sans,Verdana,Geneva,sans-serif";
+/// rough average width of a character at 11px - good enough to size the badges
+const CHAR_WIDTH: f32 = 7.0;
+const SPARKLINE_WIDTH: f32 = 200.0;
+const SPARKLINE_HEIGHT: f32 = 40.0;
+const PROGRESS_BAR_WIDTH: f32 = 120.0;
+/// PNGs are rendered at twice the size to stay sharp on high-dpi screens
+const PNG_SCALE: f32 = 2.0;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum ImageFormat {
+    Svg,
+    Png,
+}
+
+pub(crate) fn badges_router() -> Router<AppState> {
+    Router::new()
+        .route(
+            "/badges/:reset_date/:agent_file",
+            routing::get(get_agent_badge),
+        )
+        .route(
+            "/badges/:reset_date/:agent_symbol/:chart_file",
+            routing::get(get_agent_chart),
+        )
+}
+
+/// Rank and credits of an agent, e.g. `/badges/2024-03-24/FLWI.svg`
+async fn get_agent_badge(
+    State(pool): State<Pool<Sqlite>>,
+    ApiPath((reset_date, agent_file)): ApiPath<(NaiveDate, String)>,
+) -> Result<Response, ApiError> {
+    let (agent_symbol, format) = split_extension(&agent_file)?;
+    let (rank, entry) = load_leaderboard_entry(&pool, reset_date, &agent_symbol).await?;
+
+    let svg = render_badge(
+        &format!("{} · {reset_date}", entry.agent_symbol),
+        &format!("#{rank} · {} credits", format_credits(entry.credits)),
+        if rank == 1 { "#dfb317" } else { "#007ec6" },
+    );
+    image_response(svg, format).await
+}
+
+/// `credits.svg` - sparkline of the credits over the reset
+/// `jump-gate.svg` - construction progress of the agent's jump gate
+async fn get_agent_chart(
+    State(pool): State<Pool<Sqlite>>,
+    ApiPath((reset_date, agent_symbol, chart_file)): ApiPath<(NaiveDate, String, String)>,
+) -> Result<Response, ApiError> {
+    let (chart, format) = split_extension(&chart_file)?;
+    let agent_symbol = agent_symbol.to_uppercase();
+
+    let svg = match chart.as_str() {
+        "CREDITS" => credits_sparkline(&pool, reset_date, &agent_symbol).await?,
+        "JUMP-GATE" => jump_gate_progress_bar(&pool, reset_date, &agent_symbol).await?,
+        _ => {
+            return Err(ApiError::NotFound(format!(
+                "unknown chart {chart_file} - expected credits or jump-gate"
+            )))
+        }
+    };
+    image_response(svg, format).await
+}
+
+async fn load_leaderboard_entry(
+    pool: &Pool<Sqlite>,
+    reset_date: NaiveDate,
+    agent_symbol: &str,
+) -> Result<(usize, LeaderboardEntry), ApiError> {
+    load_existing_reset(pool, reset_date).await?;
+    load_leaderboard_for_reset(pool, reset_date)
+        .await?
+        .into_iter()
+        .enumerate()
+        .find(|(_, entry)| entry.agent_symbol == agent_symbol)
+        .map(|(idx, entry)| (idx + 1, entry))
+        .ok_or_else(|| {
+            ApiError::NotFound(format!(
+                "agent {agent_symbol} is not part of reset {reset_date}"
+            ))
+        })
+}
+
+async fn credits_sparkline(
+    pool: &Pool<Sqlite>,
+    reset_date: NaiveDate,
+    agent_symbol: &str,
+) -> Result<String, ApiError> {
+    let reset = load_existing_reset(pool, reset_date).await?;
+    let num_minutes = (reset.latest_ts - reset.first_ts).num_minutes().max(0) as u32;
+    let period = extract_reset_period(
+        ResetWindow::First {
+            event_time_minutes_gte: None,
+            event_time_minutes_lte: num_minutes,
+        },
+        num_minutes,
+    );
+
+    let credits_timeline = select_agent_history(
+        pool,
+        reset_date,
+        period.from_event_time_minutes,
+        period.to_event_time_minutes,
+        period.resolution_minutes,
+        vec![agent_symbol.to_string()],
+    )
+    .await?
+    .into_iter()
+    .find(|entry| entry.agent_symbol == agent_symbol)
+    .and_then(|entry| entry.credits_timeline)
+    .map(|timeline| timeline.0)
+    .filter(|timeline| !timeline.is_empty())
+    .ok_or_else(|| {
+        ApiError::NotFound(format!(
+            "no history of agent {agent_symbol} in reset {reset_date}"
+        ))
+    })?;
+
+    Ok(render_sparkline(
+        &format!("{agent_symbol} · {reset_date}"),
+        &credits_timeline,
+    ))
+}
+
+async fn jump_gate_progress_bar(
+    pool: &Pool<Sqlite>,
+    reset_date: NaiveDate,
+    agent_symbol: &str,
+) -> Result<String, ApiError> {
+    let (_, entry) = load_leaderboard_entry(pool, reset_date, agent_symbol).await?;
+    let materials: Vec<_> = select_most_recent_construction_progress_for_reset(pool, reset_date)
+        .await?
+        .into_iter()
+        .filter(|material| material.jump_gate_waypoint_symbol == entry.jump_gate_waypoint_symbol)
+        .collect();
+
+    let fulfilled: i64 = materials.iter().map(|m| m.fulfilled).sum();
+    let required: i64 = materials.iter().map(|m| m.required).sum();
+    let is_complete = materials.iter().any(|m| m.is_jump_gate_complete);
+    let ratio = if is_complete {
+        1.0
+    } else if required > 0 {
+        fulfilled as f32 / required as f32
+    } else {
+        0.0
+    };
+
+    Ok(render_progress_bar(&entry.jump_gate_waypoint_symbol, ratio))
+}
+
+/// `FLWI.svg` -> (`FLWI`, Svg). Symbols are case-insensitive.
+fn split_extension(file_name: &str) -> Result<(String, ImageFormat), ApiError> {
+    let (name, extension) = file_name
+        .rsplit_once('.')
+        .ok_or_else(|| ApiError::NotFound(format!("missing extension in {file_name}")))?;
+    let format = match extension.to_lowercase().as_str() {
+        "svg" => ImageFormat::Svg,
+        "png" => ImageFormat::Png,
+        _ => {
+            return Err(ApiError::NotFound(format!(
+                "unsupported extension {extension} - expected svg or png"
+            )))
+        }
+    };
+    Ok((name.to_uppercase(), format))
+}
+
+async fn image_response(svg: String, format: ImageFormat) -> Result<Response, ApiError> {
+    match format {
+        ImageFormat::Svg => Ok(([(header::CONTENT_TYPE, "image/svg+xml")], svg).into_response()),
+        ImageFormat::Png => {
+            let png = tokio::task::spawn_blocking(move || render_png(&svg))
+                .await
+                .map_err(|err| ApiError::Internal(err.to_string()))??;
+            Ok(([(header::CONTENT_TYPE, "image/png")], png).into_response())
+        }
+    }
+}
+
+/// The system fonts are loaded once - the runtime image needs at least one of [FONT_FAMILY]
+fn font_database() -> Arc<fontdb::Database> {
+    static FONT_DATABASE: OnceLock<Arc<fontdb::Database>> = OnceLock::new();
+    FONT_DATABASE
+        .get_or_init(|| {
+            let mut database = fontdb::Database::new();
+            database.load_system_fonts();
+            Arc::new(database)
+        })
+        .clone()
+}
+
+fn render_png(svg: &str) -> Result<Vec<u8>, ApiError> {
+    let options = Options {
+        fontdb: font_database(),
+        ..Default::default()
+    };
+    let tree = Tree::from_str(svg, &options).map_err(|err| ApiError::Internal(err.to_string()))?;
+    let size = tree
+        .size()
+        .to_int_size()
+        .scale_by(PNG_SCALE)
+        .ok_or_else(|| ApiError::Internal("badge too large to render".to_string()))?;
+    let mut pixmap = Pixmap::new(size.width(), size.height())
+        .ok_or_else(|| ApiError::Internal("badge too large to render".to_string()))?;
+    resvg::render(
+        &tree,
+        Transform::from_scale(PNG_SCALE, PNG_SCALE),
+        &mut pixmap.as_mut(),
+    );
+    pixmap
+        .encode_png()
+        .map_err(|err| ApiError::Internal(err.to_string()))
+}
+
+fn text_width(text: &str) -> f32 {
+    text.chars().count() as f32 * CHAR_WIDTH + 10.0
+}
+
+fn escape_xml(text: &str) -> String {
+    text.replace('&', "&amp;")
+        .replace('<', "&lt;")
+        .replace('>', "&gt;")
+        .replace('"', "&quot;")
+}
+
+/// 1234567 -> `1,234,567`
+pub(crate) fn format_credits(credits: i64) -> String {
+    let digits = credits.unsigned_abs().to_string();
+    let grouped = digits
+        .as_bytes()
+        .rchunks(3)
+        .rev()
+        .map(|chunk| std::str::from_utf8(chunk).unwrap_or_default())
+        .join(",");
+    if credits < 0 {
+        format!("-{grouped}")
+    } else {
+        grouped
+    }
+}
+
+/// Two-part badge in the style of shields.io
+fn render_badge(label: &str, value: &str, color: &str) -> String {
+    let label_width = text_width(label);
+    let value_width = text_width(value);
+    let width = label_width + value_width;
+    let (label, value) = (escape_xml(label), escape_xml(value));
+
+    format!(
+        r##"<svg xmlns="http://www.w3.org/2000/svg" width="{width}" height="20" role="img" aria-label="{label}: {value}">
+<title>{label}: {value}</title>
+<clipPath id="r"><rect width="{width}" height="20" rx="3"/></clipPath>
+<g clip-path="url(#r)">
+<rect width="{label_width}" height="20" fill="#555"/>
+<rect x="{label_width}" width="{value_width}" height="20" fill="{color}"/>
+</g>
+<g fill="#fff" font-family="{FONT_FAMILY}" font-size="11" text-anchor="middle">
+<text x="{label_x}" y="14">{label}</text>
+<text x="{value_x}" y="14">{value}</text>
+</g>
+</svg>"##,
+        label_x = label_width / 2.0,
+        value_x = label_width + value_width / 2.0,
+    )
+}
+
+fn render_sparkline(label: &str, values: &[i64]) -> String {
+    let min = values.iter().copied().min().unwrap_or_default() as f32;
+    let max = values.iter().copied().max().unwrap_or_default() as f32;
+    let range = (max - min).max(1.0);
+    let step = SPARKLINE_WIDTH / (values.len().max(2) - 1) as f32;
+    let top = 16.0;
+
+    let points = values
+        .iter()
+        .enumerate()
+        .map(|(idx, value)| {
+            let x = idx as f32 * step;
+            let y = top + SPARKLINE_HEIGHT - (*value as f32 - min) / range * SPARKLINE_HEIGHT;
+            format!("{x:.1},{y:.1}")
+        })
+        .join(" ");
+    let latest = values.last().copied().unwrap_or_default();
+    let title = escape_xml(&format!("{label}: {} credits", format_credits(latest)));
+    let label = escape_xml(label);
+    let latest = format_credits(latest);
+
+    format!(
+        r##"<svg xmlns="http://www.w3.org/2000/svg" width="{SPARKLINE_WIDTH}" height="{height}" role="img" aria-label="{title}">
+<title>{title}</title>
+<g font-family="{FONT_FAMILY}" font-size="11" fill="#555">
+<text x="0" y="11">{label}</text>
+<text x="{SPARKLINE_WIDTH}" y="11" text-anchor="end">{latest}</text>
+</g>
+<polyline points="{points}" fill="none" stroke="#007ec6" stroke-width="1.5" stroke-linejoin="round"/>
+</svg>"##,
+        height = top + SPARKLINE_HEIGHT + 2.0,
+    )
+}
+
+fn render_progress_bar(jump_gate_waypoint_symbol: &str, ratio: f32) -> String {
+    let ratio = ratio.clamp(0.0, 1.0);
+    let label = escape_xml(jump_gate_waypoint_symbol);
+    let label_width = text_width(jump_gate_waypoint_symbol);
+    let width = label_width + PROGRESS_BAR_WIDTH;
+    let percent = format!("{:.0}%", ratio * 100.0);
+    let color = if ratio >= 1.0 { "#4c1" } else { "#007ec6" };
+
+    format!(
+        r##"<svg xmlns="http://www.w3.org/2000/svg" width="{width}" height="20" role="img" aria-label="{label}: {percent}">
+<title>{label}: {percent}</title>
+<clipPath id="r"><rect width="{width}" height="20" rx="3"/></clipPath>
+<g clip-path="url(#r)">
+<rect width="{label_width}" height="20" fill="#555"/>
+<rect x="{label_width}" width="{PROGRESS_BAR_WIDTH}" height="20" fill="#9f9f9f"/>
+<rect x="{label_width}" width="{filled_width}" height="20" fill="{color}"/>
+</g>
+<g fill="#fff" font-family="{FONT_FAMILY}" font-size="11" text-anchor="middle">
+<text x="{label_x}" y="14">{label}</text>
+<text x="{value_x}" y="14">{percent}</text>
+</g>
+</svg>"##,
+        filled_width = PROGRESS_BAR_WIDTH * ratio,
+        label_x = label_width / 2.0,
+        value_x = label_width + PROGRESS_BAR_WIDTH / 2.0,
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use axum::http::StatusCode;
+
+    use super::*;
+
+    #[test]
+    fn split_extension_of_supported_formats() {
+        assert_eq!(
+            split_extension("flwi.svg").unwrap(),
+            ("FLWI".to_string(), ImageFormat::Svg)
+        );
+        assert_eq!(
+            split_extension("FLWI.PNG").unwrap(),
+            ("FLWI".to_string(), ImageFormat::Png)
+        );
+        // only the last dot separates the extension
+        assert_eq!(
+            split_extension("credits.v2.svg").unwrap(),
+            ("CREDITS.V2".to_string(), ImageFormat::Svg)
+        );
+    }
+
+    #[test]
+    fn split_extension_rejects_unknown_extensions() {
+        for file_name in ["FLWI.gif", "FLWI", "FLWI."] {
+            let err = split_extension(file_name).unwrap_err();
+            assert_eq!(err.status(), StatusCode::NOT_FOUND, "{file_name}");
+        }
+    }
+
+    #[test]
+    fn credits_are_grouped_by_thousands() {
+        assert_eq!(format_credits(0), "0");
+        assert_eq!(format_credits(999), "999");
+        assert_eq!(format_credits(1000), "1,000");
+        assert_eq!(format_credits(1234567), "1,234,567");
+        assert_eq!(format_credits(-175000), "-175,000");
+        assert_eq!(format_credits(i64::MIN), "-9,223,372,036,854,775,808");
+    }
+
+    #[test]
+    fn agent_symbols_are_escaped() {
+        let symbol = "<A&B>\"";
+        let svgs = [
+            render_badge(symbol, "#1 · 175,000 credits", "#007ec6"),
+            render_sparkline(symbol, &[175000, 180000]),
+            render_progress_bar(symbol, 0.5),
+        ];
+
+        for svg in svgs {
+            assert!(!svg.contains(symbol));
+            assert!(svg.contains("&lt;A&amp;B&gt;&quot;"));
+            assert!(Tree::from_str(&svg, &Options::default()).is_ok());
+        }
+    }
+}
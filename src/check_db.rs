@@ -0,0 +1,402 @@
+use std::fmt::{Display, Formatter};
+
+use anyhow::Result;
+use sqlx::{Pool, Sqlite, SqliteConnection};
+use tracing::{event, Level};
+
+use crate::db::{
+    align_job_runs, align_reset_starts, bump_data_generation, merge_duplicate_static_agent_infos,
+    recalculate_event_times, refresh_fake_materialized_view,
+    select_decreasing_construction_materials, select_duplicate_static_agent_infos,
+    select_inconsistent_event_times, select_misaligned_job_runs, select_misaligned_reset_starts,
+    select_overfulfilled_construction_materials, DbIntegrityViolation,
+};
+
+/// number of violations per check that get printed
+const MAX_PRINTED_VIOLATIONS: usize = 5;
+
+#[derive(Debug, Clone)]
+pub(crate) struct CheckResult {
+    pub(crate) name: &'static str,
+    pub(crate) repairable: bool,
+    pub(crate) violations: Vec<DbIntegrityViolation>,
+}
+
+#[derive(Debug, Clone, Default)]
+pub(crate) struct RepairResult {
+    pub(crate) reset_starts_aligned: u64,
+    pub(crate) job_runs_aligned: u64,
+    pub(crate) event_times_recalculated: u64,
+    pub(crate) duplicate_agents_removed: u64,
+}
+
+#[derive(Debug, Clone)]
+pub(crate) struct CheckDbReport {
+    pub(crate) checks: Vec<CheckResult>,
+    pub(crate) repair: Option<RepairResult>,
+    /// state of the db after the repair (inside the transaction in case of a dry run)
+    pub(crate) checks_after_repair: Option<Vec<CheckResult>>,
+    pub(crate) dry_run: bool,
+}
+
+impl CheckDbReport {
+    pub(crate) fn remaining_violations(&self) -> usize {
+        self.checks_after_repair
+            .as_ref()
+            .unwrap_or(&self.checks)
+            .iter()
+            .map(|c| c.violations.len())
+            .sum()
+    }
+}
+
+/// Validates the invariants the collector relies on and optionally repairs the fixable ones.
+/// All repairs run in one transaction, which is rolled back if `dry_run` is set.
+pub(crate) async fn check_db(
+    pool: &Pool<Sqlite>,
+    repair: bool,
+    dry_run: bool,
+) -> Result<CheckDbReport> {
+    let mut transaction = pool.begin().await?;
+
+    let checks = run_checks(&mut transaction).await?;
+    let needs_repair = checks
+        .iter()
+        .any(|c| c.repairable && !c.violations.is_empty());
+
+    if !repair || !needs_repair {
+        transaction.rollback().await?;
+        return Ok(CheckDbReport {
+            checks,
+            repair: None,
+            checks_after_repair: None,
+            dry_run,
+        });
+    }
+
+    // order matters - event_time_minutes depend on the aligned timestamps
+    let repair_result = RepairResult {
+        reset_starts_aligned: align_reset_starts(&mut transaction).await?,
+        job_runs_aligned: align_job_runs(&mut transaction).await?,
+        event_times_recalculated: recalculate_event_times(&mut transaction).await?,
+        duplicate_agents_removed: merge_duplicate_static_agent_infos(&mut transaction).await?,
+    };
+    bump_data_generation(&mut transaction).await?;
+    let checks_after_repair = run_checks(&mut transaction).await?;
+
+    if dry_run {
+        transaction.rollback().await?;
+    } else {
+        transaction.commit().await?;
+        event!(Level::INFO, "Repaired database: {repair_result:?}");
+        refresh_fake_materialized_view(pool).await?;
+    }
+
+    Ok(CheckDbReport {
+        checks,
+        repair: Some(repair_result),
+        checks_after_repair: Some(checks_after_repair),
+        dry_run,
+    })
+}
+
+async fn run_checks(conn: &mut SqliteConnection) -> Result<Vec<CheckResult>> {
+    Ok(vec![
+        CheckResult {
+            name: "reset start aligned to 5 minutes",
+            repairable: true,
+            violations: select_misaligned_reset_starts(conn).await?,
+        },
+        CheckResult {
+            name: "job_run aligned to 5 minutes",
+            repairable: true,
+            violations: select_misaligned_job_runs(conn).await?,
+        },
+        CheckResult {
+            name: "event_time_minutes matches query_time - first_ts",
+            repairable: true,
+            violations: select_inconsistent_event_times(conn).await?,
+        },
+        CheckResult {
+            name: "one static_agent_info per agent and reset",
+            repairable: true,
+            violations: select_duplicate_static_agent_infos(conn).await?,
+        },
+        CheckResult {
+            name: "fulfilled <= required",
+            repairable: false,
+            violations: select_overfulfilled_construction_materials(conn).await?,
+        },
+        CheckResult {
+            name: "fulfilled never decreases",
+            repairable: false,
+            violations: select_decreasing_construction_materials(conn).await?,
+        },
+    ])
+}
+
+fn fmt_checks(f: &mut Formatter<'_>, checks: &[CheckResult]) -> std::fmt::Result {
+    for check in checks {
+        if check.violations.is_empty() {
+            writeln!(f, "[ok] {}", check.name)?;
+            continue;
+        }
+
+        writeln!(
+            f,
+            "[{} violations] {}{}",
+            check.violations.len(),
+            check.name,
+            if check.repairable {
+                " (repairable)"
+            } else {
+                ""
+            }
+        )?;
+        for violation in check.violations.iter().take(MAX_PRINTED_VIOLATIONS) {
+            writeln!(f, "    {}", violation.details)?;
+        }
+        if check.violations.len() > MAX_PRINTED_VIOLATIONS {
+            writeln!(
+                f,
+                "    ... and {} more",
+                check.violations.len() - MAX_PRINTED_VIOLATIONS
+            )?;
+        }
+    }
+    Ok(())
+}
+
+impl Display for CheckDbReport {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        fmt_checks(f, &self.checks)?;
+
+        if let (Some(repair), Some(checks_after_repair)) = (&self.repair, &self.checks_after_repair)
+        {
+            writeln!(f)?;
+            writeln!(
+                f,
+                "repair{}: aligned {} reset starts and {} job runs, recalculated {} event times, removed {} duplicate agents",
+                if self.dry_run { " (dry run - rolled back)" } else { "" },
+                repair.reset_starts_aligned,
+                repair.job_runs_aligned,
+                repair.event_times_recalculated,
+                repair.duplicate_agents_removed,
+            )?;
+            writeln!(f)?;
+            fmt_checks(f, checks_after_repair)?;
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use sqlx::pool::PoolConnection;
+
+    use crate::db::seeded_test_pool;
+
+    use super::*;
+
+    const RESET: &str = "
+insert into reset_date (reset_id, reset, first_ts) values (1, '2024-03-24', '2024-03-24 15:00:00');
+insert into construction_site (id, reset_id, jump_gate_waypoint_symbol) values (1, 1, 'X1-AA-JG');
+";
+
+    async fn seeded_connection(seed_sql: &str) -> PoolConnection<Sqlite> {
+        seeded_test_pool(&format!("{RESET}{seed_sql}"))
+            .await
+            .acquire()
+            .await
+            .unwrap()
+    }
+
+    async fn query_times(conn: &mut SqliteConnection) -> Vec<(i64, String)> {
+        sqlx::query_as("select id, query_time from job_run order by id")
+            .fetch_all(conn)
+            .await
+            .unwrap()
+    }
+
+    #[tokio::test]
+    async fn misaligned_reset_starts_are_repaired() {
+        let mut conn =
+            seeded_connection("update reset_date set first_ts = '2024-03-24 15:02:31';").await;
+
+        assert_eq!(
+            select_misaligned_reset_starts(&mut conn)
+                .await
+                .unwrap()
+                .len(),
+            1
+        );
+        assert_eq!(align_reset_starts(&mut conn).await.unwrap(), 1);
+        assert!(select_misaligned_reset_starts(&mut conn)
+            .await
+            .unwrap()
+            .is_empty());
+        assert_eq!(align_reset_starts(&mut conn).await.unwrap(), 0);
+
+        let first_ts: String = sqlx::query_scalar("select first_ts from reset_date")
+            .fetch_one(&mut *conn)
+            .await
+            .unwrap();
+        assert_eq!(first_ts, "2024-03-24 15:05:00");
+    }
+
+    #[tokio::test]
+    async fn misaligned_job_runs_are_repaired() {
+        let mut conn = seeded_connection(
+            "
+insert into job_run (id, reset_id, query_time, event_time_minutes)
+values (1, 1, '2024-03-24 16:00:00', 60), (2, 1, '2024-03-24 16:06:10', 66), (3, 1, '2024-03-24 16:12:30', 72);
+",
+        )
+        .await;
+
+        assert_eq!(
+            select_misaligned_job_runs(&mut conn).await.unwrap().len(),
+            2
+        );
+        assert_eq!(align_job_runs(&mut conn).await.unwrap(), 2);
+        assert!(select_misaligned_job_runs(&mut conn)
+            .await
+            .unwrap()
+            .is_empty());
+        assert_eq!(align_job_runs(&mut conn).await.unwrap(), 0);
+
+        assert_eq!(
+            query_times(&mut conn).await,
+            vec![
+                (1, "2024-03-24 16:00:00".to_string()),
+                (2, "2024-03-24 16:05:00".to_string()),
+                (3, "2024-03-24 16:15:00".to_string()),
+            ]
+        );
+    }
+
+    #[tokio::test]
+    async fn aligning_job_runs_doesnt_create_duplicates() {
+        // run 2 would be rounded onto run 1, runs 3 and 4 onto the same slot
+        let mut conn = seeded_connection(
+            "
+insert into job_run (id, reset_id, query_time, event_time_minutes)
+values (1, 1, '2024-03-24 16:00:00', 60), (2, 1, '2024-03-24 16:01:00', 61),
+       (3, 1, '2024-03-24 16:09:00', 69), (4, 1, '2024-03-24 16:11:00', 71);
+",
+        )
+        .await;
+
+        assert_eq!(align_job_runs(&mut conn).await.unwrap(), 1);
+        assert_eq!(align_job_runs(&mut conn).await.unwrap(), 0);
+
+        assert_eq!(
+            query_times(&mut conn).await,
+            vec![
+                (1, "2024-03-24 16:00:00".to_string()),
+                (2, "2024-03-24 16:01:00".to_string()),
+                (3, "2024-03-24 16:10:00".to_string()),
+                (4, "2024-03-24 16:11:00".to_string()),
+            ]
+        );
+        // the skipped ones still need a manual look
+        assert_eq!(
+            select_misaligned_job_runs(&mut conn).await.unwrap().len(),
+            2
+        );
+    }
+
+    #[tokio::test]
+    async fn inconsistent_event_times_are_repaired() {
+        let mut conn = seeded_connection(
+            "
+insert into job_run (id, reset_id, query_time, event_time_minutes)
+values (1, 1, '2024-03-24 16:00:00', 60), (2, 1, '2024-03-24 16:05:00', 60);
+",
+        )
+        .await;
+
+        assert_eq!(
+            select_inconsistent_event_times(&mut conn)
+                .await
+                .unwrap()
+                .len(),
+            1
+        );
+        assert_eq!(recalculate_event_times(&mut conn).await.unwrap(), 1);
+        assert!(select_inconsistent_event_times(&mut conn)
+            .await
+            .unwrap()
+            .is_empty());
+        assert_eq!(recalculate_event_times(&mut conn).await.unwrap(), 0);
+
+        let event_times: Vec<i64> =
+            sqlx::query_scalar("select event_time_minutes from job_run order by id")
+                .fetch_all(&mut *conn)
+                .await
+                .unwrap();
+        assert_eq!(event_times, vec![60, 65]);
+    }
+
+    #[tokio::test]
+    async fn duplicate_static_agent_infos_are_merged() {
+        // the unique index prevents duplicates in migrated dbs
+        let mut conn = seeded_connection(
+            "
+drop index ux_static_agent_info__reset_id_agent_symbol;
+insert into static_agent_info (id, agent_symbol, agent_headquarters_waypoint_symbol, construction_site_id, starting_faction, reset_id, query_time)
+values (1, 'FLWI', 'X1-AA-A1', 1, 'COSMIC', 1, '2024-03-24 15:00:00'),
+       (2, 'FLWI', 'X1-AA-A1', 1, 'COSMIC', 1, '2024-03-24 15:05:00');
+insert into job_run (id, reset_id, query_time, event_time_minutes)
+values (1, 1, '2024-03-24 16:00:00', 60), (2, 1, '2024-03-24 16:05:00', 65);
+insert into agent_log (agent_id, job_id, credits, ship_count)
+values (1, 1, 175000, 2), (2, 1, 175000, 2), (2, 2, 180000, 2);
+",
+        )
+        .await;
+
+        assert_eq!(
+            select_duplicate_static_agent_infos(&mut conn)
+                .await
+                .unwrap()
+                .len(),
+            1
+        );
+        assert_eq!(
+            merge_duplicate_static_agent_infos(&mut conn).await.unwrap(),
+            1
+        );
+        assert!(select_duplicate_static_agent_infos(&mut conn)
+            .await
+            .unwrap()
+            .is_empty());
+        assert_eq!(
+            merge_duplicate_static_agent_infos(&mut conn).await.unwrap(),
+            0
+        );
+
+        let agent_log: Vec<(i64, i64)> =
+            sqlx::query_as("select agent_id, job_id from agent_log order by job_id")
+                .fetch_all(&mut *conn)
+                .await
+                .unwrap();
+        assert_eq!(agent_log, vec![(1, 1), (1, 2)]);
+    }
+
+    #[tokio::test]
+    async fn dry_run_leaves_the_db_untouched() {
+        let pool = seeded_test_pool(&format!(
+            "{RESET}
+insert into job_run (id, reset_id, query_time, event_time_minutes) values (1, 1, '2024-03-24 16:01:00', 61);
+"
+        ))
+        .await;
+
+        let report = check_db(&pool, true, true).await.unwrap();
+        assert_eq!(report.repair.as_ref().unwrap().job_runs_aligned, 1);
+        assert_eq!(report.remaining_violations(), 0);
+
+        let report = check_db(&pool, false, false).await.unwrap();
+        assert_eq!(report.remaining_violations(), 1);
+    }
+}
@@ -1,13 +1,28 @@
 use std::path::PathBuf;
 
+use chrono::NaiveDate;
 use clap::{Parser, Subcommand};
 use reqwest::Url;
 
+use crate::export::ExportFormat;
+use crate::telemetry::LogFormat;
+use crate::webhooks::WebhookConfig;
+
 #[derive(Parser)]
 #[command(version, about, long_about = None)]
 pub(crate) struct Cli {
     #[command(subcommand)]
     pub(crate) command: Commands,
+
+    /// format of the log lines on stdout. The level is configured via RUST_LOG.
+    #[arg(
+        long,
+        global = true,
+        env("LEADERBOARD_LOG_FORMAT"),
+        value_enum,
+        default_value_t = LogFormat::Text
+    )]
+    pub(crate) log_format: LogFormat,
 }
 
 #[derive(Subcommand)]
@@ -34,6 +49,166 @@ pub(crate) enum Commands {
 
         #[arg(long, env("SPACE_TRADERS_BASE_URL"), value_parser = parse_url)]
         base_url: Url,
+
+        /// bearer token for the endpoints below /api/admin. Additional tokens can be created with create-api-token.
+        /// The admin api is disabled if no token is configured.
+        #[arg(long, env("LEADERBOARD_ADMIN_TOKEN"), hide_env_values = true)]
+        admin_token: Option<String>,
+
+        /// directory for database snapshots. Enables the backup endpoint.
+        #[arg(long, env("LEADERBOARD_BACKUP_DIR"))]
+        backup_dir: Option<PathBuf>,
+
+        /// number of snapshots to keep in the backup dir
+        #[arg(long, env("LEADERBOARD_BACKUP_KEEP"), default_value_t = 7)]
+        backup_keep: usize,
+
+        /// cron expression (with seconds) for scheduled backups, e.g. "0 0 */6 * * *"
+        #[arg(long, env("LEADERBOARD_BACKUP_SCHEDULE"), requires = "backup_dir")]
+        backup_schedule: Option<String>,
+
+        /// requests per minute and client ip to the cheap api endpoints (lists, leaderboards). 0 disables the limit.
+        #[arg(
+            long,
+            env("LEADERBOARD_RATE_LIMIT_CHEAP_PER_MINUTE"),
+            default_value_t = 300
+        )]
+        rate_limit_cheap_per_minute: u32,
+
+        /// requests per minute and client ip to the heavy api endpoints (history, all-time, export, graphql). 0 disables the limit.
+        #[arg(
+            long,
+            env("LEADERBOARD_RATE_LIMIT_HEAVY_PER_MINUTE"),
+            default_value_t = 60
+        )]
+        rate_limit_heavy_per_minute: u32,
+
+        /// /readyz reports degraded if the newest job run is older than this many tick intervals (5 minutes)
+        #[arg(
+            long,
+            env("LEADERBOARD_READINESS_MAX_MISSED_TICKS"),
+            default_value_t = 3
+        )]
+        readiness_max_missed_ticks: u32,
+
+        /// take the client ip for rate limiting from the Fly-Client-IP header. Only enable behind the fly.io proxy.
+        #[arg(long, env("LEADERBOARD_TRUST_FLY_CLIENT_IP"))]
+        trust_fly_client_ip: bool,
+
+        /// webhooks notified about leaderboard events (new reset, jump gates, credits leader, overtakes).
+        /// Comma-separated list of `<name>:<format>:<url>` with format json, discord or slack.
+        #[arg(long = "webhook", env("LEADERBOARD_WEBHOOKS"), value_delimiter = ',')]
+        webhooks: Vec<WebhookConfig>,
+
+        /// token of the discord bot answering /leaderboard, /agent, /gate and /eta. The bot is disabled without it.
+        #[arg(long, env("LEADERBOARD_DISCORD_BOT_TOKEN"), hide_env_values = true)]
+        discord_bot_token: Option<String>,
+
+        /// id of the discord channel the bot announces leaderboard events to
+        #[arg(
+            long,
+            env("LEADERBOARD_DISCORD_ANNOUNCEMENT_CHANNEL_ID"),
+            requires = "discord_bot_token"
+        )]
+        discord_announcement_channel_id: Option<u64>,
+
+        /// public url of the server for the links in the atom feeds, e.g. https://flwi-spacetraders-rust-leaderboard.fly.dev/.
+        /// Derived from the Host header if missing.
+        #[arg(long, env("LEADERBOARD_PUBLIC_URL"), value_parser = parse_url)]
+        public_url: Option<Url>,
+
+        /// export traces to this otlp/http collector, e.g. http://localhost:4318
+        #[arg(long, env("LEADERBOARD_OTLP_ENDPOINT"), value_parser = parse_url)]
+        otlp_endpoint: Option<Url>,
+
+        /// filter of the exported spans. The spans of the collector, the api and the db queries are on debug level.
+        #[arg(
+            long,
+            env("LEADERBOARD_OTLP_FILTER"),
+            default_value = "info,flwi_spacetraders_leaderboard=debug"
+        )]
+        otlp_filter: String,
+    },
+
+    /// creates a compressed snapshot of the database and removes old snapshots
+    Backup {
+        #[arg(long, env("LEADERBOARD_DATABASE_URL"))]
+        database_url: String,
+
+        #[arg(long, env("LEADERBOARD_BACKUP_DIR"))]
+        backup_dir: PathBuf,
+
+        /// number of snapshots to keep in the backup dir
+        #[arg(long, env("LEADERBOARD_BACKUP_KEEP"), default_value_t = 7)]
+        keep: usize,
+    },
+
+    /// exports all data of a reset as a self-contained dataset with a manifest describing the tables
+    ExportReset {
+        #[arg(long, env("LEADERBOARD_DATABASE_URL"))]
+        database_url: String,
+
+        /// the reset date, e.g. 2024-03-10
+        #[arg(long)]
+        reset_date: NaiveDate,
+
+        #[arg(short, long)]
+        output_dir: PathBuf,
+
+        #[arg(long, value_enum, default_value_t = ExportFormat::Jsonl)]
+        format: ExportFormat,
+    },
+
+    /// imports resets from an export bundle (directory or zip) or the sqlite database of another instance.
+    /// Job runs that already exist (same query_time) are skipped.
+    Import {
+        #[arg(long, env("LEADERBOARD_DATABASE_URL"))]
+        database_url: String,
+
+        /// export bundle directory, export zip archive or sqlite database file
+        #[arg(long)]
+        source: PathBuf,
+
+        /// only import this reset, e.g. 2024-03-10
+        #[arg(long)]
+        reset_date: Option<NaiveDate>,
+
+        /// run the import inside a transaction that gets rolled back and only print the report
+        #[arg(long)]
+        dry_run: bool,
+    },
+
+    /// creates a token for the endpoints below /api/admin and prints it. Only its hash gets stored.
+    CreateApiToken {
+        #[arg(long, env("LEADERBOARD_DATABASE_URL"))]
+        database_url: String,
+
+        /// unique name of the token, e.g. the person or system using it
+        #[arg(long)]
+        name: String,
+    },
+
+    /// revokes a token created with create-api-token
+    RevokeApiToken {
+        #[arg(long, env("LEADERBOARD_DATABASE_URL"))]
+        database_url: String,
+
+        #[arg(long)]
+        name: String,
+    },
+
+    /// validates the invariants of the collected data. Fails if violations are found (or remain after --repair).
+    CheckDb {
+        #[arg(long, env("LEADERBOARD_DATABASE_URL"))]
+        database_url: String,
+
+        /// repair fixable issues (timestamp alignment, event_time_minutes, duplicate agents) in one transaction
+        #[arg(long)]
+        repair: bool,
+
+        /// roll back the repair and only print what would change
+        #[arg(long, requires = "repair")]
+        dry_run: bool,
     },
 }
 
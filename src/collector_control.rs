@@ -0,0 +1,188 @@
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Arc, Mutex, PoisonError};
+use std::time::Instant;
+
+use anyhow::Context;
+use chrono::{DateTime, NaiveDateTime, Utc};
+use reqwest::Url;
+use serde::Serialize;
+use sqlx::{Pool, Sqlite};
+use tokio::sync::broadcast;
+use tracing::{event, Level};
+
+use crate::app_metrics::{record_skipped_tick, record_tick};
+use crate::leaderboard_collector::{perform_tick, TickCompleted};
+use crate::reqwest_helpers::create_client;
+use crate::st_client::StClient;
+
+/// Runs the ticks of the collector - scheduled or triggered via the admin api - and keeps track of
+/// their outcome. At most one tick runs at a time.
+#[derive(Clone)]
+pub(crate) struct CollectorControl {
+    inner: Arc<CollectorControlInner>,
+}
+
+struct CollectorControlInner {
+    pool: Pool<Sqlite>,
+    base_url: Url,
+    tick_sender: broadcast::Sender<TickCompleted>,
+    is_paused: AtomicBool,
+    tick_lock: tokio::sync::Mutex<()>,
+    status: Mutex<CollectorStatus>,
+}
+
+#[derive(Serialize, Debug, Clone, Default)]
+#[serde(rename_all = "camelCase")]
+pub(crate) struct CollectorStatus {
+    is_paused: bool,
+    is_tick_running: bool,
+    last_tick_started_at: Option<NaiveDateTime>,
+    last_tick_finished_at: Option<NaiveDateTime>,
+    last_successful_tick: Option<TickCompleted>,
+    /// error of the last tick - cleared by the next successful one
+    last_error: Option<String>,
+    num_successful_ticks: u64,
+    num_failed_ticks: u64,
+    /// scheduled ticks that were skipped while the scheduler was paused
+    num_skipped_ticks: u64,
+    next_scheduled_tick: Option<DateTime<Utc>>,
+}
+
+#[derive(Debug)]
+pub(crate) enum TickError {
+    AlreadyRunning,
+    Failed(anyhow::Error),
+}
+
+impl CollectorControl {
+    pub(crate) fn new(
+        pool: Pool<Sqlite>,
+        base_url: Url,
+        tick_sender: broadcast::Sender<TickCompleted>,
+    ) -> Self {
+        Self {
+            inner: Arc::new(CollectorControlInner {
+                pool,
+                base_url,
+                tick_sender,
+                is_paused: AtomicBool::new(false),
+                tick_lock: tokio::sync::Mutex::new(()),
+                status: Mutex::new(CollectorStatus::default()),
+            }),
+        }
+    }
+
+    /// Called by the scheduler. Skips the tick while paused or if a triggered tick is still running.
+    pub(crate) async fn scheduled_tick(&self) {
+        if self.is_paused() {
+            event!(Level::INFO, "Collector is paused - skipping scheduled tick");
+            self.update_status(|status| status.num_skipped_ticks += 1);
+            record_skipped_tick("paused");
+            return;
+        }
+
+        match self.tick().await {
+            Ok(_) => {}
+            Err(TickError::AlreadyRunning) => {
+                event!(
+                    Level::WARN,
+                    "Previous tick is still running - skipping scheduled tick"
+                );
+                self.update_status(|status| status.num_skipped_ticks += 1);
+                record_skipped_tick("already_running");
+            }
+            Err(TickError::Failed(err)) => {
+                event!(Level::ERROR, error = format!("{err:#}"), "Tick failed")
+            }
+        }
+    }
+
+    /// Runs a tick right away and notifies the subscribers of the tick channel. Ignores the pause flag.
+    pub(crate) async fn tick(&self) -> Result<TickCompleted, TickError> {
+        let _guard = self
+            .inner
+            .tick_lock
+            .try_lock()
+            .map_err(|_| TickError::AlreadyRunning)?;
+
+        self.update_status(|status| {
+            status.is_tick_running = true;
+            status.last_tick_started_at = Some(Utc::now().naive_utc());
+        });
+
+        let start = Instant::now();
+        let client = StClient::new(create_client(), self.inner.base_url.clone());
+        let result = perform_tick(&client, self.inner.pool.clone())
+            .await
+            .context("failed at perform_tick");
+        let duration = start.elapsed();
+        record_tick(if result.is_ok() { "success" } else { "failure" }, duration);
+        if let Ok(tick_completed) = &result {
+            event!(
+                Level::INFO,
+                reset = %tick_completed.reset_date,
+                query_time = %tick_completed.query_time,
+                duration_ms = duration.as_millis() as u64,
+                "Tick completed"
+            );
+        }
+
+        self.update_status(|status| {
+            status.is_tick_running = false;
+            status.last_tick_finished_at = Some(Utc::now().naive_utc());
+            match &result {
+                Ok(tick_completed) => {
+                    status.num_successful_ticks += 1;
+                    status.last_successful_tick = Some(*tick_completed);
+                    status.last_error = None;
+                }
+                Err(err) => {
+                    status.num_failed_ticks += 1;
+                    status.last_error = Some(format!("{err:#}"));
+                }
+            }
+        });
+
+        let tick_completed = result.map_err(TickError::Failed)?;
+        // no subscribers is fine - nobody to notify
+        let _ = self.inner.tick_sender.send(tick_completed);
+        Ok(tick_completed)
+    }
+
+    pub(crate) fn set_paused(&self, is_paused: bool) {
+        self.inner.is_paused.store(is_paused, Ordering::Relaxed);
+        event!(
+            Level::INFO,
+            "Collector {}",
+            if is_paused { "paused" } else { "resumed" }
+        );
+    }
+
+    pub(crate) fn is_paused(&self) -> bool {
+        self.inner.is_paused.load(Ordering::Relaxed)
+    }
+
+    pub(crate) fn set_next_scheduled_tick(&self, next_scheduled_tick: Option<DateTime<Utc>>) {
+        self.update_status(|status| status.next_scheduled_tick = next_scheduled_tick);
+    }
+
+    pub(crate) fn status(&self) -> CollectorStatus {
+        let status = self
+            .inner
+            .status
+            .lock()
+            .unwrap_or_else(PoisonError::into_inner);
+        CollectorStatus {
+            is_paused: self.is_paused(),
+            ..status.clone()
+        }
+    }
+
+    fn update_status(&self, f: impl FnOnce(&mut CollectorStatus)) {
+        f(&mut self
+            .inner
+            .status
+            .lock()
+            .unwrap_or_else(PoisonError::into_inner));
+    }
+}
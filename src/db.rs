@@ -1,10 +1,16 @@
 use std::collections::HashMap;
+use std::time::Instant;
 
 use chrono::{Local, NaiveDate, NaiveDateTime};
 use serde::{Deserialize, Serialize};
 use sqlx::sqlite::SqlitePoolOptions;
-use sqlx::{Error, Executor, Pool, Sqlite};
+use sqlx::{Error, Pool, Sqlite, SqliteConnection};
 
+use crate::app_metrics::record_materialized_view_refresh;
+use crate::export::{
+    ExportAgent, ExportAgentLog, ExportConstructionMaterialLog, ExportConstructionRequirement,
+    ExportConstructionSite, ExportJobRun,
+};
 use crate::leaderboard_model::{
     LeaderboardCurrentAgentInfo, LeaderboardCurrentConstructionInfo, LeaderboardStaticAgentInfo,
 };
@@ -27,6 +33,23 @@ async fn main() -> Result<(), Error> {
     Ok(())
 }
 
+/// Migrated in-memory db for tests, seeded with the given statements
+#[cfg(test)]
+pub(crate) async fn seeded_test_pool(seed_sql: &str) -> Pool<Sqlite> {
+    use sqlx::Executor;
+
+    // every connection gets its own in-memory db
+    let pool = SqlitePoolOptions::new()
+        .max_connections(1)
+        .connect("sqlite::memory:")
+        .await
+        .unwrap();
+    sqlx::migrate!().run(&pool).await.unwrap();
+    pool.execute(seed_sql).await.unwrap();
+    pool
+}
+
+#[tracing::instrument(level = "debug", skip_all)]
 pub(crate) async fn load_or_create_reset_date(
     pool: &Pool<Sqlite>,
     reset_date: NaiveDate,
@@ -42,6 +65,7 @@ pub(crate) async fn load_or_create_reset_date(
     }
 }
 
+#[tracing::instrument(level = "debug", skip_all)]
 pub(crate) async fn force_wal_checkpoint(pool: &Pool<Sqlite>) -> Result<(), Error> {
     // TRUNCATE is more aggressive than PASSIVE or RESTART
     sqlx::query("PRAGMA wal_checkpoint(TRUNCATE)")
@@ -96,6 +120,7 @@ order by r.reset desc
     .await
 }
 
+#[tracing::instrument(level = "debug", skip_all)]
 pub(crate) async fn load_reset_date(
     pool: &Pool<Sqlite>,
     reset_date: NaiveDate,
@@ -120,6 +145,84 @@ group by r.reset_id, r.first_ts, r.reset
     .await
 }
 
+/// Latest job run of a reset. Its id changes with every tick, so it serves as cache validator.
+#[tracing::instrument(level = "debug", skip_all)]
+pub(crate) async fn select_latest_job_run_of_reset(
+    pool: &Pool<Sqlite>,
+    reset_date: NaiveDate,
+) -> Result<Option<DbLatestJobRun>, Error> {
+    sqlx::query_as!(
+        DbLatestJobRun,
+        r#"
+select jr.id as "job_run_id!"
+     , jr.query_time as "query_time!"
+     , (select count(*) from reset_date next where next.reset > r.reset) = 0 as "is_ongoing! :_"
+  from job_run jr
+       join reset_date r on jr.reset_id = r.reset_id
+ where r.reset = ?
+ order by jr.id desc
+ limit 1
+        "#,
+        reset_date
+    )
+    .fetch_optional(pool)
+    .await
+}
+
+/// Latest job run of the latest reset. Goes by date - an older reset imported afterwards has the higher ids.
+#[tracing::instrument(level = "debug", skip_all)]
+pub(crate) async fn select_latest_job_run(
+    pool: &Pool<Sqlite>,
+) -> Result<Option<DbLatestJobRun>, Error> {
+    sqlx::query_as!(
+        DbLatestJobRun,
+        r#"
+select jr.id as "job_run_id!"
+     , jr.query_time as "query_time!"
+     , (select count(*) from reset_date next where next.reset > r.reset) = 0 as "is_ongoing! :_"
+  from job_run jr
+       join reset_date r on jr.reset_id = r.reset_id
+ where r.reset = (select max(latest.reset) from reset_date latest)
+ order by jr.query_time desc, jr.id desc
+ limit 1
+        "#
+    )
+    .fetch_optional(pool)
+    .await
+}
+
+/// Generation of the data that isn't covered by the latest job run - see [bump_data_generation].
+#[tracing::instrument(level = "debug", skip_all)]
+pub(crate) async fn select_data_generation(pool: &Pool<Sqlite>) -> Result<DbDataGeneration, Error> {
+    sqlx::query_as!(
+        DbDataGeneration,
+        r#"
+select generation as "generation!"
+     , updated_at as "updated_at!"
+  from data_generation
+        "#
+    )
+    .fetch_one(pool)
+    .await
+}
+
+/// Marks changes to existing data (import, repair, refresh of the materialized views), so that
+/// responses validated by the latest job run alone don't stay cached.
+#[tracing::instrument(level = "debug", skip_all)]
+pub(crate) async fn bump_data_generation(conn: &mut SqliteConnection) -> Result<(), Error> {
+    sqlx::query!(
+        "
+update data_generation
+   set generation = generation + 1
+     , updated_at = datetime('now')
+        "
+    )
+    .execute(conn)
+    .await?;
+    Ok(())
+}
+
+#[tracing::instrument(level = "debug", skip_all)]
 pub(crate) async fn load_reset_dates(pool: &Pool<Sqlite>) -> Result<Vec<ResetDate>, Error> {
     sqlx::query_as!(
         ResetDate,
@@ -139,6 +242,7 @@ group by r.reset_id, r.first_ts, r.reset
     .await
 }
 
+#[tracing::instrument(level = "debug", skip_all)]
 pub(crate) async fn load_leaderboard_for_reset(
     pool: &Pool<Sqlite>,
     reset_date: NaiveDate,
@@ -151,6 +255,7 @@ select agent_symbol
      , ship_count
      , agent_headquarters_waypoint_symbol
      , jump_gate_waypoint_symbol
+     , starting_faction
 from agent_log a
          join static_agent_info sai on a.agent_id = sai.id
          join main.construction_site cs on sai.construction_site_id = cs.id
@@ -168,6 +273,64 @@ order by credits desc, ship_count desc
     .await
 }
 
+/// Leaderboard as of the given job run
+#[tracing::instrument(level = "debug", skip_all)]
+pub(crate) async fn load_leaderboard_for_job_run(
+    pool: &Pool<Sqlite>,
+    job_run_id: i64,
+) -> Result<Vec<LeaderboardEntry>, Error> {
+    sqlx::query_as!(
+        LeaderboardEntry,
+        "
+select agent_symbol
+     , credits
+     , ship_count
+     , agent_headquarters_waypoint_symbol
+     , jump_gate_waypoint_symbol
+     , starting_faction
+from agent_log a
+         join static_agent_info sai on a.agent_id = sai.id
+         join main.construction_site cs on sai.construction_site_id = cs.id
+where job_id = ?
+order by credits desc, ship_count desc, agent_symbol
+",
+        job_run_id
+    )
+    .fetch_all(pool)
+    .await
+}
+
+/// Most recent job run of a reset at or before the given point in time. Without bounds it's the latest job run.
+#[tracing::instrument(level = "debug", skip_all)]
+pub(crate) async fn select_job_run_of_reset_at(
+    pool: &Pool<Sqlite>,
+    reset_date: NaiveDate,
+    query_time_lte: Option<NaiveDateTime>,
+    event_time_minutes_lte: Option<i64>,
+) -> Result<Option<DbJobRunRef>, Error> {
+    sqlx::query_as!(
+        DbJobRunRef,
+        r#"
+select jr.id as "job_run_id!"
+     , jr.query_time as "query_time!"
+     , jr.event_time_minutes as "event_time_minutes!"
+  from job_run jr
+       join reset_date r on jr.reset_id = r.reset_id
+ where r.reset = ?1
+   and (?2 is null or datetime(jr.query_time) <= datetime(?2))
+   and (?3 is null or jr.event_time_minutes <= ?3)
+ order by jr.query_time desc
+ limit 1
+        "#,
+        reset_date,
+        query_time_lte,
+        event_time_minutes_lte,
+    )
+    .fetch_optional(pool)
+    .await
+}
+
+#[tracing::instrument(level = "debug", skip_all)]
 pub(crate) async fn select_jump_gate_agent_assignment_for_reset(
     pool: &Pool<Sqlite>,
     reset_date: NaiveDate,
@@ -196,6 +359,7 @@ order by rd.reset
     .await
 }
 
+#[tracing::instrument(level = "debug", skip_all)]
 pub(crate) async fn save_construction_sites(
     pool: &Pool<Sqlite>,
     reset_date: ResetDate,
@@ -219,13 +383,14 @@ on conflict (reset_id, jump_gate_waypoint_symbol) do nothing
     }
 }
 
+#[tracing::instrument(level = "debug", skip_all)]
 pub(crate) async fn save_static_agent_infos(
     pool: &Pool<Sqlite>,
     reset_date: ResetDate,
     static_agent_infos: Vec<LeaderboardStaticAgentInfo>,
     construction_sites: Vec<DbConstructionSite>,
     now: NaiveDateTime,
-) {
+) -> Result<(), Error> {
     let cs_lookup: HashMap<&String, &i64> = HashMap::from_iter(
         construction_sites
             .iter()
@@ -242,24 +407,34 @@ pub(crate) async fn save_static_agent_infos(
         let reset_id = reset_date.reset_id;
         let query_time = now;
 
+        // a retried tick might see the agent again. Changed infos get updated, the previous values
+        // end up in static_agent_info_history (see trigger tr_static_agent_info__history)
         sqlx::query!(
-        "
+            "
 insert into static_agent_info (agent_symbol, agent_headquarters_waypoint_symbol, construction_site_id, starting_faction, reset_id, query_time)
 values (?, ?, ?, ?, ?, ?)
-        ",
-        agent_symbol,
-agent_headquarters_waypoint_symbol,
+on conflict (reset_id, agent_symbol) do update
+    set agent_headquarters_waypoint_symbol = excluded.agent_headquarters_waypoint_symbol
+      , construction_site_id               = excluded.construction_site_id
+      , starting_faction                   = excluded.starting_faction
+  where agent_headquarters_waypoint_symbol is not excluded.agent_headquarters_waypoint_symbol
+     or construction_site_id is not excluded.construction_site_id
+     or starting_faction is not excluded.starting_faction
+            ",
+            agent_symbol,
+            agent_headquarters_waypoint_symbol,
             construction_site_id,
-starting_faction,
-reset_id,
-query_time
-            )
-            .execute(pool)
-            .await
-            .unwrap();
+            starting_faction,
+            reset_id,
+            query_time
+        )
+        .execute(pool)
+        .await?;
     }
+    Ok(())
 }
 
+#[tracing::instrument(level = "debug", skip_all)]
 pub(crate) async fn select_static_agent_infos_for_reset(
     pool: &Pool<Sqlite>,
     reset_date: ResetDate,
@@ -269,6 +444,7 @@ pub(crate) async fn select_static_agent_infos_for_reset(
         "
 select id
      , agent_symbol
+     , agent_headquarters_waypoint_symbol
      , construction_site_id
      , starting_faction
      , reset_id
@@ -282,6 +458,7 @@ select id
     .await
 }
 
+#[tracing::instrument(level = "debug", skip_all)]
 pub(crate) async fn insert_job_run(
     pool: &Pool<Sqlite>,
     reset_date: ResetDate,
@@ -304,6 +481,7 @@ returning id, reset_id, query_time, event_time_minutes
     .await
 }
 
+#[tracing::instrument(level = "debug", skip_all)]
 pub(crate) async fn select_construction_sites_for_reset(
     pool: &Pool<Sqlite>,
     reset_date: ResetDate,
@@ -323,6 +501,7 @@ select id
     .await
 }
 
+#[tracing::instrument(level = "debug", skip_all)]
 pub(crate) async fn select_jump_gate_construction_event_overview_for_reset(
     pool: &Pool<Sqlite>,
     reset_date: NaiveDate,
@@ -362,6 +541,7 @@ where reset = ?
         .await
 }
 
+#[tracing::instrument(level = "debug", skip_all)]
 pub(crate) async fn select_construction_progress_for_reset(
     pool: &Pool<Sqlite>,
     reset_date: NaiveDate,
@@ -422,6 +602,7 @@ group by jump_gate_waypoint_symbol
     .await
 }
 
+#[tracing::instrument(level = "debug", skip_all)]
 pub(crate) async fn select_most_recent_construction_progress_for_reset(
     pool: &Pool<Sqlite>,
     reset_date: NaiveDate,
@@ -470,6 +651,17 @@ where required > 1
     .await
 }
 
+#[tracing::instrument(
+    level = "debug",
+    skip_all,
+    fields(
+        reset = %reset_date,
+        from_event_time_minutes_gte = from_event_time_minutes_gte,
+        to_event_time_minutes_lte = to_event_time_minutes_lte,
+        resolution_minutes = resolution_minutes,
+        num_agents = agent_symbols.len(),
+    )
+)]
 pub(crate) async fn select_agent_history(
     pool: &Pool<Sqlite>,
     reset_date: NaiveDate,
@@ -482,13 +674,6 @@ pub(crate) async fn select_agent_history(
     let or_gte_value_to_include_latest = to_event_time_minutes_lte;
     let agent_symbols_json_string = serde_json::to_string(&agent_symbols).unwrap();
 
-    dbg!(reset_date);
-    dbg!(from_event_time_minutes_gte);
-    dbg!(to_event_time_minutes_lte);
-    dbg!(resolution_minutes);
-    dbg!(or_gte_value_to_include_latest);
-    dbg!(agent_symbols_json_string.clone());
-
     // sqlx doesn't understand a group-concat with int-values apparently
     // using an alias with a type handles that
     sqlx::query_as!(
@@ -531,6 +716,7 @@ group by ad.agent_symbol
     .await
 }
 
+#[tracing::instrument(level = "debug", skip_all)]
 pub(crate) async fn select_all_time_performance(
     pool: &Pool<Sqlite>,
 ) -> Result<Vec<DbAllTimePerformanceEntry>, Error> {
@@ -552,6 +738,7 @@ with last_entry_of_reset as (select *
                              where rn = 1)
    , ranked as (select rd.reset
                      , sai.agent_symbol
+                     , sai.starting_faction
                      , al.credits
                      , row_number() over (partition by rd.reset order by credits desc, sai.agent_symbol) as rank
                 from reset_date rd
@@ -569,6 +756,7 @@ order by reset
     .await
 }
 
+#[tracing::instrument(level = "debug", skip_all)]
 pub(crate) async fn select_all_time_construction_leaderboard(
     pool: &Pool<Sqlite>,
 ) -> Result<Vec<DbConstructionLeaderboardEntry>, Error> {
@@ -607,144 +795,1127 @@ from v_construction_leaderboard
     .await
 }
 
-async fn insert_agent_log_entry(
+/// Filters, sort order and page of the list endpoints.
+/// `sort_column` is one of the columns the respective query sorts by, `direction` is `asc` or `desc`.
+#[derive(Debug, Clone, Default)]
+pub(crate) struct DbListQuery<'a> {
+    pub(crate) faction: Option<&'a str>,
+    /// case-insensitive substring of the agent symbol
+    pub(crate) agent_symbol: Option<&'a str>,
+    pub(crate) min_credits: Option<i64>,
+    pub(crate) sort_column: Option<&'static str>,
+    pub(crate) direction: &'static str,
+    pub(crate) limit: Option<u32>,
+    pub(crate) offset: u32,
+}
+
+/// One page of a list plus the number of entries matching the filters
+#[derive(Debug, Clone)]
+pub(crate) struct DbPage<T> {
+    pub(crate) total_count: u32,
+    pub(crate) entries: Vec<T>,
+}
+
+impl<T> DbPage<T> {
+    /// The page queries left join the page to the total count, so that an empty page still has a row.
+    /// `split` returns None for that row.
+    fn from_rows<R>(
+        rows: Vec<R>,
+        total_count: impl Fn(&R) -> i64,
+        split: impl Fn(R) -> Option<T>,
+    ) -> Self {
+        Self {
+            total_count: rows.first().map(total_count).unwrap_or_default() as u32,
+            entries: rows.into_iter().filter_map(split).collect(),
+        }
+    }
+}
+
+#[derive(Debug, Clone)]
+pub(crate) struct DbRankedLeaderboardEntry {
+    /// 1-based rank by credits - independent of filters and sort order
+    pub(crate) rank: i64,
+    pub(crate) entry: LeaderboardEntry,
+}
+
+struct DbLeaderboardPageRow {
+    total_count: i64,
+    rank: Option<i64>,
+    agent_symbol: Option<String>,
+    credits: Option<i64>,
+    ship_count: Option<i64>,
+    agent_headquarters_waypoint_symbol: Option<String>,
+    jump_gate_waypoint_symbol: Option<String>,
+    starting_faction: Option<String>,
+}
+
+/// Leaderboard as of the given job run, filtered, sorted (by `credits`, `ship_count` or `agent_symbol`)
+/// and paged in the database.
+#[tracing::instrument(level = "debug", skip_all)]
+pub(crate) async fn select_leaderboard_page_for_job_run(
     pool: &Pool<Sqlite>,
-    job_run: DbJobRun,
-    leaderboard_current_agent_info: LeaderboardCurrentAgentInfo,
-    db_static_agent_info: DbStaticAgentInfo,
-) {
-    // sqlx for sqlite doesn't handle u64 (bigint)
-    let credits = leaderboard_current_agent_info.credits as i64;
-    sqlx::query!(
-        "
-insert into agent_log (agent_id, job_id, credits, ship_count)
-values (?, ?, ?, ?)
-        ",
-        db_static_agent_info.id,
-        job_run.id,
-        credits,
-        leaderboard_current_agent_info.ship_count,
+    job_run_id: i64,
+    list_query: &DbListQuery<'_>,
+) -> Result<DbPage<DbRankedLeaderboardEntry>, Error> {
+    let rows = sqlx::query_as!(
+        DbLeaderboardPageRow,
+        r#"
+with ranked as (select sai.agent_symbol
+                     , al.credits
+                     , al.ship_count
+                     , sai.agent_headquarters_waypoint_symbol
+                     , cs.jump_gate_waypoint_symbol
+                     , sai.starting_faction
+                     , row_number() over (order by al.credits desc, al.ship_count desc, sai.agent_symbol) as rank
+                  from agent_log al
+                           join static_agent_info sai on al.agent_id = sai.id
+                           join main.construction_site cs on sai.construction_site_id = cs.id
+                 where al.job_id = ?1)
+   , filtered as (select *
+                    from ranked
+                   where (?2 is null or upper(starting_faction) = upper(?2))
+                     and (?3 is null or instr(upper(agent_symbol), upper(?3)) > 0)
+                     and (?4 is null or credits >= ?4))
+   , sorted as (select *
+                     , row_number() over (order by case when ?6 = 'asc' then sort_key end
+                                                 , case when ?6 = 'desc' then sort_key end desc
+                                                 , rank) as position
+                  from (select *
+                             , case ?5
+                                   when 'ship_count' then ship_count
+                                   when 'agent_symbol' then agent_symbol
+                                   else credits
+                               end as sort_key
+                          from filtered) sub)
+select c.total_count as "total_count!: i64"
+     , s.rank as "rank?: i64"
+     , s.agent_symbol as "agent_symbol?: String"
+     , s.credits as "credits?: i64"
+     , s.ship_count as "ship_count?: i64"
+     , s.agent_headquarters_waypoint_symbol as "agent_headquarters_waypoint_symbol?: String"
+     , s.jump_gate_waypoint_symbol as "jump_gate_waypoint_symbol?: String"
+     , s.starting_faction as "starting_faction?: String"
+  from (select count(*) as total_count from filtered) c
+           left join sorted s
+                     on s.position > ?7
+                         and (?8 is null or s.position <= ?7 + ?8)
+ order by s.position
+        "#,
+        job_run_id,
+        list_query.faction,
+        list_query.agent_symbol,
+        list_query.min_credits,
+        list_query.sort_column,
+        list_query.direction,
+        list_query.offset,
+        list_query.limit,
     )
-    .execute(pool)
-    .await
-    .unwrap();
+    .fetch_all(pool)
+    .await?;
+
+    Ok(DbPage::from_rows(
+        rows,
+        |row| row.total_count,
+        |row| {
+            Some(DbRankedLeaderboardEntry {
+                rank: row.rank?,
+                entry: LeaderboardEntry {
+                    agent_symbol: row.agent_symbol?,
+                    credits: row.credits?,
+                    ship_count: row.ship_count?,
+                    agent_headquarters_waypoint_symbol: row.agent_headquarters_waypoint_symbol?,
+                    jump_gate_waypoint_symbol: row.jump_gate_waypoint_symbol?,
+                    starting_faction: row.starting_faction?,
+                },
+            })
+        },
+    ))
 }
 
-async fn insert_construction_log(
+struct DbAllTimePerformancePageRow {
+    total_count: i64,
+    reset: Option<NaiveDate>,
+    agent_symbol: Option<String>,
+    starting_faction: Option<String>,
+    credits: Option<i64>,
+    rank: Option<i64>,
+}
+
+/// Like [select_all_time_performance], but filtered, sorted (by `reset`, `rank`, `credits` or
+/// `agent_symbol` - ties by reset and rank) and paged in the database.
+#[tracing::instrument(level = "debug", skip_all)]
+pub(crate) async fn select_all_time_performance_page(
     pool: &Pool<Sqlite>,
-    job_run: DbJobRun,
-    current: LeaderboardCurrentConstructionInfo,
-    db_construction_site: DbConstructionSite,
-    db_construction_requirements: &Vec<DbConstructionRequirement>,
-) -> Result<(), Box<dyn std::error::Error>> {
-    let construction_log: DbConstructionLog = sqlx::query_as!(
-        DbConstructionLog,
-        "
-insert into construction_log (job_id, construction_site_id, is_complete)
-values (?, ?, ?)
-returning id, job_id, construction_site_id, is_complete
-        ",
-        job_run.id,
-        db_construction_site.id,
-        current.is_complete
+    list_query: &DbListQuery<'_>,
+) -> Result<DbPage<DbAllTimePerformanceEntry>, Error> {
+    let rows = sqlx::query_as!(
+        DbAllTimePerformancePageRow,
+        r#"
+with last_entry_of_reset as (select *
+                             from (select r.reset_id
+                                        , row_number() over (partition by jr.reset_id order by jr.query_time desc) as rn
+                                        , jr.id                                                                    as job_run_id_latest_entry
+                                   from reset_date r
+                                            join main.job_run jr
+                                                 on r.reset_id = jr.reset_id) sub
+                             where rn = 1)
+   , ranked as (select rd.reset
+                     , sai.agent_symbol
+                     , sai.starting_faction
+                     , al.credits
+                     , row_number() over (partition by rd.reset order by credits desc, sai.agent_symbol) as rank
+                from last_entry_of_reset last
+                         join main.agent_log al on al.job_id = last.job_run_id_latest_entry
+                         join main.static_agent_info sai on al.agent_id = sai.id
+                         join reset_date rd on rd.reset_id = last.reset_id)
+   , filtered as (select *
+                    from ranked
+                   where (?1 is null or upper(starting_faction) = upper(?1))
+                     and (?2 is null or instr(upper(agent_symbol), upper(?2)) > 0)
+                     and (?3 is null or credits >= ?3))
+   , sorted as (select *
+                     , row_number() over (order by case when ?5 = 'asc' then sort_key end
+                                                 , case when ?5 = 'desc' then sort_key end desc
+                                                 , reset
+                                                 , rank) as position
+                  from (select *
+                             , case ?4
+                                   when 'rank' then rank
+                                   when 'credits' then credits
+                                   when 'agent_symbol' then agent_symbol
+                                   else reset
+                               end as sort_key
+                          from filtered) sub)
+select c.total_count as "total_count!: i64"
+     , s.reset as "reset?: NaiveDate"
+     , s.agent_symbol as "agent_symbol?: String"
+     , s.starting_faction as "starting_faction?: String"
+     , s.credits as "credits?: i64"
+     , s.rank as "rank?: i64"
+  from (select count(*) as total_count from filtered) c
+           left join sorted s
+                     on s.position > ?6
+                         and (?7 is null or s.position <= ?6 + ?7)
+ order by s.position
+        "#,
+        list_query.faction,
+        list_query.agent_symbol,
+        list_query.min_credits,
+        list_query.sort_column,
+        list_query.direction,
+        list_query.offset,
+        list_query.limit,
     )
-    .fetch_one(pool)
+    .fetch_all(pool)
     .await?;
 
-    for material in current.materials {
-        let db_requirement = db_construction_requirements
-            .iter()
-            .find(|r| r.trade_symbol == material.trade_symbol)
-            .unwrap();
+    Ok(DbPage::from_rows(
+        rows,
+        |row| row.total_count,
+        |row| {
+            Some(DbAllTimePerformanceEntry {
+                reset: row.reset?,
+                agent_symbol: row.agent_symbol?,
+                starting_faction: row.starting_faction?,
+                credits: row.credits?,
+                rank: row.rank?,
+            })
+        },
+    ))
+}
 
-        insert_construction_material_log(pool, &construction_log, material, db_requirement).await;
-    }
+struct DbConstructionLeaderboardPageRow {
+    total_count: i64,
+    reset_date: Option<NaiveDate>,
+    ts_start_of_reset: Option<NaiveDateTime>,
+    jump_gate_waypoint_symbol: Option<String>,
+    agents_in_system_csv: Option<String>,
+    ts_start_jump_gate_construction: Option<NaiveDateTime>,
+    ts_finish_jump_gate_construction: Option<NaiveDateTime>,
+    duration_minutes_start_fortnight_start_jump_gate_construction: Option<i64>,
+    duration_minutes_start_fortnight_finish_jump_gate_construction: Option<i64>,
+    duration_minutes_jump_gate_construction: Option<i64>,
+    rank_jump_gate_construction: Option<i64>,
+    rank_start_fortnight_start_jump_gate_construction: Option<i64>,
+    rank_start_fortnight_finish_jump_gate_construction: Option<i64>,
+}
 
-    Ok(())
+/// Like [select_all_time_construction_leaderboard], but filtered by an agent in the system, sorted
+/// and paged in the database. Without `sort_column` the entries are ordered by reset and rank.
+/// Unfinished jump gates come last.
+#[tracing::instrument(level = "debug", skip_all)]
+pub(crate) async fn select_all_time_construction_leaderboard_page(
+    pool: &Pool<Sqlite>,
+    list_query: &DbListQuery<'_>,
+) -> Result<DbPage<DbConstructionLeaderboardEntry>, Error> {
+    let rows = sqlx::query_as!(
+        DbConstructionLeaderboardPageRow,
+        r#"
+with filtered as (select *
+                    from v_construction_leaderboard
+                   where (?1 is null or instr(upper(agents_in_system_csv), upper(?1)) > 0))
+   , sorted as (select *
+                     , row_number() over (order by case when ?3 = 'asc' then sort_key end
+                                                 , case when ?3 = 'desc' then sort_key end desc
+                                                 , reset_date
+                                                 , rank__jump_gate_construction
+                                                 , jump_gate_waypoint_symbol) as position
+                  from (select *
+                             , case ?2
+                                   when 'reset_date' then reset_date
+                                   when 'rank__jump_gate_construction' then rank__jump_gate_construction
+                                   when 'rank__start_fortnight__finish_jump_gate_construction'
+                                       then rank__start_fortnight__finish_jump_gate_construction
+                                   when 'duration_minutes__jump_gate_construction'
+                                       -- unfinished jump gates last
+                                       then coalesce(duration_minutes__jump_gate_construction,
+                                                     case when ?3 = 'desc' then -1 else 9223372036854775807 end)
+                               end as sort_key
+                          from filtered) sub)
+select c.total_count as "total_count!: i64"
+     , s.reset_date as "reset_date?: NaiveDate"
+     , s.ts_start_of_reset as "ts_start_of_reset?: NaiveDateTime"
+     , s.jump_gate_waypoint_symbol as "jump_gate_waypoint_symbol?: String"
+     , s.agents_in_system_csv as "agents_in_system_csv?: String"
+     , s.ts_start_jump_gate_construction as "ts_start_jump_gate_construction?: NaiveDateTime"
+     , s.ts_finish_jump_gate_construction as "ts_finish_jump_gate_construction?: NaiveDateTime"
+     , s.duration_minutes__start_fortnight__start_jump_gate_construction as "duration_minutes_start_fortnight_start_jump_gate_construction?: i64"
+     , s.duration_minutes__start_fortnight__finish_jump_gate_construction as "duration_minutes_start_fortnight_finish_jump_gate_construction?: i64"
+     , s.duration_minutes__jump_gate_construction as "duration_minutes_jump_gate_construction?: i64"
+     , s.rank__jump_gate_construction as "rank_jump_gate_construction?: i64"
+     , s.rank__start_fortnight__start_jump_gate_construction as "rank_start_fortnight_start_jump_gate_construction?: i64"
+     , s.rank__start_fortnight__finish_jump_gate_construction as "rank_start_fortnight_finish_jump_gate_construction?: i64"
+  from (select count(*) as total_count from filtered) c
+           left join sorted s
+                     on s.position > ?4
+                         and (?5 is null or s.position <= ?4 + ?5)
+ order by s.position
+        "#,
+        list_query.agent_symbol,
+        list_query.sort_column,
+        list_query.direction,
+        list_query.offset,
+        list_query.limit,
+    )
+    .fetch_all(pool)
+    .await?;
+
+    Ok(DbPage::from_rows(
+        rows,
+        |row| row.total_count,
+        |row| {
+            Some(DbConstructionLeaderboardEntry {
+                reset_date: row.reset_date?,
+                ts_start_of_reset: row.ts_start_of_reset?,
+                jump_gate_waypoint_symbol: row.jump_gate_waypoint_symbol?,
+                agents_in_system_csv: row.agents_in_system_csv?,
+                ts_start_jump_gate_construction: row.ts_start_jump_gate_construction?,
+                ts_finish_jump_gate_construction: row.ts_finish_jump_gate_construction,
+                duration_minutes_start_fortnight_start_jump_gate_construction: row
+                    .duration_minutes_start_fortnight_start_jump_gate_construction?,
+                duration_minutes_start_fortnight_finish_jump_gate_construction: row
+                    .duration_minutes_start_fortnight_finish_jump_gate_construction,
+                duration_minutes_jump_gate_construction: row
+                    .duration_minutes_jump_gate_construction,
+                rank_jump_gate_construction: row.rank_jump_gate_construction?,
+                rank_start_fortnight_start_jump_gate_construction: row
+                    .rank_start_fortnight_start_jump_gate_construction?,
+                rank_start_fortnight_finish_jump_gate_construction: row
+                    .rank_start_fortnight_finish_jump_gate_construction?,
+            })
+        },
+    ))
 }
 
-async fn insert_construction_material_log(
+#[tracing::instrument(level = "debug", skip_all)]
+pub(crate) async fn select_export_agents(
     pool: &Pool<Sqlite>,
-    db_construction_log: &DbConstructionLog,
-    construction_material: ConstructionMaterial,
-    db_construction_requirement: &DbConstructionRequirement,
-) -> () {
-    sqlx::query!(
+    reset_id: i64,
+) -> Result<Vec<ExportAgent>, Error> {
+    sqlx::query_as!(
+        ExportAgent,
         "
-insert into construction_material_log (construction_log_id, construction_requirement_id, fulfilled)
-values (?, ?, ?)
+select id as agent_id
+     , agent_symbol
+     , agent_headquarters_waypoint_symbol
+     , starting_faction
+     , construction_site_id
+     , query_time as ts_first_seen
+  from static_agent_info
+ where reset_id = ?
+ order by id
         ",
-        db_construction_log.id,
-        db_construction_requirement.id,
-        construction_material.fulfilled
+        reset_id
     )
-    .execute(pool)
+    .fetch_all(pool)
     .await
-    .unwrap();
 }
 
-pub(crate) async fn insert_job_run_and_details(
+#[tracing::instrument(level = "debug", skip_all)]
+pub(crate) async fn select_export_job_runs(
     pool: &Pool<Sqlite>,
-    now: NaiveDateTime,
-    reset_date: ResetDate,
-    current_agent_infos: Vec<LeaderboardCurrentAgentInfo>,
-    db_static_agent_infos: Vec<DbStaticAgentInfo>,
-    current_construction_infos: Vec<LeaderboardCurrentConstructionInfo>,
-    db_construction_infos: Vec<DbConstructionSite>,
-) -> Result<(), Box<dyn std::error::Error>> {
-    let agent_lookup: HashMap<&String, &DbStaticAgentInfo> = HashMap::from_iter(
-        db_static_agent_infos
-            .iter()
-            .map(|sai| (&sai.agent_symbol, sai)),
-    );
-
-    let cs_lookup: HashMap<&String, &DbConstructionSite> = HashMap::from_iter(
-        db_construction_infos
-            .iter()
-            .map(|cs| (&cs.jump_gate_waypoint_symbol, cs)),
-    );
-
-    let construction_materials: Vec<ConstructionMaterial> = current_construction_infos
-        .get(0)
-        .into_iter()
-        .flat_map(|c| c.clone().materials)
-        .collect();
-
-    for construction_material in construction_materials {
-        sqlx::query!(
-            "
-insert into construction_requirement (reset_id, trade_symbol, required)
-values (?, ?, ?)
-on conflict (reset_id, trade_symbol) do nothing
-        ",
-            reset_date.reset_id,
-            construction_material.trade_symbol,
-            construction_material.required,
-        )
-        .execute(pool)
-        .await?;
-    }
-
-    let construction_requirements: Vec<DbConstructionRequirement> = sqlx::query_as!(
-        DbConstructionRequirement,
+    reset_id: i64,
+) -> Result<Vec<ExportJobRun>, Error> {
+    sqlx::query_as!(
+        ExportJobRun,
         "
-select id, reset_id, trade_symbol, required
-  from construction_requirement
+select id as job_run_id
+     , query_time
+     , event_time_minutes
+  from job_run
  where reset_id = ?
+ order by query_time
         ",
-        reset_date.reset_id,
+        reset_id
     )
     .fetch_all(pool)
-    .await?;
+    .await
+}
 
-    let job_run = insert_job_run(pool, reset_date, now).await?;
-    for current in current_agent_infos {
-        let static_agent_info = *agent_lookup.get(&current.symbol.0).unwrap();
-        insert_agent_log_entry(pool, job_run, current, static_agent_info.clone()).await;
+#[tracing::instrument(level = "debug", skip_all)]
+pub(crate) async fn select_export_agent_logs(
+    pool: &Pool<Sqlite>,
+    reset_id: i64,
+) -> Result<Vec<ExportAgentLog>, Error> {
+    sqlx::query_as!(
+        ExportAgentLog,
+        "
+select al.agent_id
+     , al.job_id as job_run_id
+     , al.credits
+     , al.ship_count
+  from agent_log al
+       join job_run jr on al.job_id = jr.id
+ where jr.reset_id = ?
+ order by jr.query_time, al.agent_id
+        ",
+        reset_id
+    )
+    .fetch_all(pool)
+    .await
+}
+
+#[tracing::instrument(level = "debug", skip_all)]
+pub(crate) async fn select_export_construction_sites(
+    pool: &Pool<Sqlite>,
+    reset_id: i64,
+) -> Result<Vec<ExportConstructionSite>, Error> {
+    sqlx::query_as!(
+        ExportConstructionSite,
+        "
+select id as construction_site_id
+     , jump_gate_waypoint_symbol
+  from construction_site
+ where reset_id = ?
+ order by id
+        ",
+        reset_id
+    )
+    .fetch_all(pool)
+    .await
+}
+
+#[tracing::instrument(level = "debug", skip_all)]
+pub(crate) async fn select_export_construction_requirements(
+    pool: &Pool<Sqlite>,
+    reset_id: i64,
+) -> Result<Vec<ExportConstructionRequirement>, Error> {
+    sqlx::query_as!(
+        ExportConstructionRequirement,
+        "
+select id as construction_requirement_id
+     , trade_symbol
+     , required
+  from construction_requirement
+ where reset_id = ?
+ order by id
+        ",
+        reset_id
+    )
+    .fetch_all(pool)
+    .await
+}
+
+#[tracing::instrument(level = "debug", skip_all)]
+pub(crate) async fn select_export_construction_material_logs(
+    pool: &Pool<Sqlite>,
+    reset_id: i64,
+) -> Result<Vec<ExportConstructionMaterialLog>, Error> {
+    sqlx::query_as!(
+        ExportConstructionMaterialLog,
+        "
+select cl.job_id as job_run_id
+     , cl.construction_site_id
+     , cml.construction_requirement_id
+     , cl.is_complete as is_jump_gate_complete
+     , cml.fulfilled
+  from construction_material_log cml
+       join construction_log cl on cml.construction_log_id = cl.id
+       join job_run jr on cl.job_id = jr.id
+ where jr.reset_id = ?
+ order by jr.query_time, cl.construction_site_id, cml.construction_requirement_id
+        ",
+        reset_id
+    )
+    .fetch_all(pool)
+    .await
+}
+
+#[tracing::instrument(level = "debug", skip_all)]
+pub(crate) async fn select_reset_for_import(
+    conn: &mut SqliteConnection,
+    reset_date: NaiveDate,
+) -> Result<Option<(i64, NaiveDateTime)>, Error> {
+    let maybe_row = sqlx::query!(
+        "
+select reset_id
+     , first_ts
+  from reset_date
+ where reset = ?
+        ",
+        reset_date
+    )
+    .fetch_optional(conn)
+    .await?;
+
+    Ok(maybe_row.map(|row| (row.reset_id, row.first_ts)))
+}
+
+#[tracing::instrument(level = "debug", skip_all)]
+pub(crate) async fn insert_reset_for_import(
+    conn: &mut SqliteConnection,
+    reset_date: NaiveDate,
+    first_ts: NaiveDateTime,
+) -> Result<i64, Error> {
+    sqlx::query_scalar!(
+        "
+insert into reset_date (reset, first_ts)
+VALUES (?, ?)
+returning reset_id
+        ",
+        reset_date,
+        first_ts,
+    )
+    .fetch_one(conn)
+    .await
+}
+
+#[tracing::instrument(level = "debug", skip_all)]
+pub(crate) async fn upsert_construction_site_for_import(
+    conn: &mut SqliteConnection,
+    reset_id: i64,
+    jump_gate_waypoint_symbol: &str,
+) -> Result<i64, Error> {
+    sqlx::query_scalar!(
+        "
+insert into construction_site (reset_id, jump_gate_waypoint_symbol)
+values (?, ?)
+on conflict (reset_id, jump_gate_waypoint_symbol) do update set jump_gate_waypoint_symbol = excluded.jump_gate_waypoint_symbol
+returning id
+        ",
+        reset_id,
+        jump_gate_waypoint_symbol
+    )
+    .fetch_one(conn)
+    .await
+}
+
+#[tracing::instrument(level = "debug", skip_all)]
+pub(crate) async fn select_construction_requirements_for_import(
+    conn: &mut SqliteConnection,
+    reset_id: i64,
+) -> Result<Vec<DbConstructionRequirement>, Error> {
+    sqlx::query_as!(
+        DbConstructionRequirement,
+        "
+select id, reset_id, trade_symbol, required
+  from construction_requirement
+ where reset_id = ?
+        ",
+        reset_id,
+    )
+    .fetch_all(conn)
+    .await
+}
+
+#[tracing::instrument(level = "debug", skip_all)]
+pub(crate) async fn insert_construction_requirement_for_import(
+    conn: &mut SqliteConnection,
+    reset_id: i64,
+    trade_symbol: &str,
+    required: i64,
+) -> Result<i64, Error> {
+    sqlx::query_scalar!(
+        "
+insert into construction_requirement (reset_id, trade_symbol, required)
+values (?, ?, ?)
+returning id
+        ",
+        reset_id,
+        trade_symbol,
+        required
+    )
+    .fetch_one(conn)
+    .await
+}
+
+#[tracing::instrument(level = "debug", skip_all)]
+pub(crate) async fn select_agents_for_import(
+    conn: &mut SqliteConnection,
+    reset_id: i64,
+) -> Result<Vec<ExportAgent>, Error> {
+    sqlx::query_as!(
+        ExportAgent,
+        "
+select id as agent_id
+     , agent_symbol
+     , agent_headquarters_waypoint_symbol
+     , starting_faction
+     , construction_site_id
+     , query_time as ts_first_seen
+  from static_agent_info
+ where reset_id = ?
+ order by id
+        ",
+        reset_id
+    )
+    .fetch_all(conn)
+    .await
+}
+
+#[tracing::instrument(level = "debug", skip_all)]
+pub(crate) async fn insert_agent_for_import(
+    conn: &mut SqliteConnection,
+    reset_id: i64,
+    construction_site_id: i64,
+    agent: &ExportAgent,
+) -> Result<i64, Error> {
+    sqlx::query_scalar!(
+        "
+insert into static_agent_info (agent_symbol, agent_headquarters_waypoint_symbol, construction_site_id, starting_faction, reset_id, query_time)
+values (?, ?, ?, ?, ?, ?)
+returning id
+        ",
+        agent.agent_symbol,
+        agent.agent_headquarters_waypoint_symbol,
+        construction_site_id,
+        agent.starting_faction,
+        reset_id,
+        agent.ts_first_seen
+    )
+    .fetch_one(conn)
+    .await
+}
+
+#[tracing::instrument(level = "debug", skip_all)]
+pub(crate) async fn select_job_runs_for_import(
+    conn: &mut SqliteConnection,
+    reset_id: i64,
+) -> Result<Vec<ExportJobRun>, Error> {
+    sqlx::query_as!(
+        ExportJobRun,
+        "
+select id as job_run_id
+     , query_time
+     , event_time_minutes
+  from job_run
+ where reset_id = ?
+ order by query_time
+        ",
+        reset_id
+    )
+    .fetch_all(conn)
+    .await
+}
+
+#[tracing::instrument(level = "debug", skip_all)]
+pub(crate) async fn insert_job_run_for_import(
+    conn: &mut SqliteConnection,
+    reset_id: i64,
+    query_time: NaiveDateTime,
+    event_time_minutes: i64,
+) -> Result<i64, Error> {
+    sqlx::query_scalar!(
+        "
+insert into job_run (reset_id, query_time, event_time_minutes)
+VALUES (?, ?, ?)
+returning id
+        ",
+        reset_id,
+        query_time,
+        event_time_minutes
+    )
+    .fetch_one(conn)
+    .await
+}
+
+#[tracing::instrument(level = "debug", skip_all)]
+pub(crate) async fn insert_agent_log_for_import(
+    conn: &mut SqliteConnection,
+    agent_id: i64,
+    job_run_id: i64,
+    credits: i64,
+    ship_count: i64,
+) -> Result<(), Error> {
+    sqlx::query!(
+        "
+insert into agent_log (agent_id, job_id, credits, ship_count)
+values (?, ?, ?, ?)
+        ",
+        agent_id,
+        job_run_id,
+        credits,
+        ship_count,
+    )
+    .execute(conn)
+    .await?;
+    Ok(())
+}
+
+#[tracing::instrument(level = "debug", skip_all)]
+pub(crate) async fn insert_construction_log_for_import(
+    conn: &mut SqliteConnection,
+    job_run_id: i64,
+    construction_site_id: i64,
+    is_complete: bool,
+) -> Result<i64, Error> {
+    sqlx::query_scalar!(
+        "
+insert into construction_log (job_id, construction_site_id, is_complete)
+values (?, ?, ?)
+returning id
+        ",
+        job_run_id,
+        construction_site_id,
+        is_complete
+    )
+    .fetch_one(conn)
+    .await
+}
+
+#[tracing::instrument(level = "debug", skip_all)]
+pub(crate) async fn insert_construction_material_log_for_import(
+    conn: &mut SqliteConnection,
+    construction_log_id: i64,
+    construction_requirement_id: i64,
+    fulfilled: i64,
+) -> Result<(), Error> {
+    sqlx::query!(
+        "
+insert into construction_material_log (construction_log_id, construction_requirement_id, fulfilled)
+values (?, ?, ?)
+        ",
+        construction_log_id,
+        construction_requirement_id,
+        fulfilled
+    )
+    .execute(conn)
+    .await?;
+    Ok(())
+}
+
+/// The regular refresh only covers the latest reset, so an imported (older) reset needs its own run.
+#[tracing::instrument(level = "debug", skip_all)]
+pub(crate) async fn refresh_material_delivery_events_for_reset(
+    conn: &mut SqliteConnection,
+    reset_id: i64,
+) -> Result<(), Error> {
+    sqlx::query!(
+        "
+delete
+  from mat_view_material_delivery_events
+ where reset_id = ?
+        ",
+        reset_id
+    )
+    .execute(&mut *conn)
+    .await?;
+
+    sqlx::query!(
+        "
+insert into mat_view_material_delivery_events (reset_id,
+                                               construction_site_id,
+                                               construction_requirement_id,
+                                               first_ts,
+                                               query_time,
+                                               duration_seconds,
+                                               delivery_event)
+select reset_id,
+       construction_site_id,
+       construction_requirement_id,
+       first_ts,
+       query_time,
+       duration_seconds,
+       delivery_event
+  from v_material_delivery_events
+ where reset_id = ?
+        ",
+        reset_id
+    )
+    .execute(&mut *conn)
+    .await?;
+
+    Ok(())
+}
+
+#[tracing::instrument(level = "debug", skip_all)]
+pub(crate) async fn select_misaligned_reset_starts(
+    conn: &mut SqliteConnection,
+) -> Result<Vec<DbIntegrityViolation>, Error> {
+    sqlx::query_as!(
+        DbIntegrityViolation,
+        r#"
+select printf('reset %s starts at %s', reset, first_ts) as "details!: String"
+  from reset_date
+ where cast(strftime('%s', first_ts) as integer) % 300 != 0
+ order by reset_id
+        "#
+    )
+    .fetch_all(conn)
+    .await
+}
+
+#[tracing::instrument(level = "debug", skip_all)]
+pub(crate) async fn select_misaligned_job_runs(
+    conn: &mut SqliteConnection,
+) -> Result<Vec<DbIntegrityViolation>, Error> {
+    sqlx::query_as!(
+        DbIntegrityViolation,
+        r#"
+select printf('job_run %d of reset %s ran at %s', jr.id, r.reset, jr.query_time) as "details!: String"
+  from job_run jr
+       join reset_date r on jr.reset_id = r.reset_id
+ where cast(strftime('%s', jr.query_time) as integer) % 300 != 0
+ order by jr.id
+        "#
+    )
+    .fetch_all(conn)
+    .await
+}
+
+#[tracing::instrument(level = "debug", skip_all)]
+pub(crate) async fn select_inconsistent_event_times(
+    conn: &mut SqliteConnection,
+) -> Result<Vec<DbIntegrityViolation>, Error> {
+    sqlx::query_as!(
+        DbIntegrityViolation,
+        r#"
+select printf('job_run %d of reset %s has event_time_minutes %d, expected %d', jr.id, r.reset, jr.event_time_minutes,
+              (strftime('%s', jr.query_time) - strftime('%s', r.first_ts)) / 60) as "details!: String"
+  from job_run jr
+       join reset_date r on jr.reset_id = r.reset_id
+ where jr.event_time_minutes != (strftime('%s', jr.query_time) - strftime('%s', r.first_ts)) / 60
+ order by jr.id
+        "#
+    )
+    .fetch_all(conn)
+    .await
+}
+
+#[tracing::instrument(level = "debug", skip_all)]
+pub(crate) async fn select_duplicate_static_agent_infos(
+    conn: &mut SqliteConnection,
+) -> Result<Vec<DbIntegrityViolation>, Error> {
+    sqlx::query_as!(
+        DbIntegrityViolation,
+        r#"
+select printf('agent %s has %d entries in reset %s', s.agent_symbol, count(*), r.reset) as "details!: String"
+  from static_agent_info s
+       join reset_date r on s.reset_id = r.reset_id
+ group by s.reset_id, s.agent_symbol
+having count(*) > 1
+ order by min(s.id)
+        "#
+    )
+    .fetch_all(conn)
+    .await
+}
+
+#[tracing::instrument(level = "debug", skip_all)]
+pub(crate) async fn select_overfulfilled_construction_materials(
+    conn: &mut SqliteConnection,
+) -> Result<Vec<DbIntegrityViolation>, Error> {
+    sqlx::query_as!(
+        DbIntegrityViolation,
+        r#"
+select printf('%s: %d of %d %s fulfilled at %s', cs.jump_gate_waypoint_symbol, cml.fulfilled, cr.required, cr.trade_symbol,
+              jr.query_time) as "details!: String"
+  from construction_material_log cml
+       join construction_log cl on cml.construction_log_id = cl.id
+       join construction_site cs on cl.construction_site_id = cs.id
+       join construction_requirement cr on cml.construction_requirement_id = cr.id
+       join job_run jr on cl.job_id = jr.id
+ where cml.fulfilled > cr.required
+ order by cl.id
+        "#
+    )
+    .fetch_all(conn)
+    .await
+}
+
+#[tracing::instrument(level = "debug", skip_all)]
+pub(crate) async fn select_decreasing_construction_materials(
+    conn: &mut SqliteConnection,
+) -> Result<Vec<DbIntegrityViolation>, Error> {
+    sqlx::query_as!(
+        DbIntegrityViolation,
+        r#"
+with lagged as (select cl.id                                                                                                                as construction_log_id
+                     , cs.jump_gate_waypoint_symbol
+                     , cr.trade_symbol
+                     , jr.query_time
+                     , cml.fulfilled
+                     , lag(cml.fulfilled) over (partition by cl.construction_site_id, cml.construction_requirement_id order by jr.query_time) as prev_fulfilled
+                from construction_material_log cml
+                         join construction_log cl on cml.construction_log_id = cl.id
+                         join construction_site cs on cl.construction_site_id = cs.id
+                         join construction_requirement cr on cml.construction_requirement_id = cr.id
+                         join job_run jr on cl.job_id = jr.id)
+select printf('%s: fulfilled %s dropped from %d to %d at %s', jump_gate_waypoint_symbol, trade_symbol, prev_fulfilled, fulfilled,
+              query_time) as "details!: String"
+  from lagged
+ where fulfilled < prev_fulfilled
+ order by construction_log_id
+        "#
+    )
+    .fetch_all(conn)
+    .await
+}
+
+/// Rounds the start of the reset to the nearest 5 minutes - same as the fix-migrations did.
+#[tracing::instrument(level = "debug", skip_all)]
+pub(crate) async fn align_reset_starts(conn: &mut SqliteConnection) -> Result<u64, Error> {
+    let result = sqlx::query!(
+        "
+update reset_date
+   set first_ts = datetime((cast(strftime('%s', first_ts) as integer) + 150) / 300 * 300, 'unixepoch')
+ where cast(strftime('%s', first_ts) as integer) % 300 != 0
+        "
+    )
+    .execute(conn)
+    .await?;
+    Ok(result.rows_affected())
+}
+
+/// Rounds job runs to the nearest 5 minutes.
+/// A job run is left untouched if another run of the same reset already occupies the rounded time
+/// (or a lower id would be rounded to it), so it keeps showing up as a violation instead of becoming a duplicate.
+#[tracing::instrument(level = "debug", skip_all)]
+pub(crate) async fn align_job_runs(conn: &mut SqliteConnection) -> Result<u64, Error> {
+    let result = sqlx::query!(
+        "
+with misaligned as (select id
+                         , reset_id
+                         , (cast(strftime('%s', query_time) as integer) + 150) / 300 * 300 as aligned_epoch
+                      from job_run
+                     where cast(strftime('%s', query_time) as integer) % 300 != 0)
+   , aligned as (select m.id
+                      , m.aligned_epoch
+                   from misaligned m
+                  where not exists (select 1
+                                      from job_run other
+                                     where other.reset_id = m.reset_id
+                                       and cast(strftime('%s', other.query_time) as integer) = m.aligned_epoch)
+                    and not exists (select 1
+                                      from misaligned earlier
+                                     where earlier.reset_id = m.reset_id
+                                       and earlier.aligned_epoch = m.aligned_epoch
+                                       and earlier.id < m.id))
+update job_run
+   set query_time = datetime(a.aligned_epoch, 'unixepoch')
+  from aligned a
+ where job_run.id = a.id
+        "
+    )
+    .execute(conn)
+    .await?;
+    Ok(result.rows_affected())
+}
+
+#[tracing::instrument(level = "debug", skip_all)]
+pub(crate) async fn recalculate_event_times(conn: &mut SqliteConnection) -> Result<u64, Error> {
+    let result = sqlx::query!(
+        "
+update job_run
+   set event_time_minutes = (strftime('%s', job_run.query_time) - strftime('%s', r.first_ts)) / 60
+  from reset_date r
+ where job_run.reset_id = r.reset_id
+   and job_run.event_time_minutes != (strftime('%s', job_run.query_time) - strftime('%s', r.first_ts)) / 60
+        "
+    )
+    .execute(conn)
+    .await?;
+    Ok(result.rows_affected())
+}
+
+/// Keeps the oldest entry per agent and reset and re-points the agent_log entries of the duplicates to it.
+/// Log entries of duplicates for a job_run the kept entry already has are dropped.
+///
+/// Runs the same script as the migration that precedes the unique index on (reset_id, agent_symbol),
+/// so this only finds something in databases that haven't been migrated yet.
+#[tracing::instrument(level = "debug", skip_all)]
+pub(crate) async fn merge_duplicate_static_agent_infos(
+    conn: &mut SqliteConnection,
+) -> Result<u64, Error> {
+    // NOTE: not checked by sqlx at compile-time
+    const SQL: &str =
+        include_str!("../migrations/20261019085900_merge_duplicate_static_agent_infos.sql");
+
+    let count_static_agent_infos = "select count(*) from static_agent_info";
+    let before: i64 = sqlx::query_scalar(count_static_agent_infos)
+        .fetch_one(&mut *conn)
+        .await?;
+    sqlx::query(SQL).execute(&mut *conn).await?;
+    let after: i64 = sqlx::query_scalar(count_static_agent_infos)
+        .fetch_one(&mut *conn)
+        .await?;
+
+    Ok((before - after) as u64)
+}
+
+async fn insert_agent_log_entry(
+    pool: &Pool<Sqlite>,
+    job_run: DbJobRun,
+    leaderboard_current_agent_info: LeaderboardCurrentAgentInfo,
+    db_static_agent_info: DbStaticAgentInfo,
+) {
+    // sqlx for sqlite doesn't handle u64 (bigint)
+    let credits = leaderboard_current_agent_info.credits as i64;
+    sqlx::query!(
+        "
+insert into agent_log (agent_id, job_id, credits, ship_count)
+values (?, ?, ?, ?)
+        ",
+        db_static_agent_info.id,
+        job_run.id,
+        credits,
+        leaderboard_current_agent_info.ship_count,
+    )
+    .execute(pool)
+    .await
+    .unwrap();
+}
+
+async fn insert_construction_log(
+    pool: &Pool<Sqlite>,
+    job_run: DbJobRun,
+    current: LeaderboardCurrentConstructionInfo,
+    db_construction_site: DbConstructionSite,
+    db_construction_requirements: &Vec<DbConstructionRequirement>,
+) -> Result<(), Error> {
+    let construction_log: DbConstructionLog = sqlx::query_as!(
+        DbConstructionLog,
+        "
+insert into construction_log (job_id, construction_site_id, is_complete)
+values (?, ?, ?)
+returning id, job_id, construction_site_id, is_complete
+        ",
+        job_run.id,
+        db_construction_site.id,
+        current.is_complete
+    )
+    .fetch_one(pool)
+    .await?;
+
+    for material in current.materials {
+        let db_requirement = db_construction_requirements
+            .iter()
+            .find(|r| r.trade_symbol == material.trade_symbol)
+            .unwrap();
+
+        insert_construction_material_log(pool, &construction_log, material, db_requirement).await;
+    }
+
+    Ok(())
+}
+
+async fn insert_construction_material_log(
+    pool: &Pool<Sqlite>,
+    db_construction_log: &DbConstructionLog,
+    construction_material: ConstructionMaterial,
+    db_construction_requirement: &DbConstructionRequirement,
+) -> () {
+    sqlx::query!(
+        "
+insert into construction_material_log (construction_log_id, construction_requirement_id, fulfilled)
+values (?, ?, ?)
+        ",
+        db_construction_log.id,
+        db_construction_requirement.id,
+        construction_material.fulfilled
+    )
+    .execute(pool)
+    .await
+    .unwrap();
+}
+
+#[tracing::instrument(level = "debug", skip_all)]
+pub(crate) async fn insert_job_run_and_details(
+    pool: &Pool<Sqlite>,
+    now: NaiveDateTime,
+    reset_date: ResetDate,
+    current_agent_infos: Vec<LeaderboardCurrentAgentInfo>,
+    db_static_agent_infos: Vec<DbStaticAgentInfo>,
+    current_construction_infos: Vec<LeaderboardCurrentConstructionInfo>,
+    db_construction_infos: Vec<DbConstructionSite>,
+) -> Result<InsertedRows, Error> {
+    let mut inserted_rows = InsertedRows::default();
+    let agent_lookup: HashMap<&String, &DbStaticAgentInfo> = HashMap::from_iter(
+        db_static_agent_infos
+            .iter()
+            .map(|sai| (&sai.agent_symbol, sai)),
+    );
+
+    let cs_lookup: HashMap<&String, &DbConstructionSite> = HashMap::from_iter(
+        db_construction_infos
+            .iter()
+            .map(|cs| (&cs.jump_gate_waypoint_symbol, cs)),
+    );
+
+    let construction_materials: Vec<ConstructionMaterial> = current_construction_infos
+        .get(0)
+        .into_iter()
+        .flat_map(|c| c.clone().materials)
+        .collect();
+
+    for construction_material in construction_materials {
+        sqlx::query!(
+            "
+insert into construction_requirement (reset_id, trade_symbol, required)
+values (?, ?, ?)
+on conflict (reset_id, trade_symbol) do nothing
+        ",
+            reset_date.reset_id,
+            construction_material.trade_symbol,
+            construction_material.required,
+        )
+        .execute(pool)
+        .await?;
+    }
+
+    let construction_requirements: Vec<DbConstructionRequirement> = sqlx::query_as!(
+        DbConstructionRequirement,
+        "
+select id, reset_id, trade_symbol, required
+  from construction_requirement
+ where reset_id = ?
+        ",
+        reset_date.reset_id,
+    )
+    .fetch_all(pool)
+    .await?;
+
+    let job_run = insert_job_run(pool, reset_date, now).await?;
+    for current in current_agent_infos {
+        let static_agent_info = *agent_lookup.get(&current.symbol.0).unwrap();
+        insert_agent_log_entry(pool, job_run, current, static_agent_info.clone()).await;
+        inserted_rows.agent_log += 1;
     }
 
     for current in current_construction_infos {
         let cs = *cs_lookup.get(&current.symbol.0).unwrap();
+        inserted_rows.construction_log += 1;
+        inserted_rows.construction_material_log += current.materials.len() as u64;
         insert_construction_log(
             pool,
             job_run,
@@ -755,33 +1926,280 @@ select id, reset_id, trade_symbol, required
         .await?;
     }
 
+    Ok(inserted_rows)
+}
+
+/// number of rows written by [insert_job_run_and_details] per table
+#[derive(Debug, Clone, Copy, Default)]
+pub(crate) struct InsertedRows {
+    pub(crate) agent_log: u64,
+    pub(crate) construction_log: u64,
+    pub(crate) construction_material_log: u64,
+}
+
+#[tracing::instrument(level = "debug", skip_all)]
+pub(crate) async fn insert_api_token(
+    pool: &Pool<Sqlite>,
+    name: &str,
+    token_sha256: &str,
+    created_at: NaiveDateTime,
+) -> Result<i64, Error> {
+    // no `returning` - the autocommit of a statement that isn't stepped to the end only happens
+    // when it gets reset, which might be too late for a short-lived cli process
+    let result = sqlx::query!(
+        "
+insert into api_token (name, token_sha256, created_at)
+values (?, ?, ?)
+        ",
+        name,
+        token_sha256,
+        created_at
+    )
+    .execute(pool)
+    .await?;
+    Ok(result.last_insert_rowid())
+}
+
+/// Returns the number of revoked tokens (0 if there is no active token with that name)
+#[tracing::instrument(level = "debug", skip_all)]
+pub(crate) async fn revoke_api_token(
+    pool: &Pool<Sqlite>,
+    name: &str,
+    revoked_at: NaiveDateTime,
+) -> Result<u64, Error> {
+    let result = sqlx::query!(
+        "
+update api_token
+   set revoked_at = ?
+ where name = ?
+   and revoked_at is null
+        ",
+        revoked_at,
+        name
+    )
+    .execute(pool)
+    .await?;
+    Ok(result.rows_affected())
+}
+
+/// Returns the name of the active token with that hash
+#[tracing::instrument(level = "debug", skip_all)]
+pub(crate) async fn select_active_api_token_name(
+    pool: &Pool<Sqlite>,
+    token_sha256: &str,
+) -> Result<Option<String>, Error> {
+    sqlx::query_scalar!(
+        "
+select name
+  from api_token
+ where token_sha256 = ?
+   and revoked_at is null
+        ",
+        token_sha256
+    )
+    .fetch_optional(pool)
+    .await
+}
+
+#[tracing::instrument(level = "debug", skip_all)]
+pub(crate) async fn select_has_active_api_tokens(pool: &Pool<Sqlite>) -> Result<bool, Error> {
+    sqlx::query_scalar!(
+        r#"
+select exists(select 1 from api_token where revoked_at is null) as "has_active_tokens!: bool"
+        "#
+    )
+    .fetch_one(pool)
+    .await
+}
+
+/// The most recent job runs of a reset, newest first
+#[tracing::instrument(level = "debug", skip_all)]
+pub(crate) async fn select_recent_job_runs_of_reset(
+    pool: &Pool<Sqlite>,
+    reset_date: NaiveDate,
+    limit: i64,
+) -> Result<Vec<DbJobRunRef>, Error> {
+    sqlx::query_as!(
+        DbJobRunRef,
+        r#"
+select jr.id as "job_run_id!"
+     , jr.query_time as "query_time!"
+     , jr.event_time_minutes as "event_time_minutes!"
+  from job_run jr
+       join reset_date r on jr.reset_id = r.reset_id
+ where r.reset = ?
+ order by jr.id desc
+ limit ?
+        "#,
+        reset_date,
+        limit
+    )
+    .fetch_all(pool)
+    .await
+}
+
+/// Construction state of each jump gate of a reset as of the given job run,
+/// taken from the latest construction_log of each site at or before that job run
+#[tracing::instrument(level = "debug", skip_all)]
+pub(crate) async fn select_jump_gate_construction_states_at_job_run(
+    pool: &Pool<Sqlite>,
+    reset_date: NaiveDate,
+    job_run_id: i64,
+) -> Result<Vec<DbJumpGateConstructionState>, Error> {
+    sqlx::query_as!(
+        DbJumpGateConstructionState,
+        r#"
+select cs.jump_gate_waypoint_symbol
+     , cl.is_complete as "is_complete: bool"
+     , coalesce(sum(cml.fulfilled), 0) as "fulfilled!: i64"
+  from construction_site cs
+       join reset_date rd on cs.reset_id = rd.reset_id
+       join construction_log cl on cl.id = (select max(latest.id)
+                                              from construction_log latest
+                                             where latest.construction_site_id = cs.id
+                                               and latest.job_id <= ?)
+       left join construction_material_log cml on cml.construction_log_id = cl.id
+ where rd.reset = ?
+ group by cs.jump_gate_waypoint_symbol
+        , cl.is_complete
+        "#,
+        job_run_id,
+        reset_date
+    )
+    .fetch_all(pool)
+    .await
+}
+
+/// Returns `None` if the event has already been logged for this webhook
+#[tracing::instrument(level = "debug", skip_all)]
+pub(crate) async fn insert_webhook_delivery(
+    pool: &Pool<Sqlite>,
+    webhook_name: &str,
+    event_key: &str,
+    event_kind: &str,
+    payload: &str,
+    created_at: NaiveDateTime,
+) -> Result<Option<i64>, Error> {
+    let result = sqlx::query!(
+        "
+insert into webhook_delivery (webhook_name, event_key, event_kind, payload, status, attempts, created_at, updated_at)
+values (?, ?, ?, ?, 'pending', 0, ?, ?)
+on conflict (webhook_name, event_key) do nothing
+        ",
+        webhook_name,
+        event_key,
+        event_kind,
+        payload,
+        created_at,
+        created_at
+    )
+    .execute(pool)
+    .await?;
+
+    Ok((result.rows_affected() > 0).then(|| result.last_insert_rowid()))
+}
+
+#[tracing::instrument(level = "debug", skip_all)]
+pub(crate) async fn update_webhook_delivery(
+    pool: &Pool<Sqlite>,
+    id: i64,
+    status: &str,
+    attempts: i64,
+    last_http_status: Option<i64>,
+    last_error: Option<&str>,
+    updated_at: NaiveDateTime,
+) -> Result<(), Error> {
+    sqlx::query!(
+        "
+update webhook_delivery
+   set status           = ?
+     , attempts         = ?
+     , last_http_status = ?
+     , last_error       = ?
+     , updated_at       = ?
+ where id = ?
+        ",
+        status,
+        attempts,
+        last_http_status,
+        last_error,
+        updated_at,
+        id
+    )
+    .execute(pool)
+    .await?;
     Ok(())
 }
 
+/// Deliveries that got interrupted - e.g. by a restart of the server - in the order they were logged
+#[tracing::instrument(level = "debug", skip_all)]
+pub(crate) async fn select_pending_webhook_deliveries(
+    pool: &Pool<Sqlite>,
+    webhook_name: &str,
+) -> Result<Vec<DbPendingWebhookDelivery>, Error> {
+    sqlx::query_as!(
+        DbPendingWebhookDelivery,
+        "
+select id
+     , event_key
+     , payload
+  from webhook_delivery
+ where webhook_name = ?
+   and status = 'pending'
+ order by id
+        ",
+        webhook_name
+    )
+    .fetch_all(pool)
+    .await
+}
+
 #[tracing::instrument(level = "debug")]
 pub(crate) async fn refresh_fake_materialized_view(pool: &Pool<Sqlite>) -> anyhow::Result<()> {
     // NOTE: THIS SCRIPTS IS _NOT_ BEING CHECKED BY SQLX AT COMPILE-TIME
     // sqlx can't handle temporary tables that are being created inside this sql script
     const SQL: &str = include_str!("queries/update_materialized_views.sql");
 
+    let start = Instant::now();
     let mut transaction = pool.begin().await?;
 
-    sqlx::query(SQL).execute(pool).await?;
+    sqlx::query(SQL).execute(&mut *transaction).await?;
 
     transaction.commit().await?;
+    record_materialized_view_refresh(start.elapsed());
 
     Ok(())
 }
 
 #[derive(Serialize, Deserialize, Debug, Clone, Copy)]
 pub(crate) struct ResetDate {
-    reset_id: i64,
+    pub(crate) reset_id: i64,
     pub reset: NaiveDate,
     pub first_ts: NaiveDateTime,
     pub latest_ts: NaiveDateTime,
     pub is_ongoing: bool,
 }
 
+#[derive(Debug, Clone, Copy)]
+pub(crate) struct DbJobRunRef {
+    pub(crate) job_run_id: i64,
+    pub(crate) query_time: NaiveDateTime,
+    pub(crate) event_time_minutes: i64,
+}
+
+#[derive(Debug, Clone, Copy)]
+pub(crate) struct DbLatestJobRun {
+    pub(crate) job_run_id: i64,
+    pub(crate) query_time: NaiveDateTime,
+    pub(crate) is_ongoing: bool,
+}
+
+#[derive(Debug, Clone, Copy)]
+pub(crate) struct DbDataGeneration {
+    pub(crate) generation: i64,
+    pub(crate) updated_at: NaiveDateTime,
+}
+
 #[derive(Serialize, Deserialize, Debug, Clone)]
 pub(crate) struct LeaderboardEntry {
     pub agent_symbol: String,
@@ -789,6 +2207,22 @@ pub(crate) struct LeaderboardEntry {
     pub ship_count: i64,
     pub agent_headquarters_waypoint_symbol: String,
     pub jump_gate_waypoint_symbol: String,
+    pub starting_faction: String,
+}
+
+#[derive(Debug, Clone)]
+pub(crate) struct DbPendingWebhookDelivery {
+    pub(crate) id: i64,
+    pub(crate) event_key: String,
+    pub(crate) payload: String,
+}
+
+#[derive(Debug, Clone)]
+pub(crate) struct DbJumpGateConstructionState {
+    pub(crate) jump_gate_waypoint_symbol: String,
+    pub(crate) is_complete: bool,
+    /// sum of the fulfilled materials
+    pub(crate) fulfilled: i64,
 }
 
 #[derive(Serialize, Deserialize, Debug, Clone, sqlx::FromRow)]
@@ -853,6 +2287,11 @@ pub(crate) struct DbAgentHistoryEntry {
     pub(crate) ship_count_timeline: Option<sqlx::types::Json<Vec<u32>>>,
 }
 
+#[derive(Debug, Clone)]
+pub(crate) struct DbIntegrityViolation {
+    pub(crate) details: String,
+}
+
 #[derive(Serialize, Deserialize, Debug, Clone)]
 pub(crate) struct DbConstructionLog {
     id: i64,
@@ -873,8 +2312,9 @@ pub(crate) struct DbJobRun {
 pub(crate) struct DbStaticAgentInfo {
     id: i64,
     pub(crate) agent_symbol: String,
+    pub(crate) agent_headquarters_waypoint_symbol: String,
     construction_site_id: i64,
-    starting_faction: String,
+    pub(crate) starting_faction: String,
     reset_id: i64,
     query_time: NaiveDateTime,
 }
@@ -891,6 +2331,7 @@ pub struct DbConstructionRequirement {
 pub(crate) struct DbAllTimePerformanceEntry {
     pub(crate) reset: NaiveDate,
     pub(crate) agent_symbol: String,
+    pub(crate) starting_faction: String,
     pub(crate) credits: i64,
     pub(crate) rank: i64,
 }
@@ -909,3 +2350,138 @@ pub(crate) struct DbConstructionLeaderboardEntry {
     pub(crate) rank_start_fortnight_start_jump_gate_construction: i64,
     pub(crate) rank_start_fortnight_finish_jump_gate_construction: i64,
 }
+
+#[cfg(test)]
+mod tests {
+    use sqlx::Executor;
+
+    use super::*;
+    use crate::model::{AgentSymbol, FactionSymbol, WaypointSymbol};
+
+    fn ts(s: &str) -> NaiveDateTime {
+        NaiveDateTime::parse_from_str(s, "%Y-%m-%d %H:%M:%S").unwrap()
+    }
+
+    fn static_agent_info(headquarters: &str) -> LeaderboardStaticAgentInfo {
+        LeaderboardStaticAgentInfo {
+            symbol: AgentSymbol("FLWI".to_string()),
+            headquarters: WaypointSymbol(headquarters.to_string()),
+            starting_faction: FactionSymbol("COSMIC".to_string()),
+            jump_gate: WaypointSymbol("X1-AA-JG".to_string()),
+        }
+    }
+
+    #[tokio::test]
+    async fn changed_static_agent_infos_are_kept_in_the_history() {
+        let pool = seeded_test_pool(
+            "
+insert into reset_date (reset_id, reset, first_ts) values (1, '2024-03-24', '2024-03-24 15:00:00');
+insert into construction_site (id, reset_id, jump_gate_waypoint_symbol) values (1, 1, 'X1-AA-JG');
+insert into job_run (id, reset_id, query_time, event_time_minutes) values (1, 1, '2024-03-24 16:00:00', 60);
+",
+        )
+        .await;
+        let now = ts("2024-03-24 16:00:00");
+        let reset_date =
+            load_or_create_reset_date(&pool, NaiveDate::from_ymd_opt(2024, 3, 24).unwrap(), now)
+                .await
+                .unwrap();
+        let construction_sites = select_construction_sites_for_reset(&pool, reset_date)
+            .await
+            .unwrap();
+
+        for headquarters in ["X1-AA-A1", "X1-AA-A2", "X1-AA-A2"] {
+            save_static_agent_infos(
+                &pool,
+                reset_date,
+                vec![static_agent_info(headquarters)],
+                construction_sites.clone(),
+                now,
+            )
+            .await
+            .unwrap();
+        }
+
+        let static_agent_infos = select_static_agent_infos_for_reset(&pool, reset_date)
+            .await
+            .unwrap();
+        assert_eq!(static_agent_infos.len(), 1);
+        assert_eq!(
+            static_agent_infos[0].agent_headquarters_waypoint_symbol,
+            "X1-AA-A2"
+        );
+
+        // saving unchanged infos doesn't add another entry
+        let history: Vec<(i64, String)> = sqlx::query_as(
+            "select static_agent_info_id, agent_headquarters_waypoint_symbol from static_agent_info_history",
+        )
+        .fetch_all(&pool)
+        .await
+        .unwrap();
+        assert_eq!(
+            history,
+            vec![(static_agent_infos[0].id, "X1-AA-A1".to_string())]
+        );
+    }
+
+    #[tokio::test]
+    async fn migration_merges_duplicate_static_agent_infos() {
+        let pool = SqlitePoolOptions::new()
+            .max_connections(1)
+            .connect("sqlite::memory:")
+            .await
+            .unwrap();
+
+        // schema as of before the merge
+        let mut migrator = sqlx::migrate!();
+        migrator.migrations = migrator
+            .migrations
+            .iter()
+            .filter(|migration| migration.version < 20261019085900)
+            .cloned()
+            .collect::<Vec<_>>()
+            .into();
+        migrator.run(&pool).await.unwrap();
+
+        pool.execute(
+            "
+insert into reset_date (reset_id, reset, first_ts) values (1, '2024-03-24', '2024-03-24 15:00:00');
+insert into construction_site (id, reset_id, jump_gate_waypoint_symbol) values (1, 1, 'X1-AA-JG');
+insert into static_agent_info (id, agent_symbol, agent_headquarters_waypoint_symbol, construction_site_id, starting_faction, reset_id, query_time)
+values (1, 'FLWI', 'X1-AA-A1', 1, 'COSMIC', 1, '2024-03-24 15:00:00'),
+       (2, 'FLWI', 'X1-AA-A1', 1, 'COSMIC', 1, '2024-03-24 15:05:00'),
+       (3, 'WHYANDO', 'X1-AA-A1', 1, 'COSMIC', 1, '2024-03-24 15:00:00');
+insert into job_run (id, reset_id, query_time, event_time_minutes)
+values (1, 1, '2024-03-24 16:00:00', 60), (2, 1, '2024-03-24 16:05:00', 65);
+-- job run 1 has entries of both duplicates, job run 2 only of the newer one
+insert into agent_log (agent_id, job_id, credits, ship_count)
+values (1, 1, 175000, 2), (2, 1, 175000, 2), (2, 2, 180000, 2), (3, 1, 250000, 3);
+",
+        )
+        .await
+        .unwrap();
+
+        sqlx::migrate!().run(&pool).await.unwrap();
+
+        let static_agent_infos: Vec<(i64, String)> =
+            sqlx::query_as("select id, agent_symbol from static_agent_info order by id")
+                .fetch_all(&pool)
+                .await
+                .unwrap();
+        assert_eq!(
+            static_agent_infos,
+            vec![(1, "FLWI".to_string()), (3, "WHYANDO".to_string())]
+        );
+
+        let agent_logs: Vec<(i64, i64, i64)> = sqlx::query_as(
+            "select agent_id, job_id, credits from agent_log order by job_id, agent_id",
+        )
+        .fetch_all(&pool)
+        .await
+        .unwrap();
+        assert_eq!(
+            agent_logs,
+            vec![(1, 1, 175000), (3, 1, 250000), (1, 2, 180000)]
+        );
+    }
+}
@@ -0,0 +1,597 @@
+use std::collections::HashMap;
+use std::sync::Arc;
+
+use anyhow::{anyhow, Context as _};
+use async_trait::async_trait;
+use chrono::{NaiveDate, NaiveDateTime};
+use itertools::Itertools;
+use serenity::all::{
+    ChannelId, Client, Command, CommandInteraction, CommandOptionType, Context, CreateCommand,
+    CreateCommandOption, CreateInteractionResponse, CreateInteractionResponseMessage,
+    EditInteractionResponse, EventHandler, GatewayIntents, Http, Interaction, Ready, ResolvedValue,
+};
+use sqlx::{Pool, Sqlite};
+use tokio::sync::broadcast;
+use tracing::{event, Level};
+
+use crate::db::{
+    load_leaderboard_for_reset, load_reset_dates, select_jump_gate_agent_assignment_for_reset,
+    select_jump_gate_construction_event_overview_for_reset,
+    select_most_recent_construction_progress_for_reset,
+};
+use crate::leaderboard_collector::TickCompleted;
+use crate::leaderboard_events::{detect_events, DetectedEvent};
+
+/// limit of the content of a discord message
+const MAX_MESSAGE_LENGTH: usize = 2000;
+const LEADERBOARD_SIZE: usize = 10;
+
+#[derive(Debug, Clone)]
+pub(crate) struct DiscordBotSettings {
+    pub(crate) token: String,
+    /// channel for the announcements after each tick. Without it the bot only answers commands.
+    pub(crate) announcement_channel_id: Option<u64>,
+}
+
+/// Slash commands of the bot - independent of the discord library
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub(crate) enum BotCommand {
+    Leaderboard,
+    Agent { agent_symbol: String },
+    Gate { jump_gate_waypoint_symbol: String },
+    Eta { jump_gate_waypoint_symbol: String },
+}
+
+impl BotCommand {
+    /// `options` are the string options of the slash command by name
+    pub(crate) fn parse(name: &str, options: &HashMap<&str, &str>) -> Option<Self> {
+        let symbol = |option: &str| options.get(option).map(|s| s.trim().to_uppercase());
+        match name {
+            "leaderboard" => Some(BotCommand::Leaderboard),
+            "agent" => symbol("symbol").map(|agent_symbol| BotCommand::Agent { agent_symbol }),
+            "gate" => symbol("waypoint").map(|jump_gate_waypoint_symbol| BotCommand::Gate {
+                jump_gate_waypoint_symbol,
+            }),
+            "eta" => symbol("waypoint").map(|jump_gate_waypoint_symbol| BotCommand::Eta {
+                jump_gate_waypoint_symbol,
+            }),
+            _ => None,
+        }
+    }
+
+    fn definitions() -> Vec<CreateCommand> {
+        let waypoint_option = || {
+            CreateCommandOption::new(
+                CommandOptionType::String,
+                "waypoint",
+                "symbol of the jump gate, e.g. X1-AB12-I56",
+            )
+            .required(true)
+        };
+        vec![
+            CreateCommand::new("leaderboard")
+                .description("Top agents by credits of the ongoing reset"),
+            CreateCommand::new("agent")
+                .description("Credits, ships and rank of an agent")
+                .add_option(
+                    CreateCommandOption::new(CommandOptionType::String, "symbol", "agent symbol")
+                        .required(true),
+                ),
+            CreateCommand::new("gate")
+                .description("Construction progress of a jump gate")
+                .add_option(waypoint_option()),
+            CreateCommand::new("eta")
+                .description("Estimated completion of a jump gate at the current delivery rate")
+                .add_option(waypoint_option()),
+        ]
+    }
+}
+
+/// What the bot needs from discord besides answering commands. Mocked in the tests.
+#[async_trait]
+pub(crate) trait DiscordGateway: Send + Sync {
+    async fn post_message(&self, channel_id: u64, content: &str) -> anyhow::Result<()>;
+}
+
+struct SerenityGateway {
+    http: Arc<Http>,
+}
+
+#[async_trait]
+impl DiscordGateway for SerenityGateway {
+    async fn post_message(&self, channel_id: u64, content: &str) -> anyhow::Result<()> {
+        ChannelId::new(channel_id).say(&self.http, content).await?;
+        Ok(())
+    }
+}
+
+/// Answers the slash commands with the queries of the http api and announces the events of each tick.
+#[derive(Clone)]
+pub(crate) struct DiscordBot {
+    pool: Pool<Sqlite>,
+}
+
+impl DiscordBot {
+    pub(crate) fn new(pool: Pool<Sqlite>) -> Self {
+        Self { pool }
+    }
+
+    /// Always returns a message - errors are reported to the user
+    pub(crate) async fn answer(&self, command: &BotCommand) -> String {
+        let answer = match command {
+            BotCommand::Leaderboard => self.leaderboard().await,
+            BotCommand::Agent { agent_symbol } => self.agent(agent_symbol).await,
+            BotCommand::Gate {
+                jump_gate_waypoint_symbol,
+            } => self.gate(jump_gate_waypoint_symbol).await,
+            BotCommand::Eta {
+                jump_gate_waypoint_symbol,
+            } => self.eta(jump_gate_waypoint_symbol).await,
+        };
+        match answer {
+            Ok(message) => truncate_message(message),
+            Err(err) => {
+                event!(
+                    Level::ERROR,
+                    ?command,
+                    "Error answering discord command: {err:?}"
+                );
+                "Sorry, something went wrong.".to_string()
+            }
+        }
+    }
+
+    /// Posts the events of each tick to the channel
+    pub(crate) async fn announce_on_tick(
+        &self,
+        gateway: &impl DiscordGateway,
+        channel_id: u64,
+        mut receiver: broadcast::Receiver<TickCompleted>,
+    ) {
+        loop {
+            let tick = match receiver.recv().await {
+                Ok(tick) => tick,
+                Err(broadcast::error::RecvError::Lagged(_)) => continue,
+                Err(broadcast::error::RecvError::Closed) => return,
+            };
+
+            match detect_events(&self.pool, tick).await {
+                Ok(events) => {
+                    if let Err(err) = announce_events(gateway, channel_id, &events).await {
+                        event!(Level::ERROR, "Error posting discord announcement: {err:?}");
+                    }
+                }
+                Err(err) => {
+                    event!(Level::ERROR, reset = %tick.reset_date, "Error detecting leaderboard events: {err:?}")
+                }
+            }
+        }
+    }
+
+    async fn ongoing_reset(&self) -> anyhow::Result<NaiveDate> {
+        load_reset_dates(&self.pool)
+            .await?
+            .into_iter()
+            .find(|reset_date| reset_date.is_ongoing)
+            .map(|reset_date| reset_date.reset)
+            .ok_or_else(|| anyhow!("no reset with data"))
+    }
+
+    async fn leaderboard(&self) -> anyhow::Result<String> {
+        let reset_date = self.ongoing_reset().await?;
+        let entries = load_leaderboard_for_reset(&self.pool, reset_date).await?;
+        if entries.is_empty() {
+            return Ok(format!("No agents in reset {reset_date} yet."));
+        }
+
+        let lines = entries
+            .iter()
+            .take(LEADERBOARD_SIZE)
+            .enumerate()
+            .map(|(idx, entry)| {
+                format!(
+                    "{}. **{}** - {} credits, {} ships",
+                    idx + 1,
+                    entry.agent_symbol,
+                    entry.credits,
+                    entry.ship_count
+                )
+            })
+            .join("\n");
+        Ok(format!("**Leaderboard of reset {reset_date}**\n{lines}"))
+    }
+
+    async fn agent(&self, agent_symbol: &str) -> anyhow::Result<String> {
+        let reset_date = self.ongoing_reset().await?;
+        let entries = load_leaderboard_for_reset(&self.pool, reset_date).await?;
+        let Some((idx, entry)) = entries
+            .iter()
+            .find_position(|entry| entry.agent_symbol == agent_symbol)
+        else {
+            return Ok(format!(
+                "Agent {agent_symbol} is not on the leaderboard of reset {reset_date}."
+            ));
+        };
+
+        Ok(format!(
+            "**{}** (#{} of reset {reset_date})\nCredits: {}\nShips: {}\nFaction: {}\nHeadquarters: {}\nJump gate: {}",
+            entry.agent_symbol,
+            idx + 1,
+            entry.credits,
+            entry.ship_count,
+            entry.starting_faction,
+            entry.agent_headquarters_waypoint_symbol,
+            entry.jump_gate_waypoint_symbol
+        ))
+    }
+
+    async fn gate(&self, jump_gate_waypoint_symbol: &str) -> anyhow::Result<String> {
+        let reset_date = self.ongoing_reset().await?;
+        let materials: Vec<_> =
+            select_most_recent_construction_progress_for_reset(&self.pool, reset_date)
+                .await?
+                .into_iter()
+                .filter(|material| material.jump_gate_waypoint_symbol == jump_gate_waypoint_symbol)
+                .collect();
+        if materials.is_empty() {
+            return Ok(format!(
+                "No construction data for jump gate {jump_gate_waypoint_symbol} in reset {reset_date}."
+            ));
+        }
+
+        let agents = select_jump_gate_agent_assignment_for_reset(&self.pool, reset_date)
+            .await?
+            .into_iter()
+            .filter(|entry| entry.jump_gate_waypoint_symbol == jump_gate_waypoint_symbol)
+            .map(|entry| entry.agents_in_system_csv.replace(',', ", "))
+            .join(", ");
+        let is_complete = materials.iter().any(|m| m.is_jump_gate_complete);
+        let material_lines = materials
+            .iter()
+            .map(|m| format!("{}: {}/{}", m.trade_symbol, m.fulfilled, m.required))
+            .join("\n");
+
+        Ok(format!(
+            "**Jump gate {jump_gate_waypoint_symbol}**{}\nAgents: {agents}\n{material_lines}",
+            if is_complete { " (complete)" } else { "" }
+        ))
+    }
+
+    async fn eta(&self, jump_gate_waypoint_symbol: &str) -> anyhow::Result<String> {
+        let reset_date = self.ongoing_reset().await?;
+        let materials: Vec<_> =
+            select_most_recent_construction_progress_for_reset(&self.pool, reset_date)
+                .await?
+                .into_iter()
+                .filter(|material| material.jump_gate_waypoint_symbol == jump_gate_waypoint_symbol)
+                .collect();
+        let maybe_construction_start =
+            select_jump_gate_construction_event_overview_for_reset(&self.pool, reset_date)
+                .await?
+                .into_iter()
+                .filter(|entry| entry.jump_gate_waypoint_symbol == jump_gate_waypoint_symbol)
+                .map(|entry| entry.ts_first_construction_event)
+                .min();
+
+        let fulfilled: i64 = materials.iter().map(|m| m.fulfilled).sum();
+        let required: i64 = materials.iter().map(|m| m.required).sum();
+        let maybe_latest_ts = materials
+            .iter()
+            .filter_map(|m| m.ts_latest_entry_of_reset)
+            .max();
+
+        let message = if materials.iter().any(|m| m.is_jump_gate_complete) {
+            format!("Jump gate {jump_gate_waypoint_symbol} is already complete.")
+        } else {
+            match (maybe_construction_start, maybe_latest_ts) {
+                (Some(start), Some(latest_ts)) => {
+                    match estimate_completion(start, latest_ts, fulfilled, required) {
+                        Some(eta) => format!(
+                            "Jump gate {jump_gate_waypoint_symbol} is {fulfilled}/{required} done. At the current rate it completes around {} UTC.",
+                            eta.format("%Y-%m-%d %H:%M")
+                        ),
+                        None => format!(
+                            "Jump gate {jump_gate_waypoint_symbol} is {fulfilled}/{required} done - not enough progress for an estimate yet."
+                        ),
+                    }
+                }
+                _ => format!(
+                    "Construction of jump gate {jump_gate_waypoint_symbol} hasn't started yet."
+                ),
+            }
+        };
+        Ok(message)
+    }
+}
+
+/// Linear extrapolation of the delivery rate since the first delivery
+fn estimate_completion(
+    construction_start: NaiveDateTime,
+    latest_ts: NaiveDateTime,
+    fulfilled: i64,
+    required: i64,
+) -> Option<NaiveDateTime> {
+    let elapsed_seconds = (latest_ts - construction_start).num_seconds();
+    if fulfilled <= 0 || elapsed_seconds <= 0 || required <= fulfilled {
+        return None;
+    }
+    let remaining_seconds =
+        elapsed_seconds as f64 * (required - fulfilled) as f64 / fulfilled as f64;
+    Some(latest_ts + chrono::Duration::seconds(remaining_seconds.round() as i64))
+}
+
+/// One message per tick - nothing is posted for ticks without events
+async fn announce_events(
+    gateway: &impl DiscordGateway,
+    channel_id: u64,
+    events: &[DetectedEvent],
+) -> anyhow::Result<()> {
+    let Some(first) = events.first() else {
+        return Ok(());
+    };
+    let lines = events
+        .iter()
+        .map(|detected| format!("- {}", detected.event.message()))
+        .join("\n");
+    let content = format!(
+        "**Tick {} UTC**\n{lines}",
+        first.query_time.format("%Y-%m-%d %H:%M")
+    );
+    gateway
+        .post_message(channel_id, &truncate_message(content))
+        .await
+}
+
+fn truncate_message(message: String) -> String {
+    if message.chars().count() <= MAX_MESSAGE_LENGTH {
+        return message;
+    }
+    let mut truncated: String = message.chars().take(MAX_MESSAGE_LENGTH - 1).collect();
+    truncated.push('…');
+    truncated
+}
+
+struct Handler {
+    bot: DiscordBot,
+}
+
+#[async_trait]
+impl EventHandler for Handler {
+    async fn ready(&self, ctx: Context, ready: Ready) {
+        event!(Level::INFO, "Discord bot connected as {}", ready.user.name);
+        if let Err(err) = Command::set_global_commands(&ctx.http, BotCommand::definitions()).await {
+            event!(Level::ERROR, "Error registering discord commands: {err:?}");
+        }
+    }
+
+    async fn interaction_create(&self, ctx: Context, interaction: Interaction) {
+        if let Interaction::Command(command) = interaction {
+            if let Err(err) = self.respond(&ctx, &command).await {
+                event!(Level::ERROR, "Error responding to discord command: {err:?}");
+            }
+        }
+    }
+}
+
+impl Handler {
+    async fn respond(&self, ctx: &Context, command: &CommandInteraction) -> anyhow::Result<()> {
+        let resolved_options = command.data.options();
+        let options: HashMap<&str, &str> = resolved_options
+            .iter()
+            .filter_map(|option| match option.value {
+                ResolvedValue::String(value) => Some((option.name, value)),
+                _ => None,
+            })
+            .collect();
+
+        let Some(bot_command) = BotCommand::parse(&command.data.name, &options) else {
+            let response = CreateInteractionResponseMessage::new()
+                .content("Unknown command")
+                .ephemeral(true);
+            command
+                .create_response(&ctx.http, CreateInteractionResponse::Message(response))
+                .await?;
+            return Ok(());
+        };
+
+        // the queries might take longer than the 3s discord waits for a response
+        command
+            .create_response(
+                &ctx.http,
+                CreateInteractionResponse::Defer(CreateInteractionResponseMessage::new()),
+            )
+            .await?;
+        let answer = self.bot.answer(&bot_command).await;
+        command
+            .edit_response(&ctx.http, EditInteractionResponse::new().content(answer))
+            .await?;
+        Ok(())
+    }
+}
+
+/// Connects to the discord gateway if a token is configured. Slash commands only need no privileged intents.
+pub(crate) async fn run_discord_bot(
+    maybe_settings: Option<DiscordBotSettings>,
+    pool: Pool<Sqlite>,
+    receiver: broadcast::Receiver<TickCompleted>,
+) -> anyhow::Result<()> {
+    let Some(settings) = maybe_settings else {
+        return Ok(());
+    };
+
+    let bot = DiscordBot::new(pool);
+    let mut client = Client::builder(&settings.token, GatewayIntents::empty())
+        .event_handler(Handler { bot: bot.clone() })
+        .await
+        .context("failed to create the discord client")?;
+    let gateway = SerenityGateway {
+        http: client.http.clone(),
+    };
+
+    let announcements = async {
+        if let Some(channel_id) = settings.announcement_channel_id {
+            bot.announce_on_tick(&gateway, channel_id, receiver).await;
+        }
+        anyhow::Ok(())
+    };
+    // returns as soon as the client fails - the announcements never do
+    tokio::try_join!(
+        async { client.start().await.context("discord bot stopped") },
+        announcements
+    )?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::Mutex;
+
+    use super::*;
+    use crate::db::seeded_test_pool;
+    use crate::leaderboard_events::LeaderboardEvent;
+
+    #[derive(Default)]
+    struct MockGateway {
+        messages: Mutex<Vec<(u64, String)>>,
+    }
+
+    #[async_trait]
+    impl DiscordGateway for MockGateway {
+        async fn post_message(&self, channel_id: u64, content: &str) -> anyhow::Result<()> {
+            self.messages
+                .lock()
+                .unwrap()
+                .push((channel_id, content.to_string()));
+            Ok(())
+        }
+    }
+
+    fn ts(s: &str) -> NaiveDateTime {
+        NaiveDateTime::parse_from_str(s, "%Y-%m-%d %H:%M:%S").unwrap()
+    }
+
+    /// in-memory db with one job run of reset 2024-03-24 and two agents
+    async fn seeded_pool() -> Pool<Sqlite> {
+        seeded_test_pool(
+            "
+insert into reset_date (reset_id, reset, first_ts) values (1, '2024-03-24', '2024-03-24 15:00:00');
+insert into construction_site (id, reset_id, jump_gate_waypoint_symbol) values (1, 1, 'X1-AA-JG');
+insert into static_agent_info (id, agent_symbol, agent_headquarters_waypoint_symbol, construction_site_id, starting_faction, reset_id, query_time)
+values (1, 'FLWI', 'X1-AA-A1', 1, 'COSMIC', 1, '2024-03-24 15:00:00'),
+       (2, 'WHYANDO', 'X1-AA-A1', 1, 'COSMIC', 1, '2024-03-24 15:00:00');
+insert into job_run (id, reset_id, query_time, event_time_minutes) values (1, 1, '2024-03-24 16:00:00', 60);
+insert into agent_log (agent_id, job_id, credits, ship_count) values (1, 1, 175000, 2), (2, 1, 250000, 3);
+",
+        )
+        .await
+    }
+
+    #[test]
+    fn parse_commands() {
+        let options = HashMap::from([("symbol", " flwi "), ("waypoint", "x1-aa-jg")]);
+
+        assert_eq!(
+            BotCommand::parse("leaderboard", &HashMap::new()),
+            Some(BotCommand::Leaderboard)
+        );
+        assert_eq!(
+            BotCommand::parse("agent", &options),
+            Some(BotCommand::Agent {
+                agent_symbol: "FLWI".to_string()
+            })
+        );
+        assert_eq!(
+            BotCommand::parse("eta", &options),
+            Some(BotCommand::Eta {
+                jump_gate_waypoint_symbol: "X1-AA-JG".to_string()
+            })
+        );
+        assert_eq!(BotCommand::parse("agent", &HashMap::new()), None);
+        assert_eq!(BotCommand::parse("unknown", &options), None);
+    }
+
+    #[tokio::test]
+    async fn answer_leaderboard_and_agent_commands() {
+        let bot = DiscordBot::new(seeded_pool().await);
+
+        let leaderboard = bot.answer(&BotCommand::Leaderboard).await;
+        assert_eq!(
+            leaderboard,
+            "**Leaderboard of reset 2024-03-24**\n1. **WHYANDO** - 250000 credits, 3 ships\n2. **FLWI** - 175000 credits, 2 ships"
+        );
+
+        let agent = bot
+            .answer(&BotCommand::Agent {
+                agent_symbol: "FLWI".to_string(),
+            })
+            .await;
+        assert!(agent.starts_with("**FLWI** (#2 of reset 2024-03-24)\nCredits: 175000"));
+
+        let unknown_agent = bot
+            .answer(&BotCommand::Agent {
+                agent_symbol: "NOBODY".to_string(),
+            })
+            .await;
+        assert_eq!(
+            unknown_agent,
+            "Agent NOBODY is not on the leaderboard of reset 2024-03-24."
+        );
+    }
+
+    #[tokio::test]
+    async fn announce_events_of_a_tick_in_one_message() {
+        let gateway = MockGateway::default();
+        let reset_date = NaiveDate::from_ymd_opt(2024, 3, 24).unwrap();
+        let events = vec![
+            DetectedEvent {
+                key: "2024-03-24:jump-gate-completed:X1-AA-JG".to_string(),
+                query_time: ts("2024-03-30 12:05:00"),
+                event: LeaderboardEvent::JumpGateConstructionCompleted {
+                    reset_date,
+                    jump_gate_waypoint_symbol: "X1-AA-JG".to_string(),
+                    agent_symbols: vec!["FLWI".to_string()],
+                },
+            },
+            DetectedEvent {
+                key: "2024-03-24:42:new-credits-leader:FLWI".to_string(),
+                query_time: ts("2024-03-30 12:05:00"),
+                event: LeaderboardEvent::NewCreditsLeader {
+                    reset_date,
+                    agent_symbol: "FLWI".to_string(),
+                    credits: 300000,
+                    previous_leader_agent_symbol: "WHYANDO".to_string(),
+                },
+            },
+        ];
+
+        announce_events(&gateway, 42, &events).await.unwrap();
+        announce_events(&gateway, 42, &[]).await.unwrap();
+
+        let messages = gateway.messages.lock().unwrap();
+        assert_eq!(
+            *messages,
+            vec![(
+                42,
+                "**Tick 2024-03-30 12:05 UTC**\n- Jump gate X1-AA-JG is complete (FLWI)\n- FLWI took the lead from WHYANDO with 300000 credits".to_string()
+            )]
+        );
+    }
+
+    #[test]
+    fn estimate_completion_extrapolates_the_delivery_rate() {
+        // a quarter done after 6 hours - three times as long for the rest
+        assert_eq!(
+            estimate_completion(
+                ts("2024-03-25 00:00:00"),
+                ts("2024-03-25 06:00:00"),
+                25,
+                100
+            ),
+            Some(ts("2024-03-26 00:00:00"))
+        );
+        assert_eq!(
+            estimate_completion(ts("2024-03-25 00:00:00"), ts("2024-03-25 06:00:00"), 0, 100),
+            None
+        );
+    }
+}
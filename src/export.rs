@@ -0,0 +1,727 @@
+use std::fs;
+use std::io::{Cursor, Read, Write};
+use std::path::Path;
+use std::sync::Arc;
+
+use anyhow::{anyhow, Context, Result};
+use arrow_array::cast::AsArray;
+use arrow_array::types::{Int64Type, TimestampMillisecondType};
+use arrow_array::{
+    ArrayRef, BooleanArray, Int64Array, RecordBatch, StringArray, TimestampMillisecondArray,
+};
+use arrow_schema::{DataType, Field, Schema, TimeUnit};
+use chrono::{DateTime, NaiveDate, NaiveDateTime, Utc};
+use parquet::arrow::arrow_reader::ParquetRecordBatchReaderBuilder;
+use parquet::arrow::ArrowWriter;
+use serde::de::DeserializeOwned;
+use serde::{Deserialize, Serialize};
+use sqlx::{Pool, Sqlite};
+use utoipa::ToSchema;
+use zip::write::SimpleFileOptions;
+use zip::{ZipArchive, ZipWriter};
+
+use crate::db::{
+    load_reset_date, select_export_agent_logs, select_export_agents,
+    select_export_construction_material_logs, select_export_construction_requirements,
+    select_export_construction_sites, select_export_job_runs,
+};
+
+/// Bump this if the layout of the exported tables changes in an incompatible way.
+pub(crate) const EXPORT_FORMAT_VERSION: u32 = 1;
+pub(crate) const MANIFEST_FILE_NAME: &str = "manifest.json";
+
+#[derive(Serialize, Deserialize, ToSchema, clap::ValueEnum, Debug, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+pub(crate) enum ExportFormat {
+    Csv,
+    Jsonl,
+    Parquet,
+}
+
+impl ExportFormat {
+    pub(crate) fn file_extension(&self) -> &'static str {
+        match self {
+            ExportFormat::Csv => "csv",
+            ExportFormat::Jsonl => "jsonl",
+            ExportFormat::Parquet => "parquet",
+        }
+    }
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub(crate) enum ExportDataType {
+    Int64,
+    Utf8,
+    Boolean,
+    /// naive UTC timestamp. ISO-8601 in csv and jsonl, milliseconds since epoch in parquet
+    Timestamp,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub(crate) struct ExportColumn {
+    pub(crate) name: String,
+    pub(crate) data_type: ExportDataType,
+    pub(crate) description: String,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub(crate) struct ExportTableManifest {
+    pub(crate) name: String,
+    pub(crate) file_name: String,
+    pub(crate) row_count: usize,
+    pub(crate) columns: Vec<ExportColumn>,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub(crate) struct ExportManifest {
+    pub(crate) format_version: u32,
+    pub(crate) format: ExportFormat,
+    pub(crate) reset_date: NaiveDate,
+    pub(crate) ts_start_of_reset: NaiveDateTime,
+    pub(crate) ts_latest_entry_of_reset: NaiveDateTime,
+    pub(crate) exported_at: NaiveDateTime,
+    pub(crate) tables: Vec<ExportTableManifest>,
+}
+
+/// A table of the export bundle.
+/// The column list is written to the manifest and defines the parquet schema.
+pub(crate) trait ExportTable: Serialize + DeserializeOwned {
+    const TABLE_NAME: &'static str;
+    fn columns() -> Vec<ExportColumn>;
+}
+
+fn column(name: &str, data_type: ExportDataType, description: &str) -> ExportColumn {
+    ExportColumn {
+        name: name.to_string(),
+        data_type,
+        description: description.to_string(),
+    }
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub(crate) struct ExportAgent {
+    pub(crate) agent_id: i64,
+    pub(crate) agent_symbol: String,
+    pub(crate) agent_headquarters_waypoint_symbol: String,
+    pub(crate) starting_faction: String,
+    pub(crate) construction_site_id: i64,
+    pub(crate) ts_first_seen: NaiveDateTime,
+}
+
+impl ExportTable for ExportAgent {
+    const TABLE_NAME: &'static str = "agents";
+
+    fn columns() -> Vec<ExportColumn> {
+        use ExportDataType::*;
+        vec![
+            column("agent_id", Int64, "id of the agent within this export"),
+            column("agent_symbol", Utf8, "symbol of the agent"),
+            column(
+                "agent_headquarters_waypoint_symbol",
+                Utf8,
+                "waypoint of the agent's headquarters",
+            ),
+            column("starting_faction", Utf8, "faction the agent started with"),
+            column(
+                "construction_site_id",
+                Int64,
+                "jump gate in the system of the agent's headquarters \
+                 (construction_sites.construction_site_id)",
+            ),
+            column(
+                "ts_first_seen",
+                Timestamp,
+                "time the agent showed up on one of the leaderboards for the first time",
+            ),
+        ]
+    }
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub(crate) struct ExportJobRun {
+    pub(crate) job_run_id: i64,
+    pub(crate) query_time: NaiveDateTime,
+    pub(crate) event_time_minutes: i64,
+}
+
+impl ExportTable for ExportJobRun {
+    const TABLE_NAME: &'static str = "job_runs";
+
+    fn columns() -> Vec<ExportColumn> {
+        use ExportDataType::*;
+        vec![
+            column(
+                "job_run_id",
+                Int64,
+                "id of the collector run within this export",
+            ),
+            column(
+                "query_time",
+                Timestamp,
+                "time of the collector run, rounded to 5 minutes",
+            ),
+            column(
+                "event_time_minutes",
+                Int64,
+                "minutes since the start of the reset",
+            ),
+        ]
+    }
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub(crate) struct ExportAgentLog {
+    pub(crate) agent_id: i64,
+    pub(crate) job_run_id: i64,
+    pub(crate) credits: i64,
+    pub(crate) ship_count: i64,
+}
+
+impl ExportTable for ExportAgentLog {
+    const TABLE_NAME: &'static str = "agent_logs";
+
+    fn columns() -> Vec<ExportColumn> {
+        use ExportDataType::*;
+        vec![
+            column("agent_id", Int64, "agents.agent_id"),
+            column("job_run_id", Int64, "job_runs.job_run_id"),
+            column(
+                "credits",
+                Int64,
+                "credits of the agent at the time of the job run",
+            ),
+            column(
+                "ship_count",
+                Int64,
+                "number of ships of the agent at the time of the job run",
+            ),
+        ]
+    }
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub(crate) struct ExportConstructionSite {
+    pub(crate) construction_site_id: i64,
+    pub(crate) jump_gate_waypoint_symbol: String,
+}
+
+impl ExportTable for ExportConstructionSite {
+    const TABLE_NAME: &'static str = "construction_sites";
+
+    fn columns() -> Vec<ExportColumn> {
+        use ExportDataType::*;
+        vec![
+            column(
+                "construction_site_id",
+                Int64,
+                "id of the construction site within this export",
+            ),
+            column(
+                "jump_gate_waypoint_symbol",
+                Utf8,
+                "waypoint of the jump gate",
+            ),
+        ]
+    }
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub(crate) struct ExportConstructionRequirement {
+    pub(crate) construction_requirement_id: i64,
+    pub(crate) trade_symbol: String,
+    pub(crate) required: i64,
+}
+
+impl ExportTable for ExportConstructionRequirement {
+    const TABLE_NAME: &'static str = "construction_requirements";
+
+    fn columns() -> Vec<ExportColumn> {
+        use ExportDataType::*;
+        vec![
+            column(
+                "construction_requirement_id",
+                Int64,
+                "id of the requirement within this export",
+            ),
+            column(
+                "trade_symbol",
+                Utf8,
+                "material required for the jump gate construction",
+            ),
+            column(
+                "required",
+                Int64,
+                "units required to finish the construction",
+            ),
+        ]
+    }
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub(crate) struct ExportConstructionMaterialLog {
+    pub(crate) job_run_id: i64,
+    pub(crate) construction_site_id: i64,
+    pub(crate) construction_requirement_id: i64,
+    pub(crate) is_jump_gate_complete: bool,
+    pub(crate) fulfilled: i64,
+}
+
+impl ExportTable for ExportConstructionMaterialLog {
+    const TABLE_NAME: &'static str = "construction_material_logs";
+
+    fn columns() -> Vec<ExportColumn> {
+        use ExportDataType::*;
+        vec![
+            column("job_run_id", Int64, "job_runs.job_run_id"),
+            column(
+                "construction_site_id",
+                Int64,
+                "construction_sites.construction_site_id",
+            ),
+            column(
+                "construction_requirement_id",
+                Int64,
+                "construction_requirements.construction_requirement_id",
+            ),
+            column(
+                "is_jump_gate_complete",
+                Boolean,
+                "whether the jump gate was complete at the time of the job run",
+            ),
+            column(
+                "fulfilled",
+                Int64,
+                "units delivered at the time of the job run",
+            ),
+        ]
+    }
+}
+
+/// All data of one reset, decoupled from the layout of our database.
+#[derive(Debug, Clone, PartialEq)]
+pub(crate) struct ResetExport {
+    pub(crate) reset_date: NaiveDate,
+    pub(crate) ts_start_of_reset: NaiveDateTime,
+    pub(crate) ts_latest_entry_of_reset: NaiveDateTime,
+    pub(crate) agents: Vec<ExportAgent>,
+    pub(crate) job_runs: Vec<ExportJobRun>,
+    pub(crate) agent_logs: Vec<ExportAgentLog>,
+    pub(crate) construction_sites: Vec<ExportConstructionSite>,
+    pub(crate) construction_requirements: Vec<ExportConstructionRequirement>,
+    pub(crate) construction_material_logs: Vec<ExportConstructionMaterialLog>,
+}
+
+/// Loads the export of a reset. Returns None if there is no (collected) reset for that date.
+pub(crate) async fn load_reset_export(
+    pool: &Pool<Sqlite>,
+    reset_date: NaiveDate,
+) -> Result<Option<ResetExport>> {
+    let Some(reset) = load_reset_date(pool, reset_date).await? else {
+        return Ok(None);
+    };
+    let reset_id = reset.reset_id;
+
+    Ok(Some(ResetExport {
+        reset_date: reset.reset,
+        ts_start_of_reset: reset.first_ts,
+        ts_latest_entry_of_reset: reset.latest_ts,
+        agents: select_export_agents(pool, reset_id).await?,
+        job_runs: select_export_job_runs(pool, reset_id).await?,
+        agent_logs: select_export_agent_logs(pool, reset_id).await?,
+        construction_sites: select_export_construction_sites(pool, reset_id).await?,
+        construction_requirements: select_export_construction_requirements(pool, reset_id).await?,
+        construction_material_logs: select_export_construction_material_logs(pool, reset_id)
+            .await?,
+    }))
+}
+
+impl ResetExport {
+    /// Encodes all tables plus the manifest. Returns (file name, content) pairs.
+    pub(crate) fn encode(&self, format: ExportFormat) -> Result<Vec<(String, Vec<u8>)>> {
+        let tables = vec![
+            encode_table(&self.agents, format)?,
+            encode_table(&self.job_runs, format)?,
+            encode_table(&self.agent_logs, format)?,
+            encode_table(&self.construction_sites, format)?,
+            encode_table(&self.construction_requirements, format)?,
+            encode_table(&self.construction_material_logs, format)?,
+        ];
+
+        let manifest = ExportManifest {
+            format_version: EXPORT_FORMAT_VERSION,
+            format,
+            reset_date: self.reset_date,
+            ts_start_of_reset: self.ts_start_of_reset,
+            ts_latest_entry_of_reset: self.ts_latest_entry_of_reset,
+            exported_at: Utc::now().naive_utc(),
+            tables: tables
+                .iter()
+                .map(|(manifest, _)| manifest.clone())
+                .collect(),
+        };
+
+        let mut files = vec![(
+            MANIFEST_FILE_NAME.to_string(),
+            serde_json::to_vec_pretty(&manifest)?,
+        )];
+        files.extend(
+            tables
+                .into_iter()
+                .map(|(manifest, content)| (manifest.file_name, content)),
+        );
+        Ok(files)
+    }
+
+    pub(crate) fn write_to_dir(&self, format: ExportFormat, output_dir: &Path) -> Result<()> {
+        fs::create_dir_all(output_dir)
+            .with_context(|| format!("failed to create {}", output_dir.display()))?;
+
+        for (file_name, content) in self.encode(format)? {
+            fs::write(output_dir.join(&file_name), content)
+                .with_context(|| format!("failed to write {file_name}"))?;
+        }
+        Ok(())
+    }
+
+    pub(crate) fn to_zip(&self, format: ExportFormat) -> Result<Vec<u8>> {
+        let mut zip = ZipWriter::new(Cursor::new(Vec::new()));
+        let options = SimpleFileOptions::default();
+
+        for (file_name, content) in self.encode(format)? {
+            zip.start_file(file_name, options)?;
+            zip.write_all(&content)?;
+        }
+
+        Ok(zip.finish()?.into_inner())
+    }
+
+    /// Reads an export bundle, either an extracted directory or the zip archive served by the api.
+    pub(crate) fn read_bundle(path: &Path) -> Result<ResetExport> {
+        if path.is_dir() {
+            Self::decode(|file_name| {
+                fs::read(path.join(file_name))
+                    .with_context(|| format!("failed to read {file_name}"))
+            })
+        } else {
+            let mut zip = ZipArchive::new(fs::File::open(path)?)
+                .with_context(|| format!("{} is not a zip archive", path.display()))?;
+            Self::decode(|file_name| {
+                let mut content = vec![];
+                zip.by_name(file_name)
+                    .with_context(|| format!("{file_name} is missing in the archive"))?
+                    .read_to_end(&mut content)?;
+                Ok(content)
+            })
+        }
+    }
+
+    fn decode(mut read_file: impl FnMut(&str) -> Result<Vec<u8>>) -> Result<ResetExport> {
+        let manifest: ExportManifest = serde_json::from_slice(&read_file(MANIFEST_FILE_NAME)?)
+            .context("failed to parse manifest")?;
+
+        if manifest.format_version != EXPORT_FORMAT_VERSION {
+            return Err(anyhow!(
+                "unsupported export format version {} (expected {EXPORT_FORMAT_VERSION})",
+                manifest.format_version
+            ));
+        }
+
+        let mut read_table = |table_name: &str| -> Result<Vec<u8>> {
+            let table = manifest
+                .tables
+                .iter()
+                .find(|t| t.name == table_name)
+                .ok_or_else(|| anyhow!("table {table_name} is missing in the manifest"))?;
+            read_file(&table.file_name)
+        };
+
+        Ok(ResetExport {
+            reset_date: manifest.reset_date,
+            ts_start_of_reset: manifest.ts_start_of_reset,
+            ts_latest_entry_of_reset: manifest.ts_latest_entry_of_reset,
+            agents: decode_table(&read_table(ExportAgent::TABLE_NAME)?, manifest.format)?,
+            job_runs: decode_table(&read_table(ExportJobRun::TABLE_NAME)?, manifest.format)?,
+            agent_logs: decode_table(&read_table(ExportAgentLog::TABLE_NAME)?, manifest.format)?,
+            construction_sites: decode_table(
+                &read_table(ExportConstructionSite::TABLE_NAME)?,
+                manifest.format,
+            )?,
+            construction_requirements: decode_table(
+                &read_table(ExportConstructionRequirement::TABLE_NAME)?,
+                manifest.format,
+            )?,
+            construction_material_logs: decode_table(
+                &read_table(ExportConstructionMaterialLog::TABLE_NAME)?,
+                manifest.format,
+            )?,
+        })
+    }
+}
+
+fn encode_table<T: ExportTable>(
+    rows: &[T],
+    format: ExportFormat,
+) -> Result<(ExportTableManifest, Vec<u8>)> {
+    let content = match format {
+        ExportFormat::Csv => {
+            let mut writer = csv::Writer::from_writer(vec![]);
+            if rows.is_empty() {
+                writer.write_record(T::columns().iter().map(|c| c.name.as_str()))?;
+            }
+            for row in rows {
+                writer.serialize(row)?;
+            }
+            writer.into_inner()?
+        }
+        ExportFormat::Jsonl => {
+            let mut content = vec![];
+            for row in rows {
+                serde_json::to_writer(&mut content, row)?;
+                content.push(b'\n');
+            }
+            content
+        }
+        ExportFormat::Parquet => {
+            let batch = to_record_batch(rows)?;
+            let mut writer = ArrowWriter::try_new(vec![], batch.schema(), None)?;
+            writer.write(&batch)?;
+            writer.into_inner()?
+        }
+    };
+
+    let manifest = ExportTableManifest {
+        name: T::TABLE_NAME.to_string(),
+        file_name: format!("{}.{}", T::TABLE_NAME, format.file_extension()),
+        row_count: rows.len(),
+        columns: T::columns(),
+    };
+
+    Ok((manifest, content))
+}
+
+fn decode_table<T: ExportTable>(content: &[u8], format: ExportFormat) -> Result<Vec<T>> {
+    let rows = match format {
+        ExportFormat::Csv => csv::Reader::from_reader(content)
+            .deserialize()
+            .collect::<Result<Vec<T>, _>>()?,
+        ExportFormat::Jsonl => serde_json::Deserializer::from_slice(content)
+            .into_iter()
+            .collect::<Result<Vec<T>, _>>()?,
+        ExportFormat::Parquet => {
+            let reader =
+                ParquetRecordBatchReaderBuilder::try_new(bytes::Bytes::from(content.to_vec()))?
+                    .build()?;
+            let mut rows = vec![];
+            for batch in reader {
+                rows.extend(from_record_batch::<T>(&batch?)?);
+            }
+            rows
+        }
+    };
+    Ok(rows)
+}
+
+/// Builds an arrow record batch by going through the serde representation of the rows,
+/// so that the column list of the table is the only thing that has to be maintained.
+fn to_record_batch<T: ExportTable>(rows: &[T]) -> Result<RecordBatch> {
+    let columns = T::columns();
+    let json_rows: Vec<serde_json::Value> = rows
+        .iter()
+        .map(serde_json::to_value)
+        .collect::<Result<_, _>>()?;
+
+    let mut fields = vec![];
+    let mut arrays: Vec<ArrayRef> = vec![];
+
+    for column in columns.iter() {
+        let values = json_rows.iter().map(|row| &row[column.name.as_str()]);
+        let missing = || anyhow!("unexpected value in column {}", column.name);
+
+        let (data_type, array): (DataType, ArrayRef) = match column.data_type {
+            ExportDataType::Int64 => (
+                DataType::Int64,
+                Arc::new(Int64Array::from(
+                    values
+                        .map(|v| v.as_i64().ok_or_else(missing))
+                        .collect::<Result<Vec<_>>>()?,
+                )),
+            ),
+            ExportDataType::Utf8 => (
+                DataType::Utf8,
+                Arc::new(StringArray::from(
+                    values
+                        .map(|v| v.as_str().ok_or_else(missing))
+                        .collect::<Result<Vec<_>>>()?,
+                )),
+            ),
+            ExportDataType::Boolean => (
+                DataType::Boolean,
+                Arc::new(BooleanArray::from(
+                    values
+                        .map(|v| v.as_bool().ok_or_else(missing))
+                        .collect::<Result<Vec<_>>>()?,
+                )),
+            ),
+            ExportDataType::Timestamp => (
+                DataType::Timestamp(TimeUnit::Millisecond, None),
+                Arc::new(TimestampMillisecondArray::from(
+                    values
+                        .map(|v| {
+                            serde_json::from_value::<NaiveDateTime>(v.clone())
+                                .map(|ts| ts.and_utc().timestamp_millis())
+                                .map_err(|_| missing())
+                        })
+                        .collect::<Result<Vec<_>>>()?,
+                )),
+            ),
+        };
+
+        fields.push(Field::new(column.name.clone(), data_type, false));
+        arrays.push(array);
+    }
+
+    Ok(RecordBatch::try_new(Arc::new(Schema::new(fields)), arrays)?)
+}
+
+/// Inverse of [to_record_batch]
+fn from_record_batch<T: ExportTable>(batch: &RecordBatch) -> Result<Vec<T>> {
+    let mut json_rows: Vec<serde_json::Map<String, serde_json::Value>> =
+        vec![serde_json::Map::new(); batch.num_rows()];
+
+    for column in T::columns() {
+        let array = batch
+            .column_by_name(&column.name)
+            .ok_or_else(|| anyhow!("column {} is missing", column.name))?;
+        let wrong_type = || anyhow!("column {} has an unexpected type", column.name);
+
+        for (idx, row) in json_rows.iter_mut().enumerate() {
+            let value = match column.data_type {
+                ExportDataType::Int64 => array
+                    .as_primitive_opt::<Int64Type>()
+                    .ok_or_else(wrong_type)?
+                    .value(idx)
+                    .into(),
+                ExportDataType::Utf8 => array
+                    .as_string_opt::<i32>()
+                    .ok_or_else(wrong_type)?
+                    .value(idx)
+                    .into(),
+                ExportDataType::Boolean => array
+                    .as_boolean_opt()
+                    .ok_or_else(wrong_type)?
+                    .value(idx)
+                    .into(),
+                ExportDataType::Timestamp => {
+                    let millis = array
+                        .as_primitive_opt::<TimestampMillisecondType>()
+                        .ok_or_else(wrong_type)?
+                        .value(idx);
+                    let ts = DateTime::from_timestamp_millis(millis)
+                        .ok_or_else(wrong_type)?
+                        .naive_utc();
+                    serde_json::to_value(ts)?
+                }
+            };
+            row.insert(column.name.clone(), value);
+        }
+    }
+
+    json_rows
+        .into_iter()
+        .map(|row| Ok(serde_json::from_value(serde_json::Value::Object(row))?))
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::db::seeded_test_pool;
+
+    /// in-memory db with two job runs of reset 2024-03-24, two agents and one jump gate
+    async fn seeded_pool() -> Pool<Sqlite> {
+        seeded_test_pool(
+            "
+insert into reset_date (reset_id, reset, first_ts) values (1, '2024-03-24', '2024-03-24 15:00:00');
+insert into construction_site (id, reset_id, jump_gate_waypoint_symbol) values (1, 1, 'X1-AA-JG');
+insert into construction_requirement (id, reset_id, trade_symbol, required)
+values (1, 1, 'FAB_MATS', 1600), (2, 1, 'ADVANCED_CIRCUITRY', 400);
+insert into static_agent_info (id, agent_symbol, agent_headquarters_waypoint_symbol, construction_site_id, starting_faction, reset_id, query_time)
+values (1, 'FLWI', 'X1-AA-A1', 1, 'COSMIC', 1, '2024-03-24 15:00:00'),
+       (2, 'WHYANDO', 'X1-AA-A1', 1, 'COSMIC, \"QUOTED\"', 1, '2024-03-24 15:05:00');
+insert into job_run (id, reset_id, query_time, event_time_minutes)
+values (1, 1, '2024-03-24 16:00:00', 60), (2, 1, '2024-03-24 16:05:00', 65);
+insert into agent_log (agent_id, job_id, credits, ship_count)
+values (1, 1, 175000, 2), (2, 1, 250000, 3), (1, 2, 9007199254740993, 4), (2, 2, -5, 3);
+insert into construction_log (id, job_id, construction_site_id, is_complete)
+values (1, 1, 1, false), (2, 2, 1, true);
+insert into construction_material_log (construction_log_id, construction_requirement_id, fulfilled)
+values (1, 1, 800), (1, 2, 0), (2, 1, 1600), (2, 2, 400);
+",
+        )
+        .await
+    }
+
+    #[tokio::test]
+    async fn export_bundles_round_trip() {
+        let pool = seeded_pool().await;
+        let reset_date = NaiveDate::from_ymd_opt(2024, 3, 24).unwrap();
+        let export = load_reset_export(&pool, reset_date).await.unwrap().unwrap();
+
+        assert_eq!(export.agents.len(), 2);
+        assert_eq!(export.job_runs.len(), 2);
+        assert_eq!(export.agent_logs.len(), 4);
+        assert_eq!(export.construction_material_logs.len(), 4);
+
+        let test_dir = std::env::temp_dir().join(format!(
+            "flwi-leaderboard-export-test-{}",
+            std::process::id()
+        ));
+
+        for format in [
+            ExportFormat::Csv,
+            ExportFormat::Jsonl,
+            ExportFormat::Parquet,
+        ] {
+            let output_dir = test_dir.join(format.file_extension());
+            export.write_to_dir(format, &output_dir).unwrap();
+            let from_dir = ResetExport::read_bundle(&output_dir).unwrap();
+            assert_eq!(from_dir, export, "round trip through {format:?} dir");
+
+            let zip_path = test_dir.join(format!("export.{}.zip", format.file_extension()));
+            fs::write(&zip_path, export.to_zip(format).unwrap()).unwrap();
+            let from_zip = ResetExport::read_bundle(&zip_path).unwrap();
+            assert_eq!(from_zip, export, "round trip through {format:?} zip");
+        }
+
+        fs::remove_dir_all(&test_dir).unwrap();
+    }
+
+    #[tokio::test]
+    async fn empty_tables_round_trip() {
+        let pool = seeded_pool().await;
+        let reset_date = NaiveDate::from_ymd_opt(2024, 3, 24).unwrap();
+        let export = ResetExport {
+            agent_logs: vec![],
+            construction_material_logs: vec![],
+            ..load_reset_export(&pool, reset_date).await.unwrap().unwrap()
+        };
+
+        for format in [
+            ExportFormat::Csv,
+            ExportFormat::Jsonl,
+            ExportFormat::Parquet,
+        ] {
+            let files = export.encode(format).unwrap();
+            let decoded = ResetExport::decode(|file_name| {
+                files
+                    .iter()
+                    .find(|(name, _)| name == file_name)
+                    .map(|(_, content)| content.clone())
+                    .ok_or_else(|| anyhow!("{file_name} is missing"))
+            })
+            .unwrap();
+            assert_eq!(decoded, export, "round trip through {format:?}");
+        }
+    }
+}
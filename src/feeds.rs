@@ -0,0 +1,528 @@
+use std::collections::HashMap;
+
+use atom_syndication::{Entry, Feed, FixedDateTime, Link, Person, Text};
+use axum::extract::State;
+use axum::http::{header, HeaderMap};
+use axum::response::{IntoResponse, Response};
+use axum::{routing, Router};
+use chrono::{NaiveDate, NaiveDateTime, TimeZone, Utc};
+use itertools::Itertools;
+use reqwest::Url;
+use sqlx::{Pool, Sqlite};
+
+use crate::api_error::{ApiError, ApiPath};
+use crate::db::{
+    load_reset_dates, select_all_time_construction_leaderboard, select_all_time_performance,
+    DbAllTimePerformanceEntry, DbConstructionLeaderboardEntry, ResetDate,
+};
+use crate::server::AppState;
+
+const MAX_FEED_ENTRIES: usize = 100;
+const FINAL_STANDINGS_SIZE: usize = 10;
+
+/// entry ids must not change if the server is reachable under a different host
+const TAG_PREFIX: &str = "tag:flwi-spacetraders-rust-leaderboard.fly.dev,2024";
+
+#[derive(Debug, Clone)]
+pub(crate) struct FeedSettings {
+    /// base url for the links in the feeds. Derived from the Host header if missing.
+    pub(crate) public_url: Option<Url>,
+}
+
+pub(crate) fn feeds_router() -> Router<AppState> {
+    Router::new()
+        .route("/feeds/milestones.atom", routing::get(get_milestones_feed))
+        .route(
+            "/feeds/agents/:agent_symbol/milestones.atom",
+            routing::get(get_agent_milestones_feed),
+        )
+}
+
+/// A single entry of a feed
+#[derive(Debug, Clone)]
+struct Milestone {
+    /// unique across all resets - part of the entry id
+    key: String,
+    ts: NaiveDateTime,
+    title: String,
+    summary: String,
+    /// path of the api endpoint with the details
+    path: String,
+}
+
+/// Reset starts, the first jump gate construction of each reset, jump gate completions and the
+/// final standings of closed resets.
+async fn get_milestones_feed(
+    State(pool): State<Pool<Sqlite>>,
+    State(settings): State<FeedSettings>,
+    headers: HeaderMap,
+) -> Result<Response, ApiError> {
+    let base_url = base_url(&settings, &headers);
+    let milestones = load_milestones(&pool, None).await?;
+
+    atom_response(
+        &base_url,
+        "/feeds/milestones.atom",
+        "SpaceTraders leaderboard milestones",
+        milestones,
+    )
+}
+
+/// Milestones of a single agent: its jump gate construction and its final rank in each reset.
+async fn get_agent_milestones_feed(
+    State(pool): State<Pool<Sqlite>>,
+    State(settings): State<FeedSettings>,
+    ApiPath(agent_symbol): ApiPath<String>,
+    headers: HeaderMap,
+) -> Result<Response, ApiError> {
+    let agent_symbol = agent_symbol.to_uppercase();
+    let base_url = base_url(&settings, &headers);
+    let milestones = load_milestones(&pool, Some(&agent_symbol)).await?;
+    if milestones.is_empty() {
+        return Err(ApiError::NotFound(format!(
+            "no milestones for agent {agent_symbol}"
+        )));
+    }
+
+    atom_response(
+        &base_url,
+        &format!("/feeds/agents/{agent_symbol}/milestones.atom"),
+        &format!("SpaceTraders leaderboard milestones of {agent_symbol}"),
+        milestones,
+    )
+}
+
+async fn load_milestones(
+    pool: &Pool<Sqlite>,
+    maybe_agent_symbol: Option<&str>,
+) -> Result<Vec<Milestone>, ApiError> {
+    let reset_dates = load_reset_dates(pool).await?;
+    let constructions = select_all_time_construction_leaderboard(pool).await?;
+    let standings = select_all_time_performance(pool).await?;
+
+    let mut milestones = match maybe_agent_symbol {
+        None => collect_milestones(&reset_dates, &constructions, &standings),
+        Some(agent_symbol) => {
+            collect_agent_milestones(&reset_dates, &constructions, &standings, agent_symbol)
+        }
+    };
+    milestones.sort_by(|a, b| b.ts.cmp(&a.ts).then_with(|| a.key.cmp(&b.key)));
+    milestones.truncate(MAX_FEED_ENTRIES);
+    Ok(milestones)
+}
+
+fn collect_milestones(
+    reset_dates: &[ResetDate],
+    constructions: &[DbConstructionLeaderboardEntry],
+    standings: &[DbAllTimePerformanceEntry],
+) -> Vec<Milestone> {
+    let standings_of_reset = standings.iter().into_group_map_by(|entry| entry.reset);
+    let constructions_of_reset = constructions
+        .iter()
+        .into_group_map_by(|entry| entry.reset_date);
+
+    let mut milestones = Vec::new();
+    for reset_date in reset_dates {
+        let reset = reset_date.reset;
+        milestones.push(Milestone {
+            key: format!("{reset}:reset-started"),
+            ts: reset_date.first_ts,
+            title: format!("Reset {reset} started"),
+            summary: format!(
+                "{} agents registered so far.",
+                standings_of_reset.get(&reset).map_or(0, Vec::len)
+            ),
+            path: format!("/api/leaderboard/{reset}"),
+        });
+
+        let constructions = constructions_of_reset
+            .get(&reset)
+            .map(Vec::as_slice)
+            .unwrap_or_default();
+        if let Some(first) = constructions
+            .iter()
+            .min_by_key(|entry| entry.ts_start_jump_gate_construction)
+        {
+            milestones.push(construction_started(
+                first,
+                format!(
+                    "First jump gate construction of reset {reset} started at {}",
+                    first.jump_gate_waypoint_symbol
+                ),
+            ));
+        }
+        milestones.extend(
+            constructions
+                .iter()
+                .filter_map(|entry| construction_completed(entry)),
+        );
+
+        if !reset_date.is_ongoing {
+            let top = standings_of_reset
+                .get(&reset)
+                .map(|entries| {
+                    entries
+                        .iter()
+                        .sorted_by_key(|entry| entry.rank)
+                        .take(FINAL_STANDINGS_SIZE)
+                        .map(|entry| {
+                            format!(
+                                "{}. {} - {} credits",
+                                entry.rank, entry.agent_symbol, entry.credits
+                            )
+                        })
+                        .join("\n")
+                })
+                .unwrap_or_default();
+            milestones.push(Milestone {
+                key: format!("{reset}:final-standings"),
+                ts: reset_date.latest_ts,
+                title: format!("Final standings of reset {reset}"),
+                summary: top,
+                path: format!("/api/leaderboard/{reset}"),
+            });
+        }
+    }
+    milestones
+}
+
+fn collect_agent_milestones(
+    reset_dates: &[ResetDate],
+    constructions: &[DbConstructionLeaderboardEntry],
+    standings: &[DbAllTimePerformanceEntry],
+    agent_symbol: &str,
+) -> Vec<Milestone> {
+    let reset_dates: HashMap<NaiveDate, &ResetDate> = reset_dates
+        .iter()
+        .map(|reset_date| (reset_date.reset, reset_date))
+        .collect();
+    let num_agents_of_reset = standings.iter().counts_by(|entry| entry.reset);
+
+    let mut milestones = Vec::new();
+    for entry in constructions.iter().filter(|entry| {
+        entry
+            .agents_in_system_csv
+            .split(',')
+            .any(|a| a == agent_symbol)
+    }) {
+        milestones.push(construction_started(
+            entry,
+            format!(
+                "Construction of jump gate {} started in reset {}",
+                entry.jump_gate_waypoint_symbol, entry.reset_date
+            ),
+        ));
+        milestones.extend(construction_completed(entry));
+    }
+
+    for entry in standings
+        .iter()
+        .filter(|entry| entry.agent_symbol == agent_symbol)
+    {
+        let Some(reset_date) = reset_dates.get(&entry.reset) else {
+            continue;
+        };
+        if reset_date.is_ongoing {
+            continue;
+        }
+        milestones.push(Milestone {
+            key: format!("{}:final-standings:{agent_symbol}", entry.reset),
+            ts: reset_date.latest_ts,
+            title: format!(
+                "{agent_symbol} finished reset {} at #{}",
+                entry.reset, entry.rank
+            ),
+            summary: format!(
+                "{} credits - rank {} of {} agents.",
+                entry.credits,
+                entry.rank,
+                num_agents_of_reset
+                    .get(&entry.reset)
+                    .copied()
+                    .unwrap_or_default()
+            ),
+            path: format!("/api/leaderboard/{}", entry.reset),
+        });
+    }
+    milestones
+}
+
+fn construction_started(entry: &DbConstructionLeaderboardEntry, title: String) -> Milestone {
+    Milestone {
+        key: format!(
+            "{}:jump-gate-started:{}",
+            entry.reset_date, entry.jump_gate_waypoint_symbol
+        ),
+        ts: entry.ts_start_jump_gate_construction,
+        title,
+        summary: format!(
+            "{} after the start of the reset. Agents in the system: {}.",
+            format_duration_minutes(
+                entry.duration_minutes_start_fortnight_start_jump_gate_construction
+            ),
+            entry.agents_in_system_csv.replace(',', ", ")
+        ),
+        path: format!(
+            "/api/jump-gate-construction-event-overview/{}",
+            entry.reset_date
+        ),
+    }
+}
+
+/// `None` if the jump gate isn't complete yet
+fn construction_completed(entry: &DbConstructionLeaderboardEntry) -> Option<Milestone> {
+    let ts_finish = entry.ts_finish_jump_gate_construction?;
+    Some(Milestone {
+        key: format!(
+            "{}:jump-gate-completed:{}",
+            entry.reset_date, entry.jump_gate_waypoint_symbol
+        ),
+        ts: ts_finish,
+        title: format!(
+            "Jump gate {} completed as #{} in reset {}",
+            entry.jump_gate_waypoint_symbol,
+            entry.rank_start_fortnight_finish_jump_gate_construction,
+            entry.reset_date
+        ),
+        summary: format!(
+            "Construction took {} (#{} by construction time), {} after the start of the reset. Agents in the system: {}.",
+            entry
+                .duration_minutes_jump_gate_construction
+                .map(format_duration_minutes)
+                .unwrap_or_default(),
+            entry.rank_jump_gate_construction,
+            entry
+                .duration_minutes_start_fortnight_finish_jump_gate_construction
+                .map(format_duration_minutes)
+                .unwrap_or_default(),
+            entry.agents_in_system_csv.replace(',', ", ")
+        ),
+        path: format!(
+            "/api/jump-gate-construction-event-overview/{}",
+            entry.reset_date
+        ),
+    })
+}
+
+/// e.g. `2d 3h 15m`
+fn format_duration_minutes(minutes: i64) -> String {
+    let (days, hours, minutes) = (minutes / 1440, minutes % 1440 / 60, minutes % 60);
+    match (days, hours) {
+        (0, 0) => format!("{minutes}m"),
+        (0, _) => format!("{hours}h {minutes}m"),
+        _ => format!("{days}d {hours}h {minutes}m"),
+    }
+}
+
+/// Prefers the configured public url. Behind a proxy the scheme comes from `X-Forwarded-Proto`.
+fn base_url(settings: &FeedSettings, headers: &HeaderMap) -> String {
+    if let Some(public_url) = &settings.public_url {
+        return public_url.as_str().trim_end_matches('/').to_string();
+    }
+
+    let header_value = |name| {
+        headers
+            .get(name)
+            .and_then(|value| value.to_str().ok())
+            .map(|value| value.to_string())
+    };
+    let host = header_value(header::HOST).unwrap_or("localhost".to_string());
+    let scheme = header_value(header::HeaderName::from_static("x-forwarded-proto"))
+        .unwrap_or("http".to_string());
+    format!("{scheme}://{host}")
+}
+
+fn to_fixed_date_time(ts: NaiveDateTime) -> FixedDateTime {
+    Utc.from_utc_datetime(&ts).fixed_offset()
+}
+
+fn atom_response(
+    base_url: &str,
+    feed_path: &str,
+    title: &str,
+    milestones: Vec<Milestone>,
+) -> Result<Response, ApiError> {
+    let updated = milestones
+        .iter()
+        .map(|milestone| milestone.ts)
+        .max()
+        .unwrap_or_else(|| Utc::now().naive_utc());
+
+    let entries: Vec<Entry> = milestones
+        .into_iter()
+        .map(|milestone| {
+            let mut entry = Entry::default();
+            entry.set_id(format!("{TAG_PREFIX}:milestone/{}", milestone.key));
+            entry.set_title(Text::plain(milestone.title));
+            entry.set_updated(to_fixed_date_time(milestone.ts));
+            entry.set_summary(Some(Text::plain(milestone.summary)));
+            entry.set_links(vec![Link {
+                href: format!("{base_url}{}", milestone.path),
+                mime_type: Some("application/json".to_string()),
+                ..Default::default()
+            }]);
+            entry
+        })
+        .collect();
+
+    let mut feed = Feed::default();
+    feed.set_id(format!("{TAG_PREFIX}:feed{feed_path}"));
+    feed.set_title(Text::plain(title));
+    feed.set_updated(to_fixed_date_time(updated));
+    feed.set_authors(vec![Person {
+        name: "flwi-spacetraders-leaderboard".to_string(),
+        ..Default::default()
+    }]);
+    feed.set_links(vec![Link {
+        href: format!("{base_url}{feed_path}"),
+        rel: "self".to_string(),
+        mime_type: Some("application/atom+xml".to_string()),
+        ..Default::default()
+    }]);
+    feed.set_entries(entries);
+
+    let body = feed
+        .write_to(Vec::new())
+        .map_err(|err| ApiError::Internal(err.to_string()))?;
+    Ok((
+        [(header::CONTENT_TYPE, "application/atom+xml; charset=utf-8")],
+        body,
+    )
+        .into_response())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn ts(date: NaiveDate, hour: u32) -> NaiveDateTime {
+        date.and_hms_opt(hour, 0, 0).unwrap()
+    }
+
+    fn closed_reset() -> NaiveDate {
+        NaiveDate::from_ymd_opt(2024, 3, 10).unwrap()
+    }
+
+    fn ongoing_reset() -> NaiveDate {
+        NaiveDate::from_ymd_opt(2024, 3, 24).unwrap()
+    }
+
+    fn reset_dates() -> Vec<ResetDate> {
+        vec![
+            ResetDate {
+                reset_id: 1,
+                reset: closed_reset(),
+                first_ts: ts(closed_reset(), 0),
+                latest_ts: ts(closed_reset() + chrono::Days::new(13), 23),
+                is_ongoing: false,
+            },
+            ResetDate {
+                reset_id: 2,
+                reset: ongoing_reset(),
+                first_ts: ts(ongoing_reset(), 0),
+                latest_ts: ts(ongoing_reset(), 12),
+                is_ongoing: true,
+            },
+        ]
+    }
+
+    fn construction(
+        reset: NaiveDate,
+        jump_gate_waypoint_symbol: &str,
+        agents_in_system_csv: &str,
+        is_complete: bool,
+    ) -> DbConstructionLeaderboardEntry {
+        DbConstructionLeaderboardEntry {
+            reset_date: reset,
+            ts_start_of_reset: ts(reset, 0),
+            jump_gate_waypoint_symbol: jump_gate_waypoint_symbol.to_string(),
+            agents_in_system_csv: agents_in_system_csv.to_string(),
+            ts_start_jump_gate_construction: ts(reset, 2),
+            ts_finish_jump_gate_construction: is_complete.then(|| ts(reset, 5)),
+            duration_minutes_start_fortnight_start_jump_gate_construction: 120,
+            duration_minutes_start_fortnight_finish_jump_gate_construction: is_complete
+                .then_some(300),
+            duration_minutes_jump_gate_construction: is_complete.then_some(180),
+            rank_jump_gate_construction: 1,
+            rank_start_fortnight_start_jump_gate_construction: 1,
+            rank_start_fortnight_finish_jump_gate_construction: 1,
+        }
+    }
+
+    fn standing(reset: NaiveDate, agent_symbol: &str, rank: i64) -> DbAllTimePerformanceEntry {
+        DbAllTimePerformanceEntry {
+            reset,
+            agent_symbol: agent_symbol.to_string(),
+            starting_faction: "COSMIC".to_string(),
+            credits: 1000 - rank,
+            rank,
+        }
+    }
+
+    fn constructions() -> Vec<DbConstructionLeaderboardEntry> {
+        vec![
+            construction(closed_reset(), "X1-A1-JG", "AGENT-A", true),
+            construction(ongoing_reset(), "X1-B1-JG", "AGENT-B", false),
+        ]
+    }
+
+    fn standings() -> Vec<DbAllTimePerformanceEntry> {
+        vec![
+            standing(closed_reset(), "AGENT-B", 2),
+            standing(closed_reset(), "AGENT-A", 1),
+            standing(ongoing_reset(), "AGENT-A", 1),
+        ]
+    }
+
+    #[test]
+    fn milestones_contain_completions_and_final_standings_of_closed_resets() {
+        let milestones = collect_milestones(&reset_dates(), &constructions(), &standings());
+        let keys: Vec<_> = milestones.iter().map(|m| m.key.as_str()).collect();
+        assert_eq!(
+            keys,
+            vec![
+                "2024-03-10:reset-started",
+                "2024-03-10:jump-gate-started:X1-A1-JG",
+                "2024-03-10:jump-gate-completed:X1-A1-JG",
+                "2024-03-10:final-standings",
+                "2024-03-24:reset-started",
+                "2024-03-24:jump-gate-started:X1-B1-JG",
+            ]
+        );
+
+        let final_standings = &milestones[3];
+        assert_eq!(final_standings.ts, reset_dates()[0].latest_ts);
+        assert_eq!(
+            final_standings.summary,
+            "1. AGENT-A - 999 credits\n2. AGENT-B - 998 credits"
+        );
+
+        let completed = &milestones[2];
+        assert_eq!(completed.ts, ts(closed_reset(), 5));
+        assert_eq!(
+            completed.summary,
+            "Construction took 3h 0m (#1 by construction time), 5h 0m after the start of the reset. Agents in the system: AGENT-A."
+        );
+    }
+
+    #[test]
+    fn agent_milestones_only_contain_the_agent() {
+        let milestones =
+            collect_agent_milestones(&reset_dates(), &constructions(), &standings(), "AGENT-A");
+        let keys: Vec<_> = milestones.iter().map(|m| m.key.as_str()).collect();
+        assert_eq!(
+            keys,
+            vec![
+                "2024-03-10:jump-gate-started:X1-A1-JG",
+                "2024-03-10:jump-gate-completed:X1-A1-JG",
+                // no final standings of the ongoing reset
+                "2024-03-10:final-standings:AGENT-A",
+            ]
+        );
+        assert_eq!(
+            milestones[2].title,
+            "AGENT-A finished reset 2024-03-10 at #1"
+        );
+        assert_eq!(milestones[2].summary, "999 credits - rank 1 of 2 agents.");
+    }
+}
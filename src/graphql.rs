@@ -0,0 +1,701 @@
+use std::sync::Arc;
+
+use async_graphql::connection::{query, Connection, Edge};
+use async_graphql::http::GraphiQLSource;
+use async_graphql::{
+    ComplexObject, Context, EmptyMutation, EmptySubscription, Enum, Object, OutputType, Schema,
+    SimpleObject,
+};
+use axum::extract::State;
+use axum::response::{Html, IntoResponse};
+use axum::{routing, Json, Router};
+use chrono::{NaiveDate, NaiveDateTime};
+use sqlx::{Pool, Sqlite};
+use tokio::sync::OnceCell;
+
+use crate::api_error::ApiJson;
+use crate::db::{
+    load_leaderboard_for_reset, load_reset_date, load_reset_dates, select_agent_history,
+    select_construction_progress_for_reset, select_jump_gate_agent_assignment_for_reset,
+    select_most_recent_construction_progress_for_reset, DbConstructionMaterialMostRecentStatus,
+    DbJumpGateAssignmentEntry, LeaderboardEntry, ResetDate,
+};
+use crate::server::{extract_reset_period, parse_csv, AppState, ResetPeriodFilter, ResetWindow};
+
+/// page size of connections if neither `first` nor `last` is given
+const DEFAULT_PAGE_SIZE: usize = 20;
+const MAX_PAGE_SIZE: usize = 100;
+
+/// nesting reset -> agent -> constructionSite -> materials -> history is about 10 levels deep incl. the connection wrappers
+const MAX_QUERY_DEPTH: usize = 12;
+const MAX_QUERY_COMPLEXITY: usize = 5000;
+
+pub(crate) type LeaderboardSchema = Schema<QueryRoot, EmptyMutation, EmptySubscription>;
+
+pub(crate) fn build_schema() -> LeaderboardSchema {
+    Schema::build(QueryRoot, EmptyMutation, EmptySubscription)
+        .limit_depth(MAX_QUERY_DEPTH)
+        .limit_complexity(MAX_QUERY_COMPLEXITY)
+        .finish()
+}
+
+pub(crate) fn graphql_router() -> Router<AppState> {
+    Router::new().route(
+        "/graphql",
+        routing::get(graphiql).post(post_graphql_request),
+    )
+}
+
+async fn post_graphql_request(
+    State(schema): State<LeaderboardSchema>,
+    State(pool): State<Pool<Sqlite>>,
+    ApiJson(request): ApiJson<async_graphql::Request>,
+) -> Json<async_graphql::Response> {
+    Json(schema.execute(request.data(pool)).await)
+}
+
+async fn graphiql() -> impl IntoResponse {
+    Html(GraphiQLSource::build().endpoint("/graphql").finish())
+}
+
+pub(crate) struct QueryRoot;
+
+#[Object]
+impl QueryRoot {
+    /// All resets, oldest first
+    #[graphql(complexity = "page_size(first, last) * child_complexity")]
+    async fn resets(
+        &self,
+        ctx: &Context<'_>,
+        after: Option<String>,
+        before: Option<String>,
+        first: Option<i32>,
+        last: Option<i32>,
+    ) -> async_graphql::Result<Connection<usize, Reset>> {
+        let pool = ctx.data::<Pool<Sqlite>>()?;
+        let resets = load_reset_dates(pool)
+            .await?
+            .into_iter()
+            .map(Reset::new)
+            .collect();
+        paginate(resets, after, before, first, last).await
+    }
+
+    async fn reset(
+        &self,
+        ctx: &Context<'_>,
+        reset_date: NaiveDate,
+    ) -> async_graphql::Result<Option<Reset>> {
+        let pool = ctx.data::<Pool<Sqlite>>()?;
+        Ok(load_reset_date(pool, reset_date).await?.map(Reset::new))
+    }
+}
+
+#[derive(Enum, Copy, Clone, Eq, PartialEq)]
+enum HistorySelectionMode {
+    /// the first minutes of the reset
+    First,
+    /// the most recent minutes of the reset
+    Last,
+}
+
+impl HistorySelectionMode {
+    fn window(
+        self,
+        event_time_minutes_gte: Option<u32>,
+        event_time_minutes_lte: u32,
+    ) -> ResetWindow {
+        match self {
+            HistorySelectionMode::First => ResetWindow::First {
+                event_time_minutes_gte,
+                event_time_minutes_lte,
+            },
+            HistorySelectionMode::Last => ResetWindow::Last {
+                event_time_minutes_lte,
+            },
+        }
+    }
+}
+
+/// Data of a reset that is shared by its nested objects. Loaded at most once per request.
+struct ResetData {
+    reset: ResetDate,
+    leaderboard: OnceCell<Vec<LeaderboardEntry>>,
+    assignments: OnceCell<Vec<DbJumpGateAssignmentEntry>>,
+    materials: OnceCell<Vec<DbConstructionMaterialMostRecentStatus>>,
+}
+
+#[derive(Clone)]
+pub(crate) struct Reset(Arc<ResetData>);
+
+impl Reset {
+    fn new(reset: ResetDate) -> Self {
+        Self(Arc::new(ResetData {
+            reset,
+            leaderboard: OnceCell::new(),
+            assignments: OnceCell::new(),
+            materials: OnceCell::new(),
+        }))
+    }
+
+    async fn leaderboard(&self, pool: &Pool<Sqlite>) -> async_graphql::Result<&[LeaderboardEntry]> {
+        let entries = self
+            .0
+            .leaderboard
+            .get_or_try_init(|| load_leaderboard_for_reset(pool, self.0.reset.reset))
+            .await?;
+        Ok(entries)
+    }
+
+    async fn assignments(
+        &self,
+        pool: &Pool<Sqlite>,
+    ) -> async_graphql::Result<&[DbJumpGateAssignmentEntry]> {
+        let entries = self
+            .0
+            .assignments
+            .get_or_try_init(|| {
+                select_jump_gate_agent_assignment_for_reset(pool, self.0.reset.reset)
+            })
+            .await?;
+        Ok(entries)
+    }
+
+    async fn materials(
+        &self,
+        pool: &Pool<Sqlite>,
+    ) -> async_graphql::Result<&[DbConstructionMaterialMostRecentStatus]> {
+        let entries = self
+            .0
+            .materials
+            .get_or_try_init(|| {
+                select_most_recent_construction_progress_for_reset(pool, self.0.reset.reset)
+            })
+            .await?;
+        Ok(entries)
+    }
+
+    async fn agents_vec(&self, pool: &Pool<Sqlite>) -> async_graphql::Result<Vec<Agent>> {
+        let leaderboard = self.leaderboard(pool).await?;
+        Ok(leaderboard
+            .iter()
+            .enumerate()
+            .map(|(idx, entry)| Agent {
+                reset: self.clone(),
+                rank: idx + 1,
+                entry: entry.clone(),
+            })
+            .collect())
+    }
+
+    async fn construction_sites_vec(
+        &self,
+        pool: &Pool<Sqlite>,
+    ) -> async_graphql::Result<Vec<ConstructionSite>> {
+        let assignments = self.assignments(pool).await?;
+        Ok(assignments
+            .iter()
+            .map(|a| ConstructionSite {
+                reset: self.clone(),
+                jump_gate_waypoint_symbol: a.jump_gate_waypoint_symbol.clone(),
+                agent_headquarters_waypoint_symbol: a.agent_headquarters_waypoint_symbol.clone(),
+                agent_symbols: parse_csv(&a.agents_in_system_csv),
+            })
+            .collect())
+    }
+
+    fn period(
+        &self,
+        selection_mode: HistorySelectionMode,
+        event_time_minutes_gte: Option<u32>,
+        event_time_minutes_lte: u32,
+    ) -> ResetPeriodFilter {
+        let num_minutes = (self.0.reset.latest_ts - self.0.reset.first_ts)
+            .num_minutes()
+            .unsigned_abs() as u32;
+        extract_reset_period(
+            selection_mode.window(event_time_minutes_gte, event_time_minutes_lte),
+            num_minutes,
+        )
+    }
+}
+
+#[Object]
+impl Reset {
+    async fn reset_date(&self) -> NaiveDate {
+        self.0.reset.reset
+    }
+
+    async fn first_ts(&self) -> NaiveDateTime {
+        self.0.reset.first_ts
+    }
+
+    async fn latest_ts(&self) -> NaiveDateTime {
+        self.0.reset.latest_ts
+    }
+
+    async fn is_ongoing(&self) -> bool {
+        self.0.reset.is_ongoing
+    }
+
+    /// Agents of the reset ordered by their most recent credits
+    #[graphql(complexity = "page_size(first, last) * child_complexity")]
+    async fn agents(
+        &self,
+        ctx: &Context<'_>,
+        after: Option<String>,
+        before: Option<String>,
+        first: Option<i32>,
+        last: Option<i32>,
+    ) -> async_graphql::Result<Connection<usize, Agent>> {
+        let agents = self.agents_vec(ctx.data::<Pool<Sqlite>>()?).await?;
+        paginate(agents, after, before, first, last).await
+    }
+
+    async fn agent(
+        &self,
+        ctx: &Context<'_>,
+        agent_symbol: String,
+    ) -> async_graphql::Result<Option<Agent>> {
+        let agents = self.agents_vec(ctx.data::<Pool<Sqlite>>()?).await?;
+        Ok(agents
+            .into_iter()
+            .find(|a| a.entry.agent_symbol == agent_symbol))
+    }
+
+    #[graphql(complexity = "page_size(first, last) * child_complexity")]
+    async fn construction_sites(
+        &self,
+        ctx: &Context<'_>,
+        after: Option<String>,
+        before: Option<String>,
+        first: Option<i32>,
+        last: Option<i32>,
+    ) -> async_graphql::Result<Connection<usize, ConstructionSite>> {
+        let sites = self
+            .construction_sites_vec(ctx.data::<Pool<Sqlite>>()?)
+            .await?;
+        paginate(sites, after, before, first, last).await
+    }
+
+    async fn construction_site(
+        &self,
+        ctx: &Context<'_>,
+        jump_gate_waypoint_symbol: String,
+    ) -> async_graphql::Result<Option<ConstructionSite>> {
+        let sites = self
+            .construction_sites_vec(ctx.data::<Pool<Sqlite>>()?)
+            .await?;
+        Ok(sites
+            .into_iter()
+            .find(|s| s.jump_gate_waypoint_symbol == jump_gate_waypoint_symbol))
+    }
+}
+
+pub(crate) struct Agent {
+    reset: Reset,
+    rank: usize,
+    entry: LeaderboardEntry,
+}
+
+#[Object]
+impl Agent {
+    async fn agent_symbol(&self) -> &str {
+        &self.entry.agent_symbol
+    }
+
+    /// 1-based rank by the most recent credits
+    async fn rank(&self) -> usize {
+        self.rank
+    }
+
+    async fn credits(&self) -> i64 {
+        self.entry.credits
+    }
+
+    async fn ship_count(&self) -> i64 {
+        self.entry.ship_count
+    }
+
+    async fn starting_faction(&self) -> &str {
+        &self.entry.starting_faction
+    }
+
+    async fn headquarters_waypoint_symbol(&self) -> &str {
+        &self.entry.agent_headquarters_waypoint_symbol
+    }
+
+    async fn construction_site(
+        &self,
+        ctx: &Context<'_>,
+    ) -> async_graphql::Result<Option<ConstructionSite>> {
+        let sites = self
+            .reset
+            .construction_sites_vec(ctx.data::<Pool<Sqlite>>()?)
+            .await?;
+        Ok(sites
+            .into_iter()
+            .find(|s| s.jump_gate_waypoint_symbol == self.entry.jump_gate_waypoint_symbol))
+    }
+
+    /// Credits and ship count over time. The resolution depends on the length of the selected period.
+    #[graphql(complexity = "page_size(first, last) * child_complexity")]
+    #[allow(clippy::too_many_arguments)]
+    async fn history(
+        &self,
+        ctx: &Context<'_>,
+        #[graphql(default_with = "HistorySelectionMode::Last")]
+        selection_mode: HistorySelectionMode,
+        event_time_minutes_gte: Option<u32>,
+        event_time_minutes_lte: u32,
+        after: Option<String>,
+        before: Option<String>,
+        first: Option<i32>,
+        last: Option<i32>,
+    ) -> async_graphql::Result<Connection<usize, AgentHistoryEntry>> {
+        let pool = ctx.data::<Pool<Sqlite>>()?;
+        let ResetPeriodFilter {
+            from_event_time_minutes,
+            to_event_time_minutes,
+            resolution_minutes,
+        } = self.reset.period(
+            selection_mode,
+            event_time_minutes_gte,
+            event_time_minutes_lte,
+        );
+
+        let db_history = select_agent_history(
+            pool,
+            self.reset.0.reset.reset,
+            from_event_time_minutes,
+            to_event_time_minutes,
+            resolution_minutes,
+            vec![self.entry.agent_symbol.clone()],
+        )
+        .await?;
+
+        let entries = db_history
+            .into_iter()
+            .flat_map(|h| {
+                let event_times_minutes = h.event_times_minutes.map(|j| j.0).unwrap_or_default();
+                let credits = h.credits_timeline.map(|j| j.0).unwrap_or_default();
+                let ship_counts = h.ship_count_timeline.map(|j| j.0).unwrap_or_default();
+                event_times_minutes
+                    .into_iter()
+                    .zip(credits)
+                    .zip(ship_counts)
+                    .map(
+                        |((event_time_minutes, credits), ship_count)| AgentHistoryEntry {
+                            event_time_minutes,
+                            credits,
+                            ship_count,
+                        },
+                    )
+                    .collect::<Vec<_>>()
+            })
+            .collect();
+
+        paginate(entries, after, before, first, last).await
+    }
+}
+
+#[derive(SimpleObject)]
+pub(crate) struct AgentHistoryEntry {
+    event_time_minutes: u32,
+    credits: i64,
+    ship_count: u32,
+}
+
+pub(crate) struct ConstructionSite {
+    reset: Reset,
+    jump_gate_waypoint_symbol: String,
+    agent_headquarters_waypoint_symbol: String,
+    agent_symbols: Vec<String>,
+}
+
+#[Object]
+impl ConstructionSite {
+    async fn jump_gate_waypoint_symbol(&self) -> &str {
+        &self.jump_gate_waypoint_symbol
+    }
+
+    async fn agent_headquarters_waypoint_symbol(&self) -> &str {
+        &self.agent_headquarters_waypoint_symbol
+    }
+
+    async fn is_complete(&self, ctx: &Context<'_>) -> async_graphql::Result<bool> {
+        let materials = self.reset.materials(ctx.data::<Pool<Sqlite>>()?).await?;
+        Ok(materials.iter().any(|m| {
+            m.jump_gate_waypoint_symbol == self.jump_gate_waypoint_symbol && m.is_jump_gate_complete
+        }))
+    }
+
+    /// Agents headquartered in the system of the jump gate
+    async fn agents(&self, ctx: &Context<'_>) -> async_graphql::Result<Vec<Agent>> {
+        let agents = self.reset.agents_vec(ctx.data::<Pool<Sqlite>>()?).await?;
+        Ok(agents
+            .into_iter()
+            .filter(|a| self.agent_symbols.contains(&a.entry.agent_symbol))
+            .collect())
+    }
+
+    /// Most recent delivery status of the materials required for the jump gate
+    async fn materials(&self, ctx: &Context<'_>) -> async_graphql::Result<Vec<Material>> {
+        let materials = self.reset.materials(ctx.data::<Pool<Sqlite>>()?).await?;
+        Ok(materials
+            .iter()
+            .filter(|m| m.jump_gate_waypoint_symbol == self.jump_gate_waypoint_symbol)
+            .map(|m| Material {
+                reset: self.reset.clone(),
+                jump_gate_waypoint_symbol: m.jump_gate_waypoint_symbol.clone(),
+                trade_symbol: m.trade_symbol.clone(),
+                fulfilled: m.fulfilled,
+                required: m.required,
+            })
+            .collect())
+    }
+}
+
+#[derive(SimpleObject)]
+#[graphql(complex)]
+pub(crate) struct Material {
+    #[graphql(skip)]
+    reset: Reset,
+    #[graphql(skip)]
+    jump_gate_waypoint_symbol: String,
+    trade_symbol: String,
+    fulfilled: i64,
+    required: i64,
+}
+
+#[ComplexObject]
+impl Material {
+    /// Delivered amount over time. The resolution depends on the length of the selected period.
+    #[graphql(complexity = "page_size(first, last) * child_complexity")]
+    #[allow(clippy::too_many_arguments)]
+    async fn history(
+        &self,
+        ctx: &Context<'_>,
+        #[graphql(default_with = "HistorySelectionMode::Last")]
+        selection_mode: HistorySelectionMode,
+        event_time_minutes_gte: Option<u32>,
+        event_time_minutes_lte: u32,
+        after: Option<String>,
+        before: Option<String>,
+        first: Option<i32>,
+        last: Option<i32>,
+    ) -> async_graphql::Result<Connection<usize, MaterialHistoryEntry>> {
+        let pool = ctx.data::<Pool<Sqlite>>()?;
+        let ResetPeriodFilter {
+            from_event_time_minutes,
+            to_event_time_minutes,
+            resolution_minutes,
+        } = self.reset.period(
+            selection_mode,
+            event_time_minutes_gte,
+            event_time_minutes_lte,
+        );
+
+        let db_history = select_construction_progress_for_reset(
+            pool,
+            self.reset.0.reset.reset,
+            from_event_time_minutes,
+            to_event_time_minutes,
+            resolution_minutes,
+            vec![self.jump_gate_waypoint_symbol.clone()],
+        )
+        .await?;
+
+        let entries = db_history
+            .into_iter()
+            .filter(|h| h.trade_symbol == self.trade_symbol)
+            .flat_map(|h| {
+                let event_times_minutes: Vec<u32> =
+                    parse_csv(h.event_time_minutes_csv.as_deref().unwrap_or_default());
+                let fulfilled: Vec<u32> = parse_csv(h.fulfilled_csv.as_deref().unwrap_or_default());
+                event_times_minutes
+                    .into_iter()
+                    .zip(fulfilled)
+                    .map(|(event_time_minutes, fulfilled)| MaterialHistoryEntry {
+                        event_time_minutes,
+                        fulfilled,
+                    })
+                    .collect::<Vec<_>>()
+            })
+            .collect();
+
+        paginate(entries, after, before, first, last).await
+    }
+}
+
+#[derive(SimpleObject)]
+pub(crate) struct MaterialHistoryEntry {
+    event_time_minutes: u32,
+    fulfilled: u32,
+}
+
+/// used in the complexity expressions of the connection fields
+fn page_size(first: Option<i32>, last: Option<i32>) -> usize {
+    first
+        .or(last)
+        .and_then(|n| usize::try_from(n).ok())
+        .unwrap_or(DEFAULT_PAGE_SIZE)
+        .min(MAX_PAGE_SIZE)
+}
+
+/// Cursor pagination over an already loaded list. The cursor is the index in the list.
+async fn paginate<T: OutputType>(
+    items: Vec<T>,
+    after: Option<String>,
+    before: Option<String>,
+    first: Option<i32>,
+    last: Option<i32>,
+) -> async_graphql::Result<Connection<usize, T>> {
+    query(
+        after,
+        before,
+        first,
+        last,
+        |after: Option<usize>, before: Option<usize>, first, last| async move {
+            if first.or(last).is_some_and(|n| n > MAX_PAGE_SIZE) {
+                return Err(async_graphql::Error::new(format!(
+                    "at most {MAX_PAGE_SIZE} entries can be requested per page"
+                )));
+            }
+
+            let num_items = items.len();
+            let mut start = after.map(|idx| idx + 1).unwrap_or(0).min(num_items);
+            let mut end = before.unwrap_or(num_items).clamp(start, num_items);
+
+            match (first, last) {
+                (Some(first), _) => end = end.min(start + first),
+                (None, Some(last)) => start = start.max(end.saturating_sub(last)),
+                (None, None) => end = end.min(start + DEFAULT_PAGE_SIZE),
+            }
+            if let (Some(_), Some(last)) = (first, last) {
+                start = start.max(end.saturating_sub(last));
+            }
+
+            let mut connection = Connection::new(start > 0, end < num_items);
+            connection.edges.extend(
+                items
+                    .into_iter()
+                    .enumerate()
+                    .skip(start)
+                    .take(end - start)
+                    .map(|(idx, item)| Edge::new(idx, item)),
+            );
+            Ok::<_, async_graphql::Error>(connection)
+        },
+    )
+    .await
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    async fn page(
+        after: Option<usize>,
+        before: Option<usize>,
+        first: Option<i32>,
+        last: Option<i32>,
+    ) -> (Vec<usize>, bool, bool) {
+        let connection = paginate(
+            (0..10).collect(),
+            after.map(|idx| idx.to_string()),
+            before.map(|idx| idx.to_string()),
+            first,
+            last,
+        )
+        .await
+        .unwrap();
+        (
+            connection.edges.into_iter().map(|edge| edge.node).collect(),
+            connection.has_previous_page,
+            connection.has_next_page,
+        )
+    }
+
+    #[tokio::test]
+    async fn paginate_first_and_last() {
+        assert_eq!(
+            page(None, None, Some(3), None).await,
+            (vec![0, 1, 2], false, true)
+        );
+        assert_eq!(
+            page(None, None, None, Some(3)).await,
+            (vec![7, 8, 9], true, false)
+        );
+        // last is applied to the result of first
+        assert_eq!(
+            page(None, None, Some(5), Some(2)).await,
+            (vec![3, 4], true, true)
+        );
+        assert_eq!(
+            page(None, None, Some(2), Some(5)).await,
+            (vec![0, 1], false, true)
+        );
+    }
+
+    #[tokio::test]
+    async fn paginate_clamps_after_and_before() {
+        assert_eq!(
+            page(Some(2), Some(6), None, None).await,
+            (vec![3, 4, 5], true, true)
+        );
+        assert_eq!(
+            page(Some(8), None, Some(5), None).await,
+            (vec![9], true, false)
+        );
+        assert_eq!(
+            page(None, Some(20), None, Some(2)).await,
+            (vec![8, 9], true, false)
+        );
+        assert_eq!(
+            page(Some(20), None, Some(2), None).await,
+            (vec![], true, false)
+        );
+        // before in front of after
+        assert_eq!(
+            page(Some(5), Some(1), None, None).await,
+            (vec![], true, true)
+        );
+    }
+
+    #[tokio::test]
+    async fn paginate_rejects_too_large_pages() {
+        let too_large = MAX_PAGE_SIZE as i32 + 1;
+        for (first, last) in [(Some(too_large), None), (None, Some(too_large))] {
+            assert!(
+                paginate((0..10).collect::<Vec<usize>>(), None, None, first, last)
+                    .await
+                    .is_err()
+            );
+        }
+    }
+
+    #[tokio::test]
+    async fn rejects_too_deep_queries() {
+        let mut selection = "agentSymbol".to_string();
+        for _ in 0..MAX_QUERY_DEPTH {
+            selection = format!("constructionSite {{ agents {{ {selection} }} }}");
+        }
+        let query = format!(
+            "{{ reset(resetDate: \"2024-03-24\") {{ agent(agentSymbol: \"FLWI\") {{ {selection} }} }} }}"
+        );
+
+        let response = build_schema().execute(query).await;
+        assert_eq!(response.errors.len(), 1);
+        assert!(response.errors[0].message.contains("nested too deep"));
+    }
+
+    #[tokio::test]
+    async fn rejects_too_complex_queries() {
+        let query = "{ resets(first: 100) { edges { node { agents(first: 100) { edges { node { agentSymbol } } } } } } }";
+
+        let response = build_schema().execute(query).await;
+        assert_eq!(response.errors.len(), 1);
+        assert!(response.errors[0].message.contains("too complex"));
+    }
+}
@@ -0,0 +1,179 @@
+use std::collections::HashSet;
+
+use axum::extract::State;
+use axum::http::StatusCode;
+use axum::response::{IntoResponse, Response};
+use axum::{routing, Json, Router};
+use chrono::{TimeDelta, Utc};
+use serde::Serialize;
+use sqlx::migrate::Migrator;
+use sqlx::{Pool, Sqlite};
+
+use crate::collector_control::CollectorControl;
+use crate::db::select_latest_job_run;
+use crate::http_cache::TICK_INTERVAL_SECONDS;
+use crate::server::AppState;
+
+static MIGRATOR: Migrator = sqlx::migrate!();
+
+#[derive(Debug, Clone)]
+pub(crate) struct HealthSettings {
+    /// `/readyz` reports degraded if the newest job_run is older than this many tick intervals
+    pub(crate) max_missed_ticks: u32,
+}
+
+#[derive(Serialize, Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+#[serde(rename_all = "camelCase")]
+enum CheckStatus {
+    Ok,
+    Degraded,
+    Failing,
+}
+
+#[derive(Serialize, Debug)]
+#[serde(rename_all = "camelCase")]
+struct HealthReport {
+    /// worst status of all checks
+    status: CheckStatus,
+    checks: Vec<HealthCheck>,
+}
+
+#[derive(Serialize, Debug)]
+#[serde(rename_all = "camelCase")]
+struct HealthCheck {
+    name: &'static str,
+    status: CheckStatus,
+    detail: String,
+}
+
+impl HealthCheck {
+    fn new(name: &'static str, status: CheckStatus, detail: impl Into<String>) -> Self {
+        Self {
+            name,
+            status,
+            detail: detail.into(),
+        }
+    }
+}
+
+impl IntoResponse for HealthReport {
+    fn into_response(self) -> Response {
+        let status = match self.status {
+            CheckStatus::Ok => StatusCode::OK,
+            CheckStatus::Degraded | CheckStatus::Failing => StatusCode::SERVICE_UNAVAILABLE,
+        };
+        (status, Json(self)).into_response()
+    }
+}
+
+impl HealthReport {
+    fn new(checks: Vec<HealthCheck>) -> Self {
+        let status = checks
+            .iter()
+            .map(|check| check.status)
+            .max()
+            .unwrap_or(CheckStatus::Ok);
+        Self { status, checks }
+    }
+}
+
+pub(crate) fn health_router() -> Router<AppState> {
+    Router::new()
+        .route("/healthz", routing::get(get_healthz))
+        .route("/readyz", routing::get(get_readyz))
+}
+
+/// Liveness: the process serves requests and can reach the database
+async fn get_healthz(State(pool): State<Pool<Sqlite>>) -> HealthReport {
+    HealthReport::new(vec![check_database(&pool).await])
+}
+
+/// Readiness: additionally checks that the collector keeps the data of the ongoing reset fresh and
+/// that all migrations have been applied
+async fn get_readyz(
+    State(pool): State<Pool<Sqlite>>,
+    State(settings): State<HealthSettings>,
+    State(collector_control): State<CollectorControl>,
+) -> HealthReport {
+    HealthReport::new(vec![
+        check_database(&pool).await,
+        check_migrations(&pool).await,
+        check_freshness(&pool, &settings, &collector_control).await,
+    ])
+}
+
+async fn check_database(pool: &Pool<Sqlite>) -> HealthCheck {
+    match sqlx::query("select 1").execute(pool).await {
+        Ok(_) => HealthCheck::new("database", CheckStatus::Ok, "reachable"),
+        Err(err) => HealthCheck::new("database", CheckStatus::Failing, err.to_string()),
+    }
+}
+
+async fn check_migrations(pool: &Pool<Sqlite>) -> HealthCheck {
+    // _sqlx_migrations is created by the migrator - not part of the schema sqlx checks at compile-time
+    let applied_versions: Vec<i64> =
+        match sqlx::query_scalar("select version from _sqlx_migrations where success")
+            .fetch_all(pool)
+            .await
+        {
+            Ok(versions) => versions,
+            Err(err) => {
+                return HealthCheck::new("migrations", CheckStatus::Failing, err.to_string())
+            }
+        };
+    let applied_versions: HashSet<i64> = applied_versions.into_iter().collect();
+
+    let pending: Vec<String> = MIGRATOR
+        .iter()
+        .filter(|migration| !applied_versions.contains(&migration.version))
+        .map(|migration| format!("{} {}", migration.version, migration.description))
+        .collect();
+
+    if pending.is_empty() {
+        HealthCheck::new("migrations", CheckStatus::Ok, "all migrations applied")
+    } else {
+        HealthCheck::new(
+            "migrations",
+            CheckStatus::Degraded,
+            format!("pending migrations: {}", pending.join(", ")),
+        )
+    }
+}
+
+async fn check_freshness(
+    pool: &Pool<Sqlite>,
+    settings: &HealthSettings,
+    collector_control: &CollectorControl,
+) -> HealthCheck {
+    let latest_job_run = match select_latest_job_run(pool).await {
+        Ok(Some(latest_job_run)) => latest_job_run,
+        Ok(None) => return HealthCheck::new("freshness", CheckStatus::Degraded, "no job runs yet"),
+        Err(err) => return HealthCheck::new("freshness", CheckStatus::Failing, err.to_string()),
+    };
+
+    let max_age = TimeDelta::seconds(TICK_INTERVAL_SECONDS * i64::from(settings.max_missed_ticks));
+    let age = Utc::now().naive_utc() - latest_job_run.query_time;
+    let paused_note = if collector_control.is_paused() {
+        " (collector is paused)"
+    } else {
+        ""
+    };
+    let detail = format!(
+        "newest job run of the ongoing reset is from {} ({} minutes ago){paused_note}",
+        latest_job_run.query_time,
+        age.num_minutes()
+    );
+
+    if age <= max_age {
+        HealthCheck::new("freshness", CheckStatus::Ok, detail)
+    } else {
+        HealthCheck::new(
+            "freshness",
+            CheckStatus::Degraded,
+            format!(
+                "{detail} - more than {} ticks missed",
+                settings.max_missed_ticks
+            ),
+        )
+    }
+}
@@ -0,0 +1,249 @@
+use std::collections::BTreeMap;
+
+use askama::Template;
+use axum::extract::State;
+use axum::{routing, Router};
+use chrono::{NaiveDate, NaiveDateTime};
+use itertools::Itertools;
+use sqlx::{Pool, Sqlite};
+
+use crate::api_error::{ApiError, ApiPath};
+use crate::badges::format_credits;
+use crate::db::{
+    load_leaderboard_for_reset, load_reset_dates, select_jump_gate_agent_assignment_for_reset,
+    select_most_recent_construction_progress_for_reset,
+};
+use crate::server::leaderboard::load_existing_reset;
+use crate::server::AppState;
+
+/// Server-rendered pages for clients without javascript (and crawlers).
+/// Below /html to not shadow the routes of the react app.
+pub(crate) fn html_router() -> Router<AppState> {
+    Router::new()
+        .route("/html/resets", routing::get(get_resets_page))
+        .route(
+            "/html/resets/:reset_date/leaderboard",
+            routing::get(get_leaderboard_page),
+        )
+        .route(
+            "/html/resets/:reset_date/jump-gate",
+            routing::get(get_jump_gates_page),
+        )
+        .route(
+            "/html/resets/:reset_date/agents/:agent_symbol",
+            routing::get(get_agent_page),
+        )
+}
+
+#[derive(Template)]
+#[template(path = "resets.html")]
+struct ResetsTemplate {
+    resets: Vec<ResetRow>,
+}
+
+struct ResetRow {
+    reset_date: NaiveDate,
+    first_ts: String,
+    latest_ts: String,
+    is_ongoing: bool,
+}
+
+#[derive(Template)]
+#[template(path = "leaderboard.html")]
+struct LeaderboardTemplate {
+    reset_date: NaiveDate,
+    latest_ts: String,
+    is_ongoing: bool,
+    entries: Vec<LeaderboardRow>,
+}
+
+struct LeaderboardRow {
+    rank: usize,
+    agent_symbol: String,
+    starting_faction: String,
+    jump_gate_waypoint_symbol: String,
+    credits: String,
+    ship_count: i64,
+}
+
+#[derive(Template)]
+#[template(path = "jump_gates.html")]
+struct JumpGatesTemplate {
+    reset_date: NaiveDate,
+    gates: Vec<JumpGateProgress>,
+}
+
+#[derive(Template)]
+#[template(path = "agent.html")]
+struct AgentTemplate {
+    reset_date: NaiveDate,
+    agent_symbol: String,
+    rank: usize,
+    num_agents: usize,
+    credits: String,
+    ship_count: i64,
+    starting_faction: String,
+    agent_headquarters_waypoint_symbol: String,
+    maybe_gate: Option<JumpGateProgress>,
+}
+
+struct JumpGateProgress {
+    jump_gate_waypoint_symbol: String,
+    agent_symbols: Vec<String>,
+    is_complete: bool,
+    materials: Vec<MaterialProgress>,
+}
+
+struct MaterialProgress {
+    trade_symbol: String,
+    fulfilled: i64,
+    required: i64,
+    percent: i64,
+}
+
+fn format_ts(ts: NaiveDateTime) -> String {
+    ts.format("%Y-%m-%d %H:%M UTC").to_string()
+}
+
+async fn get_resets_page(State(pool): State<Pool<Sqlite>>) -> Result<ResetsTemplate, ApiError> {
+    let resets = load_reset_dates(&pool)
+        .await?
+        .into_iter()
+        .sorted_by_key(|reset_date| std::cmp::Reverse(reset_date.reset))
+        .map(|reset_date| ResetRow {
+            reset_date: reset_date.reset,
+            first_ts: format_ts(reset_date.first_ts),
+            latest_ts: format_ts(reset_date.latest_ts),
+            is_ongoing: reset_date.is_ongoing,
+        })
+        .collect();
+
+    Ok(ResetsTemplate { resets })
+}
+
+async fn get_leaderboard_page(
+    State(pool): State<Pool<Sqlite>>,
+    ApiPath(reset_date): ApiPath<NaiveDate>,
+) -> Result<LeaderboardTemplate, ApiError> {
+    let reset = load_existing_reset(&pool, reset_date).await?;
+    let entries = load_leaderboard_for_reset(&pool, reset_date)
+        .await?
+        .into_iter()
+        .enumerate()
+        .map(|(idx, entry)| LeaderboardRow {
+            rank: idx + 1,
+            agent_symbol: entry.agent_symbol,
+            starting_faction: entry.starting_faction,
+            jump_gate_waypoint_symbol: entry.jump_gate_waypoint_symbol,
+            credits: format_credits(entry.credits),
+            ship_count: entry.ship_count,
+        })
+        .collect();
+
+    Ok(LeaderboardTemplate {
+        reset_date,
+        latest_ts: format_ts(reset.latest_ts),
+        is_ongoing: reset.is_ongoing,
+        entries,
+    })
+}
+
+async fn get_jump_gates_page(
+    State(pool): State<Pool<Sqlite>>,
+    ApiPath(reset_date): ApiPath<NaiveDate>,
+) -> Result<JumpGatesTemplate, ApiError> {
+    load_existing_reset(&pool, reset_date).await?;
+    let gates = load_jump_gate_progress(&pool, reset_date)
+        .await?
+        .into_values()
+        .collect();
+
+    Ok(JumpGatesTemplate { reset_date, gates })
+}
+
+async fn get_agent_page(
+    State(pool): State<Pool<Sqlite>>,
+    ApiPath((reset_date, agent_symbol)): ApiPath<(NaiveDate, String)>,
+) -> Result<AgentTemplate, ApiError> {
+    let agent_symbol = agent_symbol.to_uppercase();
+    load_existing_reset(&pool, reset_date).await?;
+    let leaderboard = load_leaderboard_for_reset(&pool, reset_date).await?;
+    let num_agents = leaderboard.len();
+    let (idx, entry) = leaderboard
+        .into_iter()
+        .find_position(|entry| entry.agent_symbol == agent_symbol)
+        .ok_or_else(|| {
+            ApiError::NotFound(format!(
+                "agent {agent_symbol} is not part of reset {reset_date}"
+            ))
+        })?;
+    let maybe_gate = load_jump_gate_progress(&pool, reset_date)
+        .await?
+        .remove(&entry.jump_gate_waypoint_symbol);
+
+    Ok(AgentTemplate {
+        reset_date,
+        agent_symbol: entry.agent_symbol,
+        rank: idx + 1,
+        num_agents,
+        credits: format_credits(entry.credits),
+        ship_count: entry.ship_count,
+        starting_faction: entry.starting_faction,
+        agent_headquarters_waypoint_symbol: entry.agent_headquarters_waypoint_symbol,
+        maybe_gate,
+    })
+}
+
+/// jump gates with construction progress, by waypoint symbol
+async fn load_jump_gate_progress(
+    pool: &Pool<Sqlite>,
+    reset_date: NaiveDate,
+) -> Result<BTreeMap<String, JumpGateProgress>, ApiError> {
+    // a jump gate can have agents from several headquarters
+    let agents_of_gate: BTreeMap<String, Vec<String>> =
+        select_jump_gate_agent_assignment_for_reset(pool, reset_date)
+            .await?
+            .into_iter()
+            .map(|entry| (entry.jump_gate_waypoint_symbol, entry.agents_in_system_csv))
+            .into_grouping_map()
+            .fold(Vec::new(), |mut agent_symbols, _, csv| {
+                agent_symbols.extend(csv.split(',').map(|s| s.to_string()));
+                agent_symbols
+            })
+            .into_iter()
+            .collect();
+
+    let gates = select_most_recent_construction_progress_for_reset(pool, reset_date)
+        .await?
+        .into_iter()
+        .into_group_map_by(|material| material.jump_gate_waypoint_symbol.clone())
+        .into_iter()
+        .map(|(jump_gate_waypoint_symbol, materials)| {
+            let gate = JumpGateProgress {
+                agent_symbols: agents_of_gate
+                    .get(&jump_gate_waypoint_symbol)
+                    .map(|agent_symbols| agent_symbols.iter().sorted().cloned().collect())
+                    .unwrap_or_default(),
+                is_complete: materials.iter().any(|m| m.is_jump_gate_complete),
+                materials: materials
+                    .into_iter()
+                    .sorted_by(|a, b| a.trade_symbol.cmp(&b.trade_symbol))
+                    .map(|m| MaterialProgress {
+                        percent: if m.required > 0 {
+                            m.fulfilled * 100 / m.required
+                        } else {
+                            0
+                        },
+                        trade_symbol: m.trade_symbol,
+                        fulfilled: m.fulfilled,
+                        required: m.required,
+                    })
+                    .collect(),
+                jump_gate_waypoint_symbol: jump_gate_waypoint_symbol.clone(),
+            };
+            (jump_gate_waypoint_symbol, gate)
+        })
+        .collect();
+
+    Ok(gates)
+}
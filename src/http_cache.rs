@@ -0,0 +1,289 @@
+use axum::extract::{Query, RawPathParams, Request, State};
+use axum::http::{header, HeaderMap, HeaderValue, Method, StatusCode};
+use axum::middleware::Next;
+use axum::response::{IntoResponse, Response};
+use chrono::{NaiveDate, NaiveDateTime, Utc};
+use serde::Deserialize;
+use sqlx::{Pool, Sqlite};
+
+use crate::api_error::ApiError;
+use crate::db::{
+    select_data_generation, select_latest_job_run, select_latest_job_run_of_reset,
+    DbDataGeneration, DbLatestJobRun,
+};
+
+/// the collector runs every 5 minutes - data can't change more often than that
+pub(crate) const TICK_INTERVAL_SECONDS: i64 = 5 * 60;
+
+/// data of a closed reset only changes by an import or a repair - clients revalidate it once an hour
+const CACHE_CONTROL_CLOSED_RESET: &str = "public, max-age=3600";
+
+const HTTP_DATE_FORMAT: &str = "%a, %d %b %Y %H:%M:%S GMT";
+
+/// Conditional GET for the read endpoints.
+///
+/// The ETag is derived from the latest job_run of the requested reset (path parameter `reset_date`)
+/// or of all resets for endpoints that aggregate over all resets. Requests with a matching
+/// `If-None-Match` (or a non-outdated `If-Modified-Since`) get a 304 without running the handler.
+/// Requests that reference another reset (query parameter `referenceResetDate`) depend on its
+/// latest job_run as well. Changes that don't add a job_run (import, repair, refresh of the
+/// materialized views) bump the data generation, which is part of every ETag.
+pub(crate) async fn conditional_get(
+    State(pool): State<Pool<Sqlite>>,
+    maybe_path_params: Option<RawPathParams>,
+    request: Request,
+    next: Next,
+) -> Result<Response, ApiError> {
+    if request.method() != Method::GET && request.method() != Method::HEAD {
+        return Ok(next.run(request).await);
+    }
+
+    let maybe_reset_date: Option<NaiveDate> = maybe_path_params.and_then(|params| {
+        params
+            .iter()
+            .find(|(key, _)| *key == "reset_date")
+            .and_then(|(_, value)| value.parse().ok())
+    });
+
+    let maybe_reference_reset_date = Query::<ReferenceResetParams>::try_from_uri(request.uri())
+        .ok()
+        .and_then(|Query(params)| params.reference_reset_date)
+        .filter(|reference_reset_date| Some(*reference_reset_date) != maybe_reset_date);
+
+    let maybe_latest_job_run = match maybe_reset_date {
+        Some(reset_date) => select_latest_job_run_of_reset(&pool, reset_date).await?,
+        None => select_latest_job_run(&pool).await?,
+    };
+
+    // unknown reset or empty db - let the handler deal with it
+    let Some(latest_job_run) = maybe_latest_job_run else {
+        return Ok(next.run(request).await);
+    };
+
+    let maybe_reference = match maybe_reference_reset_date {
+        Some(reference_reset_date) => {
+            match select_latest_job_run_of_reset(&pool, reference_reset_date).await? {
+                Some(reference_job_run) => Some((reference_reset_date, reference_job_run)),
+                // unknown reference reset - the handler rejects the request
+                None => return Ok(next.run(request).await),
+            }
+        }
+        None => None,
+    };
+
+    let data_generation = select_data_generation(&pool).await?;
+    let validator = CacheValidator::new(
+        maybe_reset_date,
+        data_generation,
+        latest_job_run,
+        maybe_reference,
+    );
+
+    if validator.is_fresh(request.headers()) {
+        let mut response = StatusCode::NOT_MODIFIED.into_response();
+        validator.apply_headers(response.headers_mut());
+        return Ok(response);
+    }
+
+    let mut response = next.run(request).await;
+    if response.status().is_success() {
+        validator.apply_headers(response.headers_mut());
+    }
+    Ok(response)
+}
+
+#[derive(Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct ReferenceResetParams {
+    reference_reset_date: Option<NaiveDate>,
+}
+
+struct CacheValidator {
+    etag: String,
+    last_modified: NaiveDateTime,
+    cache_control: String,
+}
+
+impl CacheValidator {
+    fn new(
+        maybe_reset_date: Option<NaiveDate>,
+        data_generation: DbDataGeneration,
+        latest_job_run: DbLatestJobRun,
+        maybe_reference: Option<(NaiveDate, DbLatestJobRun)>,
+    ) -> Self {
+        let scope = maybe_reset_date
+            .map(|reset_date| reset_date.format("%Y-%m-%d").to_string())
+            .unwrap_or("all".to_string());
+
+        let is_closed_reset = maybe_reset_date.is_some()
+            && !latest_job_run.is_ongoing
+            && maybe_reference.is_none_or(|(_, reference)| !reference.is_ongoing);
+
+        let cache_control = if is_closed_reset {
+            CACHE_CONTROL_CLOSED_RESET.to_string()
+        } else {
+            format!("public, max-age={}", seconds_until_next_tick())
+        };
+
+        let generation = data_generation.generation;
+        let (etag, last_modified) = match maybe_reference {
+            Some((reference_reset_date, reference)) => (
+                format!(
+                    "W/\"{scope}-{generation}-{}-{}-{}\"",
+                    latest_job_run.job_run_id,
+                    reference_reset_date.format("%Y-%m-%d"),
+                    reference.job_run_id
+                ),
+                latest_job_run.query_time.max(reference.query_time),
+            ),
+            None => (
+                format!("W/\"{scope}-{generation}-{}\"", latest_job_run.job_run_id),
+                latest_job_run.query_time,
+            ),
+        };
+        let last_modified = last_modified.max(data_generation.updated_at);
+
+        Self {
+            etag,
+            last_modified,
+            cache_control,
+        }
+    }
+
+    fn is_fresh(&self, request_headers: &HeaderMap) -> bool {
+        // If-None-Match takes precedence over If-Modified-Since (RFC 9110 13.2.2)
+        if let Some(if_none_match) = request_headers.get(header::IF_NONE_MATCH) {
+            return if_none_match.to_str().is_ok_and(|value| {
+                value
+                    .split(',')
+                    .map(|tag| tag.trim())
+                    .any(|tag| tag == "*" || weak_eq(tag, &self.etag))
+            });
+        }
+
+        request_headers
+            .get(header::IF_MODIFIED_SINCE)
+            .and_then(|value| value.to_str().ok())
+            .and_then(|value| NaiveDateTime::parse_from_str(value, HTTP_DATE_FORMAT).ok())
+            .is_some_and(|if_modified_since| self.last_modified <= if_modified_since)
+    }
+
+    fn apply_headers(&self, headers: &mut HeaderMap) {
+        let values = [
+            (header::ETAG, self.etag.clone()),
+            (
+                header::LAST_MODIFIED,
+                self.last_modified.format(HTTP_DATE_FORMAT).to_string(),
+            ),
+            (header::CACHE_CONTROL, self.cache_control.clone()),
+        ];
+        for (name, value) in values {
+            if let Ok(value) = HeaderValue::from_str(&value) {
+                headers.insert(name, value);
+            }
+        }
+    }
+}
+
+/// weak comparison - ignores the W/ prefix
+fn weak_eq(a: &str, b: &str) -> bool {
+    a.trim_start_matches("W/") == b.trim_start_matches("W/")
+}
+
+fn seconds_until_next_tick() -> i64 {
+    let now = Utc::now().timestamp();
+    TICK_INTERVAL_SECONDS - now.rem_euclid(TICK_INTERVAL_SECONDS)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn ts(value: &str) -> NaiveDateTime {
+        NaiveDateTime::parse_from_str(value, "%Y-%m-%d %H:%M:%S").unwrap()
+    }
+
+    fn job_run(job_run_id: i64, query_time: &str, is_ongoing: bool) -> DbLatestJobRun {
+        DbLatestJobRun {
+            job_run_id,
+            query_time: ts(query_time),
+            is_ongoing,
+        }
+    }
+
+    fn data_generation(generation: i64, updated_at: &str) -> DbDataGeneration {
+        DbDataGeneration {
+            generation,
+            updated_at: ts(updated_at),
+        }
+    }
+
+    #[test]
+    fn closed_reset_is_cached_longer_unless_it_references_an_ongoing_one() {
+        let closed_reset = NaiveDate::from_ymd_opt(2024, 3, 10).unwrap();
+        let ongoing_reset = NaiveDate::from_ymd_opt(2024, 3, 24).unwrap();
+        let closed = job_run(10, "2024-03-23 23:55:00", false);
+        let ongoing = job_run(20, "2024-03-25 12:00:00", true);
+        let generation = data_generation(0, "2024-03-01 00:00:00");
+
+        let validator = CacheValidator::new(Some(closed_reset), generation, closed, None);
+        assert_eq!(validator.cache_control, CACHE_CONTROL_CLOSED_RESET);
+        assert_eq!(validator.etag, "W/\"2024-03-10-0-10\"");
+
+        let validator = CacheValidator::new(
+            Some(closed_reset),
+            generation,
+            closed,
+            Some((ongoing_reset, ongoing)),
+        );
+        assert_ne!(validator.cache_control, CACHE_CONTROL_CLOSED_RESET);
+        assert_eq!(validator.etag, "W/\"2024-03-10-0-10-2024-03-24-20\"");
+        assert_eq!(validator.last_modified, ongoing.query_time);
+
+        let validator = CacheValidator::new(
+            Some(ongoing_reset),
+            generation,
+            ongoing,
+            Some((closed_reset, closed)),
+        );
+        assert_ne!(validator.cache_control, CACHE_CONTROL_CLOSED_RESET);
+    }
+
+    #[test]
+    fn bumped_data_generation_invalidates_the_validator() {
+        let closed_reset = NaiveDate::from_ymd_opt(2024, 3, 10).unwrap();
+        let closed = job_run(10, "2024-03-23 23:55:00", false);
+
+        let before = CacheValidator::new(
+            Some(closed_reset),
+            data_generation(0, "2024-03-01 00:00:00"),
+            closed,
+            None,
+        );
+        let mut request_headers = HeaderMap::new();
+        before.apply_headers(&mut request_headers);
+        let if_none_match = request_headers.get(header::ETAG).unwrap().clone();
+        let if_modified_since = request_headers.get(header::LAST_MODIFIED).unwrap().clone();
+        assert!(before.is_fresh(&HeaderMap::from_iter([(
+            header::IF_NONE_MATCH,
+            if_none_match.clone()
+        )])));
+
+        // e.g. an import of the same reset from another instance
+        let after = CacheValidator::new(
+            Some(closed_reset),
+            data_generation(1, "2024-04-02 10:00:00"),
+            closed,
+            None,
+        );
+        assert_eq!(after.last_modified, ts("2024-04-02 10:00:00"));
+        assert!(!after.is_fresh(&HeaderMap::from_iter([(
+            header::IF_NONE_MATCH,
+            if_none_match
+        )])));
+        assert!(!after.is_fresh(&HeaderMap::from_iter([(
+            header::IF_MODIFIED_SINCE,
+            if_modified_since
+        )])));
+    }
+}
@@ -0,0 +1,421 @@
+use std::collections::{BTreeMap, HashMap, HashSet};
+use std::fmt::{Display, Formatter};
+use std::path::Path;
+
+use anyhow::{anyhow, Context, Result};
+use chrono::NaiveDate;
+use sqlx::sqlite::SqliteConnectOptions;
+use sqlx::{Pool, Sqlite, SqliteConnection};
+use tracing::{event, Level};
+
+use crate::db::{
+    bump_data_generation, insert_agent_for_import, insert_agent_log_for_import,
+    insert_construction_log_for_import, insert_construction_material_log_for_import,
+    insert_construction_requirement_for_import, insert_job_run_for_import, insert_reset_for_import,
+    load_reset_dates, refresh_fake_materialized_view, refresh_material_delivery_events_for_reset,
+    select_agents_for_import, select_construction_requirements_for_import,
+    select_job_runs_for_import, select_reset_for_import, upsert_construction_site_for_import,
+};
+use crate::export::{load_reset_export, ResetExport, MANIFEST_FILE_NAME};
+
+/// Outcome of importing one reset. Conflicts are reported, but don't abort the import -
+/// the data of the target database wins.
+#[derive(Debug, Clone, Default)]
+pub(crate) struct ImportReport {
+    pub(crate) reset_date: NaiveDate,
+    pub(crate) reset_created: bool,
+    pub(crate) agents_created: usize,
+    pub(crate) construction_requirements_created: usize,
+    pub(crate) job_runs_imported: usize,
+    pub(crate) job_runs_skipped: usize,
+    pub(crate) conflicts: Vec<String>,
+}
+
+impl Display for ImportReport {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        writeln!(
+            f,
+            "reset {}{}: imported {} job runs, skipped {} already existing job runs",
+            self.reset_date,
+            if self.reset_created { " (new)" } else { "" },
+            self.job_runs_imported,
+            self.job_runs_skipped,
+        )?;
+        writeln!(
+            f,
+            "  created {} agents, {} construction requirements",
+            self.agents_created, self.construction_requirements_created,
+        )?;
+        for conflict in &self.conflicts {
+            writeln!(f, "  conflict: {conflict}")?;
+        }
+        Ok(())
+    }
+}
+
+/// Reads the resets to import from `source`, which is either an export bundle
+/// (extracted directory or zip archive) or the sqlite database of another instance.
+pub(crate) async fn load_import_source(
+    source: &Path,
+    maybe_reset_date: Option<NaiveDate>,
+) -> Result<Vec<ResetExport>> {
+    let is_bundle = source.join(MANIFEST_FILE_NAME).is_file()
+        || source.extension().is_some_and(|ext| ext == "zip");
+
+    if is_bundle {
+        let source = source.to_path_buf();
+        let export = tokio::task::spawn_blocking(move || ResetExport::read_bundle(&source))
+            .await?
+            .context("failed to read export bundle")?;
+
+        return match maybe_reset_date {
+            Some(reset_date) if reset_date != export.reset_date => Err(anyhow!(
+                "bundle contains reset {}, not {reset_date}",
+                export.reset_date
+            )),
+            _ => Ok(vec![export]),
+        };
+    }
+
+    let options = SqliteConnectOptions::new().filename(source).read_only(true);
+    let source_pool = Pool::<Sqlite>::connect_with(options)
+        .await
+        .with_context(|| format!("failed to open {} as database", source.display()))?;
+
+    let mut exports = vec![];
+    for reset in load_reset_dates(&source_pool).await? {
+        if maybe_reset_date.is_some_and(|reset_date| reset_date != reset.reset) {
+            continue;
+        }
+        if let Some(export) = load_reset_export(&source_pool, reset.reset).await? {
+            exports.push(export);
+        }
+    }
+    source_pool.close().await;
+
+    Ok(exports)
+}
+
+/// Imports the resets into the target database. Each reset is imported in its own transaction,
+/// which is rolled back if `dry_run` is set.
+pub(crate) async fn import_resets(
+    pool: &Pool<Sqlite>,
+    exports: &[ResetExport],
+    dry_run: bool,
+) -> Result<Vec<ImportReport>> {
+    let mut reports = vec![];
+    for export in exports {
+        let mut transaction = pool.begin().await?;
+        let report = import_reset(&mut transaction, export)
+            .await
+            .with_context(|| format!("failed to import reset {}", export.reset_date))?;
+
+        if dry_run {
+            transaction.rollback().await?;
+        } else {
+            transaction.commit().await?;
+        }
+
+        event!(
+            Level::INFO,
+            "Imported reset {} ({} job runs, {} skipped, {} conflicts){}",
+            report.reset_date,
+            report.job_runs_imported,
+            report.job_runs_skipped,
+            report.conflicts.len(),
+            if dry_run { " - dry run" } else { "" }
+        );
+        reports.push(report);
+    }
+
+    if !dry_run && reports.iter().any(|r| r.job_runs_imported > 0) {
+        refresh_fake_materialized_view(pool).await?;
+    }
+
+    Ok(reports)
+}
+
+async fn import_reset(conn: &mut SqliteConnection, export: &ResetExport) -> Result<ImportReport> {
+    let mut report = ImportReport {
+        reset_date: export.reset_date,
+        ..ImportReport::default()
+    };
+
+    let (reset_id, first_ts) = match select_reset_for_import(conn, export.reset_date).await? {
+        Some((reset_id, first_ts)) => {
+            if first_ts != export.ts_start_of_reset {
+                report.conflicts.push(format!(
+                    "start of reset differs (target: {first_ts}, source: {}). event_time_minutes get recalculated",
+                    export.ts_start_of_reset
+                ));
+            }
+            (reset_id, first_ts)
+        }
+        None => {
+            report.reset_created = true;
+            let reset_id =
+                insert_reset_for_import(conn, export.reset_date, export.ts_start_of_reset).await?;
+            (reset_id, export.ts_start_of_reset)
+        }
+    };
+
+    // construction sites are unique per reset, so they can be upserted
+    let mut construction_site_id_lookup: HashMap<i64, i64> = HashMap::new();
+    for site in &export.construction_sites {
+        let target_id =
+            upsert_construction_site_for_import(conn, reset_id, &site.jump_gate_waypoint_symbol)
+                .await?;
+        construction_site_id_lookup.insert(site.construction_site_id, target_id);
+    }
+
+    let existing_requirements = select_construction_requirements_for_import(conn, reset_id).await?;
+    let mut requirement_id_lookup: HashMap<i64, i64> = HashMap::new();
+    for requirement in &export.construction_requirements {
+        let target_id = match existing_requirements
+            .iter()
+            .find(|r| r.trade_symbol == requirement.trade_symbol)
+        {
+            Some(existing) => {
+                if existing.required != requirement.required {
+                    report.conflicts.push(format!(
+                        "requirement for {} differs (target: {}, source: {})",
+                        requirement.trade_symbol, existing.required, requirement.required
+                    ));
+                }
+                existing.id
+            }
+            None => {
+                report.construction_requirements_created += 1;
+                insert_construction_requirement_for_import(
+                    conn,
+                    reset_id,
+                    &requirement.trade_symbol,
+                    requirement.required,
+                )
+                .await?
+            }
+        };
+        requirement_id_lookup.insert(requirement.construction_requirement_id, target_id);
+    }
+
+    let existing_agents = select_agents_for_import(conn, reset_id).await?;
+    let mut agent_id_lookup: HashMap<i64, i64> = HashMap::new();
+    for agent in &export.agents {
+        let target_id = match existing_agents
+            .iter()
+            .find(|a| a.agent_symbol == agent.agent_symbol)
+        {
+            Some(existing) => {
+                if existing.agent_headquarters_waypoint_symbol
+                    != agent.agent_headquarters_waypoint_symbol
+                    || existing.starting_faction != agent.starting_faction
+                {
+                    report.conflicts.push(format!(
+                        "agent {} differs (target: {}/{}, source: {}/{})",
+                        agent.agent_symbol,
+                        existing.agent_headquarters_waypoint_symbol,
+                        existing.starting_faction,
+                        agent.agent_headquarters_waypoint_symbol,
+                        agent.starting_faction
+                    ));
+                }
+                existing.agent_id
+            }
+            None => {
+                let construction_site_id = *construction_site_id_lookup
+                    .get(&agent.construction_site_id)
+                    .ok_or_else(|| {
+                        anyhow!(
+                            "agent {} references unknown construction site {}",
+                            agent.agent_symbol,
+                            agent.construction_site_id
+                        )
+                    })?;
+                report.agents_created += 1;
+                insert_agent_for_import(conn, reset_id, construction_site_id, agent).await?
+            }
+        };
+        agent_id_lookup.insert(agent.agent_id, target_id);
+    }
+
+    // overlapping job runs are detected by their query_time - the target database wins
+    let existing_query_times: HashSet<_> = select_job_runs_for_import(conn, reset_id)
+        .await?
+        .into_iter()
+        .map(|jr| jr.query_time)
+        .collect();
+
+    let mut job_run_id_lookup: HashMap<i64, i64> = HashMap::new();
+    for job_run in &export.job_runs {
+        if existing_query_times.contains(&job_run.query_time) {
+            report.job_runs_skipped += 1;
+            continue;
+        }
+        let event_time_minutes = (job_run.query_time - first_ts).num_minutes();
+        let target_id =
+            insert_job_run_for_import(conn, reset_id, job_run.query_time, event_time_minutes)
+                .await?;
+        job_run_id_lookup.insert(job_run.job_run_id, target_id);
+        report.job_runs_imported += 1;
+    }
+
+    for agent_log in &export.agent_logs {
+        let Some(job_run_id) = job_run_id_lookup.get(&agent_log.job_run_id) else {
+            continue;
+        };
+        let agent_id = agent_id_lookup
+            .get(&agent_log.agent_id)
+            .ok_or_else(|| anyhow!("agent log references unknown agent {}", agent_log.agent_id))?;
+        insert_agent_log_for_import(
+            conn,
+            *agent_id,
+            *job_run_id,
+            agent_log.credits,
+            agent_log.ship_count,
+        )
+        .await?;
+    }
+
+    // one construction_log per job run and construction site, one material log per requirement
+    let mut material_logs_by_construction_log: BTreeMap<(i64, i64), Vec<_>> = BTreeMap::new();
+    for material_log in &export.construction_material_logs {
+        if job_run_id_lookup.contains_key(&material_log.job_run_id) {
+            material_logs_by_construction_log
+                .entry((material_log.job_run_id, material_log.construction_site_id))
+                .or_default()
+                .push(material_log);
+        }
+    }
+
+    for ((source_job_run_id, source_construction_site_id), material_logs) in
+        material_logs_by_construction_log
+    {
+        let construction_site_id = construction_site_id_lookup
+            .get(&source_construction_site_id)
+            .ok_or_else(|| {
+                anyhow!("construction log references unknown construction site {source_construction_site_id}")
+            })?;
+        let is_complete = material_logs.iter().all(|m| m.is_jump_gate_complete);
+        let construction_log_id = insert_construction_log_for_import(
+            conn,
+            job_run_id_lookup[&source_job_run_id],
+            *construction_site_id,
+            is_complete,
+        )
+        .await?;
+
+        for material_log in material_logs {
+            let requirement_id = requirement_id_lookup
+                .get(&material_log.construction_requirement_id)
+                .ok_or_else(|| {
+                    anyhow!(
+                        "construction material log references unknown requirement {}",
+                        material_log.construction_requirement_id
+                    )
+                })?;
+            insert_construction_material_log_for_import(
+                conn,
+                construction_log_id,
+                *requirement_id,
+                material_log.fulfilled,
+            )
+            .await?;
+        }
+    }
+
+    if report.job_runs_imported > 0 {
+        refresh_material_delivery_events_for_reset(conn, reset_id).await?;
+        bump_data_generation(conn).await?;
+    }
+
+    Ok(report)
+}
+
+#[cfg(test)]
+mod tests {
+    use sqlx::Executor;
+
+    use super::*;
+    use crate::db::seeded_test_pool;
+
+    /// in-memory db with one reset, one agent and the construction progress of its jump gate
+    async fn seeded_pool(reset: &str, fulfilled: [i64; 2]) -> Pool<Sqlite> {
+        seeded_test_pool(
+            &format!(
+                "
+insert into reset_date (reset_id, reset, first_ts) values (1, '{reset}', '{reset} 15:00:00');
+insert into construction_site (id, reset_id, jump_gate_waypoint_symbol) values (1, 1, 'X1-AA-JG');
+insert into construction_requirement (id, reset_id, trade_symbol, required) values (1, 1, 'FAB_MATS', 1600);
+insert into static_agent_info (id, agent_symbol, agent_headquarters_waypoint_symbol, construction_site_id, starting_faction, reset_id, query_time)
+values (1, 'FLWI', 'X1-AA-A1', 1, 'COSMIC', 1, '{reset} 15:00:00');
+insert into job_run (id, reset_id, query_time, event_time_minutes)
+values (1, 1, '{reset} 16:00:00', 60), (2, 1, '{reset} 16:05:00', 65);
+insert into agent_log (agent_id, job_id, credits, ship_count) values (1, 1, 175000, 2), (1, 2, 180000, 2);
+insert into construction_log (id, job_id, construction_site_id, is_complete) values (1, 1, 1, false), (2, 2, 1, false);
+insert into construction_material_log (construction_log_id, construction_requirement_id, fulfilled)
+values (1, 1, {}), (2, 1, {});
+",
+                fulfilled[0], fulfilled[1]
+            ),
+        )
+        .await
+    }
+
+    async fn delivery_events(pool: &Pool<Sqlite>) -> Vec<(String, String)> {
+        sqlx::query_as(
+            "
+select r.reset, e.delivery_event
+  from mat_view_material_delivery_events e
+  join reset_date r on r.reset_id = e.reset_id
+ order by r.reset, e.query_time
+            ",
+        )
+        .fetch_all(pool)
+        .await
+        .unwrap()
+    }
+
+    #[tokio::test]
+    async fn importing_an_older_reset_keeps_refreshing_the_latest_one() {
+        let target = seeded_pool("2024-03-24", [0, 800]).await;
+        let source = seeded_pool("2024-03-10", [100, 1600]).await;
+        let export = load_reset_export(&source, NaiveDate::from_ymd_opt(2024, 3, 10).unwrap())
+            .await
+            .unwrap()
+            .unwrap();
+
+        let reports = import_resets(&target, &[export], false).await.unwrap();
+        assert!(reports[0].reset_created);
+        assert_eq!(reports[0].job_runs_imported, 2);
+
+        // the imported reset got the higher reset_id, but is not the latest one
+        let (imported_reset_id,): (i64,) =
+            sqlx::query_as("select reset_id from reset_date where reset = '2024-03-10'")
+                .fetch_one(&target)
+                .await
+                .unwrap();
+        assert_eq!(imported_reset_id, 2);
+
+        // next tick of the ongoing reset completes the delivery
+        target
+            .execute(
+                "
+insert into job_run (id, reset_id, query_time, event_time_minutes) values (10, 1, '2024-03-24 16:10:00', 70);
+insert into construction_log (id, job_id, construction_site_id, is_complete) values (10, 10, 1, false);
+insert into construction_material_log (construction_log_id, construction_requirement_id, fulfilled) values (10, 1, 1600);
+",
+            )
+            .await
+            .unwrap();
+        refresh_fake_materialized_view(&target).await.unwrap();
+
+        assert_eq!(
+            delivery_events(&target).await,
+            vec![
+                ("2024-03-10".to_string(), "last".to_string()),
+                ("2024-03-24".to_string(), "first".to_string()),
+                ("2024-03-24".to_string(), "last".to_string()),
+            ]
+        );
+    }
+}
@@ -1,5 +1,6 @@
-use std::collections::HashSet;
+use std::collections::{HashMap, HashSet};
 
+use crate::app_metrics::record_inserted_rows;
 use crate::db;
 use crate::db::*;
 use crate::leaderboard_model::*;
@@ -7,17 +8,33 @@ use crate::model::*;
 use crate::pagination::paginate;
 use crate::st_client::StClient;
 use anyhow::Context;
-use chrono::{DateTime, Duration, DurationRound, Local, NaiveDate, SubsecRound, Timelike};
+use chrono::{
+    DateTime, Duration, DurationRound, Local, NaiveDate, NaiveDateTime, SubsecRound, Timelike,
+};
 use futures::future::join_all;
 use itertools::Itertools;
+use serde::Serialize;
 use sqlx::{Pool, Sqlite};
-use tracing::{event, Level};
+use tracing::field::Empty;
+use tracing::{debug_span, event, Instrument, Level, Span};
+
+/// Published after a tick has written its data
+#[derive(Serialize, Debug, Clone, Copy)]
+#[serde(rename_all = "camelCase")]
+pub(crate) struct TickCompleted {
+    pub(crate) reset_date: NaiveDate,
+    pub(crate) query_time: NaiveDateTime,
+}
 
-pub async fn perform_tick(client: &StClient, pool: Pool<Sqlite>) -> anyhow::Result<()> {
-    let st_status = client.get_status().await?;
+#[tracing::instrument(level = "info", skip_all, fields(reset = Empty))]
+pub async fn perform_tick(client: &StClient, pool: Pool<Sqlite>) -> anyhow::Result<TickCompleted> {
+    let st_status = client
+        .get_status()
+        .instrument(debug_span!("tick.fetch_status"))
+        .await?;
 
-    event!(Level::INFO, "Reset Date: {:?}", st_status.reset_date);
-    event!(Level::INFO, "{:?}", st_status.stats);
+    Span::current().record("reset", st_status.reset_date.as_str());
+    event!(Level::DEBUG, stats = ?st_status.stats, "Got status of the SpaceTraders server");
 
     let now = Local::now()
         .naive_utc()
@@ -26,98 +43,186 @@ pub async fn perform_tick(client: &StClient, pool: Pool<Sqlite>) -> anyhow::Resu
 
     let reset_date = NaiveDate::parse_from_str(st_status.reset_date.as_str(), "%Y-%m-%d").unwrap();
 
-    let reset_date_db = load_or_create_reset_date(&pool, reset_date, now).await?;
-    event!(Level::INFO, "Using reset_date_db {:?}", reset_date_db);
-    let static_agent_infos: Vec<DbStaticAgentInfo> =
-        select_static_agent_infos_for_reset(&pool, reset_date_db).await?;
+    let (reset_date_db, static_agent_infos, construction_sites) =
+        update_static_infos(client, &pool, st_status, reset_date, now)
+            .instrument(debug_span!("tick.update_static_infos"))
+            .await?;
+
+    let num_agents = static_agent_infos.len();
+    let num_construction_sites = construction_sites.len();
 
     event!(
         Level::INFO,
-        "Got {num} static_agent_infos",
-        num = static_agent_infos.len(),
+        num_agents,
+        num_construction_sites,
+        "Downloading current infos of agents and construction sites",
     );
 
-    let new_agent_symbols = determine_missing_agent_symbols(st_status, static_agent_infos);
+    let (current_agent_entries, current_construction_entries) =
+        collect_data(client, static_agent_infos.clone(), construction_sites)
+            .instrument(debug_span!("tick.collect_data"))
+            .await
+            .context("failed at collect_data")?;
+
+    // the static infos of known agents are only downloaded again if their HQ or faction changed
+    let changed_agent_symbols =
+        determine_changed_agent_symbols(&static_agent_infos, &current_agent_entries);
+    if !changed_agent_symbols.is_empty() {
+        event!(
+            Level::INFO,
+            changed_agent_symbols = changed_agent_symbols.join(", "),
+            "Found agents with changed static infos",
+        );
+        save_static_agent_infos_of(client, &pool, reset_date_db, changed_agent_symbols, now)
+            .instrument(debug_span!("tick.update_changed_static_infos"))
+            .await?;
+    }
+
+    async {
+        let db_construction_infos = select_construction_sites_for_reset(&pool, reset_date_db)
+            .await
+            .context("failed at select_construction_sites_for_reset")?;
+
+        let inserted_rows = insert_job_run_and_details(
+            &pool,
+            now,
+            reset_date_db,
+            current_agent_entries,
+            static_agent_infos.clone(),
+            current_construction_entries,
+            db_construction_infos,
+        )
+        .await
+        .context("failed at insert_job_run_and_details")?;
+        record_inserted_rows(&inserted_rows);
+        anyhow::Ok(())
+    }
+    .instrument(debug_span!("tick.insert_job_run"))
+    .await?;
 
     event!(
         Level::INFO,
-        "{num} agent_symbols are new: {infos:?}",
-        num = new_agent_symbols.len(),
-        infos = new_agent_symbols
+        num_agents,
+        num_construction_sites,
+        "Done collecting current infos of agents and construction sites",
     );
 
+    async {
+        event!(Level::DEBUG, "Refreshing 'materialized view'",);
+        refresh_fake_materialized_view(&pool)
+            .await
+            .context("failed at refresh_fake_materialized_view")?;
+
+        force_wal_checkpoint(&pool)
+            .await
+            .context("failed at force_wal_checkpoint")?;
+        anyhow::Ok(())
+    }
+    .instrument(debug_span!("tick.refresh_materialized_view"))
+    .await?;
+
     event!(
-        Level::INFO,
-        "Done downloading static infos for {num_agents} agents",
-        num_agents = new_agent_symbols.len()
+        Level::DEBUG,
+        "Done refreshing 'materialized view' and cleaning up DB wal",
     );
 
-    let static_agent_info_results = load_static_agent_infos(&client, new_agent_symbols).await?;
-    save_construction_sites(&pool, reset_date_db, static_agent_info_results.clone()).await;
-    let construction_sites = select_construction_sites_for_reset(&pool, reset_date_db)
-        .await
-        .context("failed at select_construction_sites_for_reset")?;
-
-    save_static_agent_infos(
-        &pool,
-        reset_date_db,
-        static_agent_info_results.clone(),
-        construction_sites.clone(),
-        now,
-    )
-    .await;
+    Ok(TickCompleted {
+        reset_date,
+        query_time: now,
+    })
+}
 
+/// Registers new agents and construction sites of the reset and returns the static infos of all agents
+async fn update_static_infos(
+    client: &StClient,
+    pool: &Pool<Sqlite>,
+    st_status: StStatusResponse,
+    reset_date: NaiveDate,
+    now: NaiveDateTime,
+) -> anyhow::Result<(ResetDate, Vec<DbStaticAgentInfo>, Vec<DbConstructionSite>)> {
+    let reset_date_db = load_or_create_reset_date(pool, reset_date, now).await?;
+    event!(
+        Level::DEBUG,
+        reset_id = reset_date_db.reset_id,
+        "Using reset_date_db"
+    );
     let static_agent_infos: Vec<DbStaticAgentInfo> =
-        select_static_agent_infos_for_reset(&pool, reset_date_db).await?;
+        select_static_agent_infos_for_reset(pool, reset_date_db).await?;
 
-    let num_agents = static_agent_infos.len();
-    let num_construction_sites = construction_sites.len();
+    event!(
+        Level::DEBUG,
+        num_static_agent_infos = static_agent_infos.len(),
+        "Loaded static_agent_infos",
+    );
+
+    let new_agent_symbols = determine_missing_agent_symbols(st_status, static_agent_infos);
 
     event!(
         Level::INFO,
-        "Downloading current infos for {num_agents} agents and {num_construction_sites} construction sites",
+        num_new_agents = new_agent_symbols.len(),
+        new_agent_symbols = new_agent_symbols.join(", "),
+        "Found new agents",
     );
 
-    let (current_agent_entries, current_construction_entries) =
-        collect_data(&client, static_agent_infos.clone(), construction_sites)
-            .await
-            .context("failed at collect_data")?;
+    let construction_sites =
+        save_static_agent_infos_of(client, pool, reset_date_db, new_agent_symbols, now).await?;
 
-    let db_construction_infos = select_construction_sites_for_reset(&pool, reset_date_db)
+    let static_agent_infos: Vec<DbStaticAgentInfo> =
+        select_static_agent_infos_for_reset(pool, reset_date_db).await?;
+
+    Ok((reset_date_db, static_agent_infos, construction_sites))
+}
+
+/// Downloads the static infos of the agents and upserts them together with their construction sites.
+/// Returns all construction sites of the reset.
+async fn save_static_agent_infos_of(
+    client: &StClient,
+    pool: &Pool<Sqlite>,
+    reset_date_db: ResetDate,
+    agent_symbols: Vec<String>,
+    now: NaiveDateTime,
+) -> anyhow::Result<Vec<DbConstructionSite>> {
+    let static_agent_info_results = load_static_agent_infos(client, agent_symbols).await?;
+    save_construction_sites(pool, reset_date_db, static_agent_info_results.clone()).await;
+    let construction_sites = select_construction_sites_for_reset(pool, reset_date_db)
         .await
         .context("failed at select_construction_sites_for_reset")?;
 
-    let _ = insert_job_run_and_details(
-        &pool,
-        now,
+    save_static_agent_infos(
+        pool,
         reset_date_db,
-        current_agent_entries,
-        static_agent_infos.clone(),
-        current_construction_entries,
-        db_construction_infos,
+        static_agent_info_results,
+        construction_sites.clone(),
+        now,
     )
-    .await;
-
-    event!(
-        Level::INFO,
-        "Done collecting current infos for {num_agents} agents and {num_construction_sites} construction sites",
-    );
+    .await
+    .context("failed at save_static_agent_infos")?;
 
-    event!(Level::DEBUG, "Refreshing 'materialized view'",);
-    let _ = refresh_fake_materialized_view(&pool)
-        .await
-        .context("failed at refresh_fake_materialized_view")?;
-
-    let _ = force_wal_checkpoint(&pool)
-        .await
-        .context("failed at force_wal_checkpoint")?;
+    Ok(construction_sites)
+}
 
-    event!(
-        Level::INFO,
-        "Done refreshing 'materialized view' and cleaning up DB wal",
-    );
+/// Agents whose headquarters or starting faction differ from the stored static infos
+fn determine_changed_agent_symbols(
+    static_agent_infos: &[DbStaticAgentInfo],
+    current_agent_infos: &[LeaderboardCurrentAgentInfo],
+) -> Vec<String> {
+    let static_agent_infos: HashMap<&str, &DbStaticAgentInfo> = static_agent_infos
+        .iter()
+        .map(|sai| (sai.agent_symbol.as_str(), sai))
+        .collect();
 
-    Ok(())
+    current_agent_infos
+        .iter()
+        .filter(|current| {
+            static_agent_infos
+                .get(current.symbol.0.as_str())
+                .is_some_and(|sai| {
+                    sai.agent_headquarters_waypoint_symbol != current.headquarters.0
+                        || sai.starting_faction != current.starting_faction.0
+                })
+        })
+        .map(|current| current.symbol.0.clone())
+        .collect()
 }
 
 fn determine_missing_agent_symbols(
@@ -162,11 +267,7 @@ async fn load_static_agent_infos(
         .collect();
 
     let num_agents = static_agent_futures.len();
-    event!(
-        Level::INFO,
-        "Downloading static infos for {} agents",
-        num_agents
-    );
+    event!(Level::DEBUG, num_agents, "Downloading static infos");
 
     let joined = join_all(static_agent_futures).await;
 
@@ -248,6 +349,8 @@ async fn get_current_agent_info(
         symbol: agent_symbol,
         credits: agent_info.credits,
         ship_count: agent_info.ship_count,
+        headquarters: WaypointSymbol(agent_info.headquarters),
+        starting_faction: FactionSymbol(agent_info.starting_faction),
     })
 }
 
@@ -0,0 +1,430 @@
+use std::collections::HashMap;
+
+use chrono::{NaiveDate, NaiveDateTime};
+use serde::Serialize;
+use sqlx::{Pool, Sqlite};
+
+use crate::db::{
+    load_leaderboard_for_job_run, select_jump_gate_agent_assignment_for_reset,
+    select_jump_gate_construction_states_at_job_run, select_recent_job_runs_of_reset,
+    DbJumpGateConstructionState, LeaderboardEntry,
+};
+use crate::leaderboard_collector::TickCompleted;
+
+/// overtakes are only reported if the agent ends up within this rank - below that it's mostly noise
+const OVERTAKE_MAX_RANK: usize = 10;
+
+/// Notable changes between the latest job run of a reset and the one before.
+#[derive(Serialize, Debug, Clone, PartialEq, Eq)]
+#[serde(tag = "kind", rename_all = "camelCase")]
+pub(crate) enum LeaderboardEvent {
+    /// first job run of a new reset
+    #[serde(rename_all = "camelCase")]
+    NewReset { reset_date: NaiveDate },
+    /// the first materials of a jump gate have been delivered
+    #[serde(rename_all = "camelCase")]
+    JumpGateConstructionStarted {
+        reset_date: NaiveDate,
+        jump_gate_waypoint_symbol: String,
+        agent_symbols: Vec<String>,
+    },
+    #[serde(rename_all = "camelCase")]
+    JumpGateConstructionCompleted {
+        reset_date: NaiveDate,
+        jump_gate_waypoint_symbol: String,
+        agent_symbols: Vec<String>,
+    },
+    #[serde(rename_all = "camelCase")]
+    NewCreditsLeader {
+        reset_date: NaiveDate,
+        agent_symbol: String,
+        credits: i64,
+        previous_leader_agent_symbol: String,
+    },
+    /// an agent passed other agents in the credits ranking
+    #[serde(rename_all = "camelCase")]
+    Overtake {
+        reset_date: NaiveDate,
+        agent_symbol: String,
+        /// 1-based rank by credits after the overtake
+        rank: u32,
+        credits: i64,
+        overtaken_agent_symbols: Vec<String>,
+    },
+}
+
+/// An event of a specific job run
+#[derive(Serialize, Debug, Clone)]
+#[serde(rename_all = "camelCase")]
+pub(crate) struct DetectedEvent {
+    /// identifies the event across restarts - used to deliver each event at most once
+    pub(crate) key: String,
+    pub(crate) query_time: NaiveDateTime,
+    pub(crate) event: LeaderboardEvent,
+}
+
+impl LeaderboardEvent {
+    pub(crate) fn kind(&self) -> &'static str {
+        match self {
+            LeaderboardEvent::NewReset { .. } => "newReset",
+            LeaderboardEvent::JumpGateConstructionStarted { .. } => "jumpGateConstructionStarted",
+            LeaderboardEvent::JumpGateConstructionCompleted { .. } => {
+                "jumpGateConstructionCompleted"
+            }
+            LeaderboardEvent::NewCreditsLeader { .. } => "newCreditsLeader",
+            LeaderboardEvent::Overtake { .. } => "overtake",
+        }
+    }
+
+    /// Human-readable one-liner for chat messages
+    pub(crate) fn message(&self) -> String {
+        match self {
+            LeaderboardEvent::NewReset { reset_date } => {
+                format!("A new reset started: {reset_date}")
+            }
+            LeaderboardEvent::JumpGateConstructionStarted {
+                jump_gate_waypoint_symbol,
+                agent_symbols,
+                ..
+            } => format!(
+                "Construction of jump gate {jump_gate_waypoint_symbol} started ({})",
+                agent_symbols.join(", ")
+            ),
+            LeaderboardEvent::JumpGateConstructionCompleted {
+                jump_gate_waypoint_symbol,
+                agent_symbols,
+                ..
+            } => format!(
+                "Jump gate {jump_gate_waypoint_symbol} is complete ({})",
+                agent_symbols.join(", ")
+            ),
+            LeaderboardEvent::NewCreditsLeader {
+                agent_symbol,
+                credits,
+                previous_leader_agent_symbol,
+                ..
+            } => format!(
+                "{agent_symbol} took the lead from {previous_leader_agent_symbol} with {credits} credits"
+            ),
+            LeaderboardEvent::Overtake {
+                agent_symbol,
+                rank,
+                credits,
+                overtaken_agent_symbols,
+                ..
+            } => format!(
+                "{agent_symbol} overtook {} and is now #{rank} with {credits} credits",
+                overtaken_agent_symbols.join(", ")
+            ),
+        }
+    }
+
+    fn key_suffix(&self) -> String {
+        match self {
+            LeaderboardEvent::NewReset { .. } => "new-reset".to_string(),
+            LeaderboardEvent::JumpGateConstructionStarted {
+                jump_gate_waypoint_symbol,
+                ..
+            } => format!("jump-gate-started:{jump_gate_waypoint_symbol}"),
+            LeaderboardEvent::JumpGateConstructionCompleted {
+                jump_gate_waypoint_symbol,
+                ..
+            } => format!("jump-gate-completed:{jump_gate_waypoint_symbol}"),
+            LeaderboardEvent::NewCreditsLeader { agent_symbol, .. } => {
+                format!("new-credits-leader:{agent_symbol}")
+            }
+            LeaderboardEvent::Overtake { agent_symbol, .. } => format!("overtake:{agent_symbol}"),
+        }
+    }
+}
+
+/// Compares the job run of the tick with the previous job run of the reset.
+/// Only depends on the db, so a restart of the server neither loses nor repeats events.
+pub(crate) async fn detect_events(
+    pool: &Pool<Sqlite>,
+    tick: TickCompleted,
+) -> Result<Vec<DetectedEvent>, sqlx::Error> {
+    let reset_date = tick.reset_date;
+    let job_runs = select_recent_job_runs_of_reset(pool, reset_date, 2).await?;
+    let (latest, maybe_previous) = match job_runs.as_slice() {
+        [] => return Ok(vec![]),
+        [latest] => (latest, None),
+        [latest, previous, ..] => (latest, Some(previous)),
+    };
+
+    let events = match maybe_previous {
+        None => vec![LeaderboardEvent::NewReset { reset_date }],
+        Some(previous) => {
+            let leaderboard_before =
+                load_leaderboard_for_job_run(pool, previous.job_run_id).await?;
+            let leaderboard_after = load_leaderboard_for_job_run(pool, latest.job_run_id).await?;
+            let gates_before = select_jump_gate_construction_states_at_job_run(
+                pool,
+                reset_date,
+                previous.job_run_id,
+            )
+            .await?;
+            let gates_after = select_jump_gate_construction_states_at_job_run(
+                pool,
+                reset_date,
+                latest.job_run_id,
+            )
+            .await?;
+            let agents_of_gate: HashMap<String, Vec<String>> =
+                select_jump_gate_agent_assignment_for_reset(pool, reset_date)
+                    .await?
+                    .into_iter()
+                    .map(|entry| {
+                        (
+                            entry.jump_gate_waypoint_symbol,
+                            entry
+                                .agents_in_system_csv
+                                .split(',')
+                                .map(|s| s.to_string())
+                                .collect(),
+                        )
+                    })
+                    .collect();
+
+            let mut events =
+                jump_gate_events(reset_date, &gates_before, &gates_after, &agents_of_gate);
+            events.extend(credits_events(
+                reset_date,
+                &leaderboard_before,
+                &leaderboard_after,
+            ));
+            events
+        }
+    };
+
+    Ok(events
+        .into_iter()
+        .map(|event| DetectedEvent {
+            key: match event {
+                // happen once per reset or gate - independent of the job run
+                LeaderboardEvent::NewReset { .. }
+                | LeaderboardEvent::JumpGateConstructionStarted { .. }
+                | LeaderboardEvent::JumpGateConstructionCompleted { .. } => {
+                    format!("{reset_date}:{}", event.key_suffix())
+                }
+                LeaderboardEvent::NewCreditsLeader { .. } | LeaderboardEvent::Overtake { .. } => {
+                    format!("{reset_date}:{}:{}", latest.job_run_id, event.key_suffix())
+                }
+            },
+            query_time: latest.query_time,
+            event,
+        })
+        .collect())
+}
+
+fn jump_gate_events(
+    reset_date: NaiveDate,
+    before: &[DbJumpGateConstructionState],
+    after: &[DbJumpGateConstructionState],
+    agents_of_gate: &HashMap<String, Vec<String>>,
+) -> Vec<LeaderboardEvent> {
+    let before: HashMap<&str, &DbJumpGateConstructionState> = before
+        .iter()
+        .map(|state| (state.jump_gate_waypoint_symbol.as_str(), state))
+        .collect();
+
+    let mut events = Vec::new();
+    for state in after {
+        let maybe_before = before.get(state.jump_gate_waypoint_symbol.as_str());
+        let fulfilled_before = maybe_before.map(|b| b.fulfilled).unwrap_or(0);
+        let was_complete = maybe_before.is_some_and(|b| b.is_complete);
+        let agent_symbols = agents_of_gate
+            .get(&state.jump_gate_waypoint_symbol)
+            .cloned()
+            .unwrap_or_default();
+
+        if fulfilled_before == 0 && state.fulfilled > 0 {
+            events.push(LeaderboardEvent::JumpGateConstructionStarted {
+                reset_date,
+                jump_gate_waypoint_symbol: state.jump_gate_waypoint_symbol.clone(),
+                agent_symbols: agent_symbols.clone(),
+            });
+        }
+        if !was_complete && state.is_complete {
+            events.push(LeaderboardEvent::JumpGateConstructionCompleted {
+                reset_date,
+                jump_gate_waypoint_symbol: state.jump_gate_waypoint_symbol.clone(),
+                agent_symbols,
+            });
+        }
+    }
+    events
+}
+
+/// Both leaderboards are sorted by credits (desc)
+fn credits_events(
+    reset_date: NaiveDate,
+    before: &[LeaderboardEntry],
+    after: &[LeaderboardEntry],
+) -> Vec<LeaderboardEvent> {
+    let mut events = Vec::new();
+
+    let mut has_new_leader = false;
+    if let (Some(leader_before), Some(leader_after)) = (before.first(), after.first()) {
+        if leader_before.agent_symbol != leader_after.agent_symbol {
+            has_new_leader = true;
+            events.push(LeaderboardEvent::NewCreditsLeader {
+                reset_date,
+                agent_symbol: leader_after.agent_symbol.clone(),
+                credits: leader_after.credits,
+                previous_leader_agent_symbol: leader_before.agent_symbol.clone(),
+            });
+        }
+    }
+
+    let rank_before: HashMap<&str, usize> = before
+        .iter()
+        .enumerate()
+        .map(|(idx, entry)| (entry.agent_symbol.as_str(), idx))
+        .collect();
+
+    // the overtakes of a new leader are already covered by NewCreditsLeader
+    let skip = usize::from(has_new_leader);
+    for (idx, entry) in after.iter().enumerate().take(OVERTAKE_MAX_RANK).skip(skip) {
+        let Some(&idx_before) = rank_before.get(entry.agent_symbol.as_str()) else {
+            continue;
+        };
+        // agents that were ahead and are behind now
+        let overtaken_agent_symbols: Vec<String> = after[idx + 1..]
+            .iter()
+            .filter(|other| {
+                rank_before
+                    .get(other.agent_symbol.as_str())
+                    .is_some_and(|&other_idx_before| other_idx_before < idx_before)
+            })
+            .map(|other| other.agent_symbol.clone())
+            .collect();
+
+        if !overtaken_agent_symbols.is_empty() {
+            events.push(LeaderboardEvent::Overtake {
+                reset_date,
+                agent_symbol: entry.agent_symbol.clone(),
+                rank: idx as u32 + 1,
+                credits: entry.credits,
+                overtaken_agent_symbols,
+            });
+        }
+    }
+
+    events
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn reset_date() -> NaiveDate {
+        NaiveDate::from_ymd_opt(2024, 3, 10).unwrap()
+    }
+
+    /// entries in the given order with descending credits
+    fn leaderboard(agent_symbols: &[impl AsRef<str>]) -> Vec<LeaderboardEntry> {
+        agent_symbols
+            .iter()
+            .enumerate()
+            .map(|(idx, agent_symbol)| LeaderboardEntry {
+                agent_symbol: agent_symbol.as_ref().to_string(),
+                credits: 1000 - idx as i64,
+                ship_count: 2,
+                agent_headquarters_waypoint_symbol: "X1-A1-HQ".to_string(),
+                jump_gate_waypoint_symbol: "X1-A1-JG".to_string(),
+                starting_faction: "COSMIC".to_string(),
+            })
+            .collect()
+    }
+
+    fn gate(symbol: &str, fulfilled: i64, is_complete: bool) -> DbJumpGateConstructionState {
+        DbJumpGateConstructionState {
+            jump_gate_waypoint_symbol: symbol.to_string(),
+            is_complete,
+            fulfilled,
+        }
+    }
+
+    #[test]
+    fn leader_change_is_reported_without_an_overtake_of_the_new_leader() {
+        let actual = credits_events(
+            reset_date(),
+            &leaderboard(&["A", "B", "C"]),
+            &leaderboard(&["B", "A", "C"]),
+        );
+        assert_eq!(
+            actual,
+            vec![LeaderboardEvent::NewCreditsLeader {
+                reset_date: reset_date(),
+                agent_symbol: "B".to_string(),
+                credits: 1000,
+                previous_leader_agent_symbol: "A".to_string(),
+            }]
+        );
+    }
+
+    #[test]
+    fn overtakes_are_only_reported_within_overtake_max_rank() {
+        let before: Vec<String> = (0..OVERTAKE_MAX_RANK + 2)
+            .map(|idx| format!("AGENT-{idx}"))
+            .collect();
+        let mut after = before.clone();
+        // rank 3 passes rank 2 and the last agent passes the one above - both outside the top
+        after.swap(1, 2);
+        after.swap(OVERTAKE_MAX_RANK, OVERTAKE_MAX_RANK + 1);
+
+        let actual = credits_events(reset_date(), &leaderboard(&before), &leaderboard(&after));
+        assert_eq!(
+            actual,
+            vec![LeaderboardEvent::Overtake {
+                reset_date: reset_date(),
+                agent_symbol: "AGENT-2".to_string(),
+                rank: 2,
+                credits: 999,
+                overtaken_agent_symbols: vec!["AGENT-1".to_string()],
+            }]
+        );
+    }
+
+    #[test]
+    fn gate_start_and_completion_are_reported_once() {
+        let agents_of_gate = HashMap::from([(
+            "X1-A1-JG".to_string(),
+            vec!["A".to_string(), "B".to_string()],
+        )]);
+        let before = vec![
+            gate("X1-A1-JG", 0, false),
+            gate("X1-B1-JG", 10, false),
+            gate("X1-C1-JG", 10, false),
+        ];
+        let after = vec![
+            gate("X1-A1-JG", 5, false),
+            gate("X1-B1-JG", 20, true),
+            gate("X1-C1-JG", 15, false),
+        ];
+
+        let actual = jump_gate_events(reset_date(), &before, &after, &agents_of_gate);
+        assert_eq!(
+            actual,
+            vec![
+                LeaderboardEvent::JumpGateConstructionStarted {
+                    reset_date: reset_date(),
+                    jump_gate_waypoint_symbol: "X1-A1-JG".to_string(),
+                    agent_symbols: vec!["A".to_string(), "B".to_string()],
+                },
+                LeaderboardEvent::JumpGateConstructionCompleted {
+                    reset_date: reset_date(),
+                    jump_gate_waypoint_symbol: "X1-B1-JG".to_string(),
+                    agent_symbols: vec![],
+                },
+            ]
+        );
+
+        // the next tick doesn't repeat them
+        assert_eq!(
+            jump_gate_events(reset_date(), &after, &after, &agents_of_gate),
+            vec![]
+        );
+    }
+}
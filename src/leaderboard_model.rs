@@ -17,6 +17,8 @@ pub struct LeaderboardCurrentAgentInfo {
     pub symbol: AgentSymbol,
     pub credits: i64,
     pub ship_count: u32,
+    pub headquarters: WaypointSymbol,
+    pub starting_faction: FactionSymbol,
 }
 
 #[derive(Deserialize, Serialize, Debug, Clone)]
@@ -0,0 +1,34 @@
+use serde::Deserialize;
+use utoipa::ToSchema;
+
+use crate::api_error::ApiError;
+
+/// upper bound for the `limit` query parameter of list endpoints
+pub(crate) const MAX_LIMIT: u32 = 1000;
+
+#[derive(Deserialize, ToSchema, Debug, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "camelCase")]
+pub(crate) enum SortDirection {
+    Asc,
+    Desc,
+}
+
+impl SortDirection {
+    /// the value the list queries in db.rs expect
+    pub(crate) fn as_sql(self) -> &'static str {
+        match self {
+            SortDirection::Asc => "asc",
+            SortDirection::Desc => "desc",
+        }
+    }
+}
+
+/// Rejects a `limit` above [MAX_LIMIT]. Without `limit` all entries after `offset` are returned.
+pub(crate) fn check_limit(limit: Option<u32>) -> Result<Option<u32>, ApiError> {
+    if limit.is_some_and(|limit| limit > MAX_LIMIT) {
+        return Err(ApiError::BadRequest(format!(
+            "limit must not be greater than {MAX_LIMIT}"
+        )));
+    }
+    Ok(limit)
+}
@@ -0,0 +1,418 @@
+use std::collections::HashMap;
+use std::convert::Infallible;
+use std::sync::Arc;
+
+use axum::extract::State;
+use axum::response::sse::{Event, KeepAlive, Sse};
+use axum::{routing, Router};
+use chrono::{NaiveDate, NaiveDateTime};
+use futures::{stream, Stream};
+use serde::{Deserialize, Serialize};
+use sqlx::{Pool, Sqlite};
+use tokio::sync::broadcast;
+use tracing::{event, Level};
+use utoipa::ToSchema;
+
+use crate::db::{
+    load_leaderboard_for_reset, select_most_recent_construction_progress_for_reset,
+    DbConstructionMaterialMostRecentStatus, LeaderboardEntry,
+};
+use crate::leaderboard_collector::TickCompleted;
+use crate::server::leaderboard::{ApiAgentSymbol, ApiResetDate, ApiTradeSymbol, ApiWaypointSymbol};
+use crate::server::AppState;
+
+/// number of serialized events a slow client may fall behind before it skips some
+const LIVE_EVENT_BUFFER: usize = 16;
+
+/// Fan-out of the tick events to the clients of `/api/live`.
+///
+/// The events are built once per tick by [LiveUpdates::publish_on_tick] and shared by all clients.
+#[derive(Clone)]
+pub(crate) struct LiveUpdates {
+    sender: broadcast::Sender<LiveEvent>,
+}
+
+#[derive(Clone, Debug)]
+struct LiveEvent {
+    id: String,
+    data: Arc<str>,
+}
+
+impl Default for LiveUpdates {
+    fn default() -> Self {
+        let (sender, _) = broadcast::channel(LIVE_EVENT_BUFFER);
+        Self { sender }
+    }
+}
+
+/// Sent after each tick of the collector. Entries only contain what changed since the previous tick,
+/// unless `isFullSnapshot` is set (first tick after a restart of the server or of a new reset).
+#[derive(Serialize, Deserialize, ToSchema, Debug)]
+#[serde(rename_all = "camelCase")]
+pub(crate) struct LiveTickEvent {
+    reset_date: ApiResetDate,
+    /// timestamp of the new job_run
+    query_time: NaiveDateTime,
+    is_full_snapshot: bool,
+    leaderboard_entries: Vec<LiveLeaderboardEntry>,
+    construction_progress_entries: Vec<LiveConstructionProgressEntry>,
+}
+
+#[derive(Serialize, Deserialize, ToSchema, Debug)]
+#[serde(rename_all = "camelCase")]
+pub(crate) struct LiveLeaderboardEntry {
+    agent_symbol: ApiAgentSymbol,
+    /// 1-based rank by credits
+    rank: u32,
+    credits: i64,
+    credits_delta: i64,
+    ship_count: i64,
+    ship_count_delta: i64,
+}
+
+#[derive(Serialize, Deserialize, ToSchema, Debug)]
+#[serde(rename_all = "camelCase")]
+pub(crate) struct LiveConstructionProgressEntry {
+    jump_gate_waypoint_symbol: ApiWaypointSymbol,
+    trade_symbol: ApiTradeSymbol,
+    fulfilled: i64,
+    fulfilled_delta: i64,
+    required: i64,
+    is_jump_gate_complete: bool,
+}
+
+/// State of the previous tick the deltas are computed against
+struct TickSnapshot {
+    reset_date: NaiveDate,
+    /// agent_symbol -> (rank, credits, ship_count)
+    agents: HashMap<String, (u32, i64, i64)>,
+    /// (jump_gate_waypoint_symbol, trade_symbol) -> (fulfilled, is_jump_gate_complete)
+    materials: HashMap<(String, String), (i64, bool)>,
+}
+
+pub(crate) fn live_router() -> Router<AppState> {
+    Router::new().route("/api/live", routing::get(get_live))
+}
+
+impl LiveUpdates {
+    /// Listens for completed ticks of the collector and publishes the changes of the ongoing reset.
+    pub(crate) async fn publish_on_tick(
+        self,
+        pool: Pool<Sqlite>,
+        mut receiver: broadcast::Receiver<TickCompleted>,
+    ) {
+        let mut maybe_previous: Option<TickSnapshot> = None;
+        loop {
+            let tick = match receiver.recv().await {
+                Ok(tick) => tick,
+                Err(broadcast::error::RecvError::Lagged(_)) => {
+                    // deltas against a snapshot we skipped would be wrong
+                    maybe_previous = None;
+                    continue;
+                }
+                Err(broadcast::error::RecvError::Closed) => return,
+            };
+
+            let (leaderboard, materials) = match load_tick_data(&pool, tick.reset_date).await {
+                Ok(data) => data,
+                Err(err) => {
+                    event!(Level::ERROR, "Error loading data for live update: {err:?}");
+                    maybe_previous = None;
+                    continue;
+                }
+            };
+
+            let snapshot = TickSnapshot::new(tick.reset_date, &leaderboard, &materials);
+            let tick_event = build_tick_event(
+                tick,
+                maybe_previous
+                    .as_ref()
+                    .filter(|p| p.reset_date == tick.reset_date),
+                &snapshot,
+                &materials,
+            );
+            maybe_previous = Some(snapshot);
+
+            match serde_json::to_string(&tick_event) {
+                Ok(data) => {
+                    // no subscribers is fine - nobody to notify
+                    let _ = self.sender.send(LiveEvent {
+                        id: tick.query_time.format("%Y-%m-%dT%H:%M:%S").to_string(),
+                        data: data.into(),
+                    });
+                }
+                Err(err) => event!(Level::ERROR, "Error serializing live update: {err:?}"),
+            }
+        }
+    }
+
+    fn subscribe(&self) -> broadcast::Receiver<LiveEvent> {
+        self.sender.subscribe()
+    }
+}
+
+async fn load_tick_data(
+    pool: &Pool<Sqlite>,
+    reset_date: NaiveDate,
+) -> Result<
+    (
+        Vec<LeaderboardEntry>,
+        Vec<DbConstructionMaterialMostRecentStatus>,
+    ),
+    sqlx::Error,
+> {
+    let leaderboard = load_leaderboard_for_reset(pool, reset_date).await?;
+    let materials = select_most_recent_construction_progress_for_reset(pool, reset_date).await?;
+    Ok((leaderboard, materials))
+}
+
+impl TickSnapshot {
+    fn new(
+        reset_date: NaiveDate,
+        leaderboard: &[LeaderboardEntry],
+        materials: &[DbConstructionMaterialMostRecentStatus],
+    ) -> Self {
+        // the leaderboard is ordered by credits
+        let agents = leaderboard
+            .iter()
+            .enumerate()
+            .map(|(idx, e)| {
+                (
+                    e.agent_symbol.clone(),
+                    (idx as u32 + 1, e.credits, e.ship_count),
+                )
+            })
+            .collect();
+
+        let materials = materials
+            .iter()
+            .map(|m| {
+                (
+                    (m.jump_gate_waypoint_symbol.clone(), m.trade_symbol.clone()),
+                    (m.fulfilled, m.is_jump_gate_complete),
+                )
+            })
+            .collect();
+
+        Self {
+            reset_date,
+            agents,
+            materials,
+        }
+    }
+}
+
+fn build_tick_event(
+    tick: TickCompleted,
+    maybe_previous: Option<&TickSnapshot>,
+    current: &TickSnapshot,
+    materials: &[DbConstructionMaterialMostRecentStatus],
+) -> LiveTickEvent {
+    let mut leaderboard_entries: Vec<LiveLeaderboardEntry> = current
+        .agents
+        .iter()
+        .filter_map(|(agent_symbol, &(rank, credits, ship_count))| {
+            let previous = maybe_previous.and_then(|p| p.agents.get(agent_symbol));
+            if previous == Some(&(rank, credits, ship_count)) {
+                return None;
+            }
+            let (_, previous_credits, previous_ship_count) = previous.copied().unwrap_or_default();
+            Some(LiveLeaderboardEntry {
+                agent_symbol: ApiAgentSymbol(agent_symbol.clone()),
+                rank,
+                credits,
+                credits_delta: credits - previous_credits,
+                ship_count,
+                ship_count_delta: ship_count - previous_ship_count,
+            })
+        })
+        .collect();
+    leaderboard_entries.sort_by_key(|e| e.rank);
+
+    let construction_progress_entries = materials
+        .iter()
+        .filter_map(|m| {
+            let key = (m.jump_gate_waypoint_symbol.clone(), m.trade_symbol.clone());
+            let previous = maybe_previous.and_then(|p| p.materials.get(&key));
+            if previous == Some(&(m.fulfilled, m.is_jump_gate_complete)) {
+                return None;
+            }
+            let (previous_fulfilled, _) = previous.copied().unwrap_or_default();
+            Some(LiveConstructionProgressEntry {
+                jump_gate_waypoint_symbol: ApiWaypointSymbol(m.jump_gate_waypoint_symbol.clone()),
+                trade_symbol: ApiTradeSymbol(m.trade_symbol.clone()),
+                fulfilled: m.fulfilled,
+                fulfilled_delta: m.fulfilled - previous_fulfilled,
+                required: m.required,
+                is_jump_gate_complete: m.is_jump_gate_complete,
+            })
+        })
+        .collect();
+
+    LiveTickEvent {
+        reset_date: ApiResetDate(tick.reset_date.format("%Y-%m-%d").to_string()),
+        query_time: tick.query_time,
+        is_full_snapshot: maybe_previous.is_none(),
+        leaderboard_entries,
+        construction_progress_entries,
+    }
+}
+
+/// Stream of `tick` events, one after each completed tick of the collector.
+///
+/// Clients should load the current state from the other endpoints first and apply the deltas of the
+/// events on top. A client that falls behind skips events and gets a `resync` event instead
+/// (data: `{"skippedEvents": <n>}`). It has to reload the state before applying further deltas.
+#[utoipa::path(
+    get,
+    path = "/api/live",
+    // same tag as the other endpoints, so that generated clients keep them in one api class
+    tag = "crate",
+    responses((status = 200, description = "server-sent events named `tick` and `resync`", content_type = "text/event-stream", body = LiveTickEvent)),
+)]
+pub(crate) async fn get_live(
+    State(live_updates): State<LiveUpdates>,
+) -> Sse<impl Stream<Item = Result<Event, Infallible>>> {
+    let receiver = live_updates.subscribe();
+
+    let events = stream::unfold(receiver, |mut receiver| async move {
+        let event = match receiver.recv().await {
+            Ok(live_event) => Event::default()
+                .event("tick")
+                .id(live_event.id)
+                .data(&*live_event.data),
+            Err(broadcast::error::RecvError::Lagged(num_skipped)) => {
+                event!(
+                    Level::DEBUG,
+                    "Live client lagged behind, skipped {num_skipped} events"
+                );
+                // the deltas of the following events don't apply to the state of the client
+                Event::default()
+                    .event("resync")
+                    .data(format!("{{\"skippedEvents\":{num_skipped}}}"))
+            }
+            Err(broadcast::error::RecvError::Closed) => return None,
+        };
+        Some((Ok(event), receiver))
+    });
+
+    Sse::new(events).keep_alive(KeepAlive::default())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn leaderboard_entry(agent_symbol: &str, credits: i64, ship_count: i64) -> LeaderboardEntry {
+        LeaderboardEntry {
+            agent_symbol: agent_symbol.to_string(),
+            credits,
+            ship_count,
+            agent_headquarters_waypoint_symbol: "X1-AA-A1".to_string(),
+            jump_gate_waypoint_symbol: "X1-AA-JG".to_string(),
+            starting_faction: "COSMIC".to_string(),
+        }
+    }
+
+    fn material(trade_symbol: &str, fulfilled: i64) -> DbConstructionMaterialMostRecentStatus {
+        DbConstructionMaterialMostRecentStatus {
+            reset_id: Some(1),
+            reset: None,
+            ts_start_of_reset: None,
+            ts_latest_entry_of_reset: None,
+            trade_symbol: trade_symbol.to_string(),
+            fulfilled,
+            required: 1600,
+            jump_gate_waypoint_symbol: "X1-AA-JG".to_string(),
+            is_jump_gate_complete: false,
+        }
+    }
+
+    fn tick(query_time: &str) -> TickCompleted {
+        TickCompleted {
+            reset_date: NaiveDate::from_ymd_opt(2024, 3, 24).unwrap(),
+            query_time: NaiveDateTime::parse_from_str(query_time, "%Y-%m-%d %H:%M:%S").unwrap(),
+        }
+    }
+
+    #[test]
+    fn first_tick_is_a_full_snapshot() {
+        let leaderboard = vec![
+            leaderboard_entry("WHYANDO", 250_000, 3),
+            leaderboard_entry("FLWI", 175_000, 2),
+        ];
+        let materials = vec![material("FAB_MATS", 0), material("ADVANCED_CIRCUITRY", 0)];
+        let current = TickSnapshot::new(
+            tick("2024-03-24 16:00:00").reset_date,
+            &leaderboard,
+            &materials,
+        );
+
+        let event = build_tick_event(tick("2024-03-24 16:00:00"), None, &current, &materials);
+
+        assert!(event.is_full_snapshot);
+        assert_eq!(
+            event
+                .leaderboard_entries
+                .iter()
+                .map(|e| (e.agent_symbol.0.as_str(), e.rank, e.credits_delta))
+                .collect::<Vec<_>>(),
+            vec![("WHYANDO", 1, 250_000), ("FLWI", 2, 175_000)]
+        );
+        assert_eq!(event.construction_progress_entries.len(), 2);
+    }
+
+    #[test]
+    fn following_ticks_only_contain_the_changes() {
+        let reset_date = tick("2024-03-24 16:00:00").reset_date;
+        let materials = vec![material("FAB_MATS", 0), material("ADVANCED_CIRCUITRY", 0)];
+        let previous = TickSnapshot::new(
+            reset_date,
+            &[
+                leaderboard_entry("WHYANDO", 250_000, 3),
+                leaderboard_entry("FLWI", 175_000, 2),
+                leaderboard_entry("IDLE", 100_000, 1),
+            ],
+            &materials,
+        );
+
+        let leaderboard = vec![
+            leaderboard_entry("FLWI", 300_000, 3),
+            leaderboard_entry("WHYANDO", 250_000, 3),
+            leaderboard_entry("IDLE", 100_000, 1),
+        ];
+        let materials = vec![material("FAB_MATS", 120), material("ADVANCED_CIRCUITRY", 0)];
+        let current = TickSnapshot::new(reset_date, &leaderboard, &materials);
+
+        let event = build_tick_event(
+            tick("2024-03-24 16:05:00"),
+            Some(&previous),
+            &current,
+            &materials,
+        );
+
+        assert!(!event.is_full_snapshot);
+        // WHYANDO only lost the lead, IDLE didn't change at all
+        assert_eq!(
+            event
+                .leaderboard_entries
+                .iter()
+                .map(|e| (
+                    e.agent_symbol.0.as_str(),
+                    e.rank,
+                    e.credits_delta,
+                    e.ship_count_delta
+                ))
+                .collect::<Vec<_>>(),
+            vec![("FLWI", 1, 125_000, 1), ("WHYANDO", 2, 0, 0)]
+        );
+        assert_eq!(
+            event
+                .construction_progress_entries
+                .iter()
+                .map(|e| (e.trade_symbol.0.as_str(), e.fulfilled, e.fulfilled_delta))
+                .collect::<Vec<_>>(),
+            vec![("FAB_MATS", 120, 120)]
+        );
+    }
+}
@@ -1,24 +1,38 @@
 use std::fs;
+use std::num::NonZeroU32;
 use std::time::Duration;
 
-use anyhow::{Context, Error, Result};
+use anyhow::{anyhow, Context, Error, Result};
 use clap::Parser;
-use futures::join;
-use reqwest::Url;
+use futures::{join, TryFutureExt};
 use sqlx::sqlite::SqlitePoolOptions;
 use sqlx::sqlx_macros::migrate;
 use sqlx::{ConnectOptions, Executor, Pool, Sqlite};
+use tokio::sync::broadcast;
 use tokio_cron_scheduler::{Job, JobScheduler};
 use tracing::log::LevelFilter;
 use tracing::{event, Level};
-use tracing_subscriber::{fmt, prelude::*, EnvFilter};
 use utoipa::OpenApi;
 
+use crate::admin::AdminSettings;
+use crate::api_token::{create_api_token, revoke_api_token_by_name};
+use crate::app_metrics::MetricsExporter;
+use crate::backup::{create_backup, BackupSettings};
+use crate::check_db::check_db;
 use crate::cli_args::{Cli, Commands};
-use crate::leaderboard_collector::perform_tick;
-use crate::reqwest_helpers::create_client;
-use crate::server::http_server;
-use crate::st_client::StClient;
+use crate::collector_control::CollectorControl;
+use crate::discord_bot::{run_discord_bot, DiscordBotSettings};
+use crate::export::load_reset_export;
+use crate::feeds::FeedSettings;
+use crate::graphql::build_schema as build_graphql_schema;
+use crate::health::HealthSettings;
+use crate::import::{import_resets, load_import_source};
+use crate::leaderboard_collector::TickCompleted;
+use crate::live::LiveUpdates;
+use crate::rate_limit::{RateLimitSettings, RateLimits};
+use crate::server::{http_server, AppState, QueryCache};
+use crate::telemetry::{init_tracing, OtlpSettings};
+use crate::webhooks::Webhooks;
 
 mod leaderboard_model;
 mod model;
@@ -26,9 +40,31 @@ mod pagination;
 mod reqwest_helpers;
 mod st_client;
 
+mod admin;
+mod api_error;
+mod api_token;
+mod app_metrics;
+mod backup;
+mod badges;
+mod check_db;
 mod cli_args;
+mod collector_control;
 mod db;
+mod discord_bot;
+mod export;
+mod feeds;
+mod graphql;
+mod health;
+mod html;
+mod http_cache;
+mod import;
 mod leaderboard_collector;
+mod leaderboard_events;
+mod list_params;
+mod live;
+mod rate_limit;
+mod telemetry;
+mod webhooks;
 
 mod server;
 
@@ -37,7 +73,10 @@ async fn main() -> Result<()> {
     let args = cli_args::Cli::parse();
 
     match args {
-        Cli { command } => match command {
+        Cli {
+            command,
+            log_format,
+        } => match command {
             Commands::GenerateOpenapi { output_path } => {
                 let docs = server::leaderboard::ApiDoc::openapi()
                     .to_pretty_json()
@@ -51,32 +90,28 @@ async fn main() -> Result<()> {
                 host,
                 port,
                 base_url,
+                admin_token,
+                backup_dir,
+                backup_keep,
+                backup_schedule,
+                rate_limit_cheap_per_minute,
+                rate_limit_heavy_per_minute,
+                trust_fly_client_ip,
+                readiness_max_missed_ticks,
+                webhooks,
+                discord_bot_token,
+                discord_announcement_channel_id,
+                public_url,
+                otlp_endpoint,
+                otlp_filter,
             } => {
-                tracing_subscriber::registry()
-                    .with(fmt::layer())
-                    .with(EnvFilter::from_default_env())
-                    .init();
-
-                // I have a long-running query calculating the progress of the jump-gate construction.
-                // I'm setting the warning threshold for slow queries to 60s to prevent log-spam.
-                let database_connection_options: sqlx::sqlite::SqliteConnectOptions = database_url
-                    .parse::<sqlx::sqlite::SqliteConnectOptions>()?
-                    .log_slow_statements(LevelFilter::Warn, Duration::from_secs(60));
-
-                let background_task_pool = SqlitePoolOptions::new()
-                    .after_connect(|conn, _| {
-                        Box::pin(async move {
-                            // Set WAL mode explicitly
-                            conn.execute("PRAGMA journal_mode=WAL").await?;
-                            // Auto-checkpoint after this many pages (default is 1000)
-                            // Lower this number if you want more frequent checkpoints
-                            conn.execute("PRAGMA wal_autocheckpoint=1000").await?;
-                            Ok(())
-                        })
-                    })
-                    .max_connections(5)
-                    .connect_with(database_connection_options)
-                    .await?;
+                let otlp_settings = otlp_endpoint.map(|endpoint| OtlpSettings {
+                    endpoint,
+                    filter: otlp_filter,
+                });
+                let _telemetry = init_tracing(log_format, otlp_settings.as_ref())?;
+
+                let background_task_pool = connect_database(&database_url).await?;
 
                 event!(Level::INFO, "Migrating database if necessary");
                 sqlx::migrate!().run(&background_task_pool).await?;
@@ -89,13 +124,171 @@ async fn main() -> Result<()> {
 
                 let bind_address = format!("{}:{}", host, port);
 
+                let backup_settings = backup_dir.map(|backup_dir| BackupSettings {
+                    backup_dir,
+                    keep: backup_keep,
+                });
+
+                let (tick_sender, _) = broadcast::channel::<TickCompleted>(16);
+                let query_cache = QueryCache::default();
+                let live_updates = LiveUpdates::default();
+                let metrics_exporter = MetricsExporter::install(vec![
+                    ("api", pool.clone()),
+                    ("collector", background_task_pool.clone()),
+                ])?;
+                let rate_limits = RateLimits::new(&RateLimitSettings {
+                    cheap_requests_per_minute: NonZeroU32::new(rate_limit_cheap_per_minute),
+                    heavy_requests_per_minute: NonZeroU32::new(rate_limit_heavy_per_minute),
+                    trust_fly_client_ip,
+                });
+                let webhooks = Webhooks::new(webhooks);
+                let discord_bot_settings = discord_bot_token.map(|token| DiscordBotSettings {
+                    token,
+                    announcement_channel_id: discord_announcement_channel_id,
+                });
+                let collector_control = CollectorControl::new(
+                    background_task_pool.clone(),
+                    base_url,
+                    tick_sender.clone(),
+                );
+
+                let state = AppState {
+                    pool: pool.clone(),
+                    admin_settings: AdminSettings {
+                        admin_token,
+                        backup_settings: backup_settings.clone(),
+                    },
+                    query_cache: query_cache.clone(),
+                    live_updates: live_updates.clone(),
+                    graphql_schema: build_graphql_schema(),
+                    collector_control: collector_control.clone(),
+                    rate_limits: rate_limits.clone(),
+                    metrics_exporter,
+                    health_settings: HealthSettings {
+                        max_missed_ticks: readiness_max_missed_ticks,
+                    },
+                    feed_settings: FeedSettings { public_url },
+                };
+
                 let _ = join!(
-                    background_collect(background_task_pool.clone(), base_url),
-                    http_server(pool.clone(), bind_address, asset_dir)
+                    query_cache.invalidate_on_tick(tick_sender.subscribe()),
+                    rate_limits.clean_up_periodically(),
+                    live_updates.publish_on_tick(pool.clone(), tick_sender.subscribe()),
+                    webhooks.deliver_on_tick(background_task_pool.clone(), tick_sender.subscribe()),
+                    run_discord_bot(discord_bot_settings, pool.clone(), tick_sender.subscribe())
+                        .inspect_err(|err| event!(Level::ERROR, "Discord bot failed: {err:?}")),
+                    background_collect(collector_control),
+                    background_backup(
+                        background_task_pool.clone(),
+                        backup_settings,
+                        backup_schedule
+                    )
+                    .inspect_err(|err| event!(Level::ERROR, "Scheduled backups failed: {err:?}")),
+                    http_server(state, bind_address, asset_dir)
                 );
 
                 Ok(())
             }
+            Commands::Backup {
+                database_url,
+                backup_dir,
+                keep,
+            } => {
+                let _telemetry = init_tracing(log_format, None)?;
+
+                let pool = connect_database(&database_url).await?;
+                let settings = BackupSettings { backup_dir, keep };
+                let result = create_backup(&pool, &settings).await?;
+                println!("{}", settings.backup_dir.join(result.file_name).display());
+
+                Ok(())
+            }
+            Commands::ExportReset {
+                database_url,
+                reset_date,
+                output_dir,
+                format,
+            } => {
+                let _telemetry = init_tracing(log_format, None)?;
+
+                let pool = connect_database(&database_url).await?;
+                let export = load_reset_export(&pool, reset_date)
+                    .await?
+                    .ok_or_else(|| anyhow!("No data found for reset {reset_date}"))?;
+                export.write_to_dir(format, &output_dir)?;
+                event!(
+                    Level::INFO,
+                    "Exported reset {reset_date} to {}",
+                    output_dir.display()
+                );
+
+                Ok(())
+            }
+            Commands::Import {
+                database_url,
+                source,
+                reset_date,
+                dry_run,
+            } => {
+                let _telemetry = init_tracing(log_format, None)?;
+
+                let exports = load_import_source(&source, reset_date).await?;
+                if exports.is_empty() {
+                    return Err(anyhow!("No resets found in {}", source.display()));
+                }
+
+                let pool = connect_and_migrate_database(&database_url).await?;
+
+                let reports = import_resets(&pool, &exports, dry_run).await?;
+                for report in reports {
+                    print!("{report}");
+                }
+                if dry_run {
+                    println!("dry run - no changes were written");
+                }
+
+                Ok(())
+            }
+            Commands::CreateApiToken { database_url, name } => {
+                let _telemetry = init_tracing(log_format, None)?;
+
+                let pool = connect_and_migrate_database(&database_url).await?;
+
+                let token = create_api_token(&pool, &name).await?;
+                println!("{token}");
+
+                Ok(())
+            }
+            Commands::RevokeApiToken { database_url, name } => {
+                let _telemetry = init_tracing(log_format, None)?;
+
+                let pool = connect_and_migrate_database(&database_url).await?;
+                revoke_api_token_by_name(&pool, &name).await?;
+                event!(Level::INFO, "Revoked api token '{name}'");
+
+                Ok(())
+            }
+            Commands::CheckDb {
+                database_url,
+                repair,
+                dry_run,
+            } => {
+                let _telemetry = init_tracing(log_format, None)?;
+
+                // a plain check and a dry run must not write anything - not even migrations
+                let pool = if repair && !dry_run {
+                    connect_and_migrate_database(&database_url).await?
+                } else {
+                    connect_database(&database_url).await?
+                };
+                let report = check_db(&pool, repair, dry_run).await?;
+                print!("{report}");
+
+                match report.remaining_violations() {
+                    0 => Ok(()),
+                    n => Err(anyhow!("{n} invariant violations found")),
+                }
+            }
         },
     }
 
@@ -103,31 +296,50 @@ async fn main() -> Result<()> {
     // as a workaround I added this step
 }
 
-async fn background_collect(pool: Pool<Sqlite>, base_url: Url) -> Result<()> {
+/// For the subcommands that write to the db - they rely on the latest schema
+async fn connect_and_migrate_database(database_url: &str) -> Result<Pool<Sqlite>> {
+    let pool = connect_database(database_url).await?;
+    sqlx::migrate!().run(&pool).await?;
+    Ok(pool)
+}
+
+async fn connect_database(database_url: &str) -> Result<Pool<Sqlite>> {
+    // I have a long-running query calculating the progress of the jump-gate construction.
+    // I'm setting the warning threshold for slow queries to 60s to prevent log-spam.
+    let database_connection_options: sqlx::sqlite::SqliteConnectOptions = database_url
+        .parse::<sqlx::sqlite::SqliteConnectOptions>()?
+        .log_slow_statements(LevelFilter::Warn, Duration::from_secs(60));
+
+    let pool = SqlitePoolOptions::new()
+        .after_connect(|conn, _| {
+            Box::pin(async move {
+                // Set WAL mode explicitly
+                conn.execute("PRAGMA journal_mode=WAL").await?;
+                // Auto-checkpoint after this many pages (default is 1000)
+                // Lower this number if you want more frequent checkpoints
+                conn.execute("PRAGMA wal_autocheckpoint=1000").await?;
+                Ok(())
+            })
+        })
+        .max_connections(5)
+        .connect_with(database_connection_options)
+        .await?;
+
+    Ok(pool)
+}
+
+async fn background_collect(collector_control: CollectorControl) -> Result<()> {
     let mut sched = JobScheduler::new().await?;
 
     // I don't know what I'm doing. `move`d stuff around, until the compiler was happy
     // Add async job
+    let job_collector_control = collector_control.clone();
     let job = Job::new_async("0 */5 * * * *", move |uuid, mut l| {
         Box::pin({
-            let clone_wars_pool = pool.clone();
-
-            let base_url = base_url.clone();
+            let collector_control = job_collector_control.clone();
 
             async move {
-                let reqwest_client_with_middleware = create_client();
-                let client = StClient::new(reqwest_client_with_middleware, base_url);
-
-                let result = perform_tick(&client, clone_wars_pool)
-                    .await
-                    .context("failed at perform_tick");
-
-                match result {
-                    Ok(_) => {}
-                    Err(err) => {
-                        event!(Level::ERROR, "Error during perform tick: {}", err)
-                    }
-                }
+                collector_control.scheduled_tick().await;
 
                 // Query the next execution time for this job
                 let next_tick = l.next_tick_for_job(uuid).await;
@@ -135,6 +347,7 @@ async fn background_collect(pool: Pool<Sqlite>, base_url: Url) -> Result<()> {
                     Ok(Some(ts)) => event!(Level::INFO, "Next time for 5min job is {:?}", ts),
                     _ => event!(Level::ERROR, "Could not get next tick for 5min job"),
                 }
+                collector_control.set_next_scheduled_tick(next_tick.ok().flatten());
             }
         })
     })?;
@@ -144,13 +357,48 @@ async fn background_collect(pool: Pool<Sqlite>, base_url: Url) -> Result<()> {
     // Start the scheduler
     sched.start().await?;
 
-    match sched.next_tick_for_job(job.guid()).await {
+    let next_tick = sched.next_tick_for_job(job.guid()).await;
+    match next_tick {
         Ok(Some(ts)) => event!(Level::INFO, "Next time for 5min job is {:?}", ts),
         _ => event!(Level::INFO, "Could not get next tick for 5min job"),
     }
+    collector_control.set_next_scheduled_tick(next_tick.ok().flatten());
 
     // Just run the whole thing forever
     tokio::time::sleep(Duration::MAX).await;
 
     Ok(())
 }
+
+async fn background_backup(
+    pool: Pool<Sqlite>,
+    maybe_backup_settings: Option<BackupSettings>,
+    maybe_schedule: Option<String>,
+) -> Result<()> {
+    let (Some(backup_settings), Some(schedule)) = (maybe_backup_settings, maybe_schedule) else {
+        return Ok(());
+    };
+
+    let sched = JobScheduler::new().await?;
+
+    let job = Job::new_async(schedule.as_str(), move |_uuid, _l| {
+        let pool = pool.clone();
+        let backup_settings = backup_settings.clone();
+
+        Box::pin(async move {
+            if let Err(err) = create_backup(&pool, &backup_settings).await {
+                event!(Level::ERROR, "Error during scheduled backup: {err:?}")
+            }
+        })
+    })
+    .with_context(|| format!("invalid backup schedule '{schedule}'"))?;
+
+    sched.add(job).await?;
+    sched.start().await?;
+
+    event!(Level::INFO, "Scheduled backups with '{schedule}'");
+
+    tokio::time::sleep(Duration::MAX).await;
+
+    Ok(())
+}
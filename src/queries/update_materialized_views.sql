@@ -66,9 +66,13 @@ where required > 1
 ;
 
 
+-- pick the latest reset by date, not by reset_id - an older reset imported afterwards gets the higher id
 delete
 from mat_view_material_delivery_events
-where reset_id = (select max(main.reset_date.reset_id) from reset_date)
+where reset_id = (select latest.reset_id
+                  from reset_date latest
+                  order by latest.reset desc, latest.reset_id desc
+                  limit 1)
 ;
 
 
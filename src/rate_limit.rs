@@ -0,0 +1,180 @@
+use std::net::{IpAddr, SocketAddr};
+use std::num::NonZeroU32;
+use std::sync::Arc;
+use std::time::Duration;
+
+use axum::extract::{ConnectInfo, Request, State};
+use axum::http::{HeaderMap, Method};
+use axum::middleware::Next;
+use axum::response::Response;
+use governor::clock::{Clock, DefaultClock};
+use governor::{DefaultKeyedRateLimiter, Quota, RateLimiter};
+use sqlx::{Pool, Sqlite};
+use tracing::{event, Level};
+
+use crate::admin::{bearer_token, AdminSettings};
+use crate::api_error::ApiError;
+use crate::api_token::is_valid_api_token;
+
+/// how often the state of clients that are back to their full quota gets dropped
+const CLEAN_UP_INTERVAL: Duration = Duration::from_secs(60);
+
+const FLY_CLIENT_IP_HEADER: &str = "fly-client-ip";
+
+#[derive(Debug, Clone)]
+pub(crate) struct RateLimitSettings {
+    /// requests per minute and client to the cheap endpoints. `None` disables the limit.
+    pub(crate) cheap_requests_per_minute: Option<NonZeroU32>,
+    /// requests per minute and client to the heavy endpoints. `None` disables the limit.
+    pub(crate) heavy_requests_per_minute: Option<NonZeroU32>,
+    /// take the client ip from the `Fly-Client-IP` header of the fly.io proxy instead of the peer address
+    pub(crate) trust_fly_client_ip: bool,
+}
+
+/// Per-client rate limits of the public api. Clients are identified by their ip address.
+/// Requests with a valid api token are not limited.
+#[derive(Clone)]
+pub(crate) struct RateLimits {
+    inner: Arc<RateLimitsInner>,
+}
+
+struct RateLimitsInner {
+    trust_fly_client_ip: bool,
+    cheap: Option<DefaultKeyedRateLimiter<IpAddr>>,
+    heavy: Option<DefaultKeyedRateLimiter<IpAddr>>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum RouteClass {
+    /// lists, snapshots, badges and html pages served from small queries or the query cache
+    Cheap,
+    /// history, all-time aggregations, exports, feeds and arbitrary graphql queries
+    Heavy,
+}
+
+impl RouteClass {
+    /// `None` for routes that aren't limited (docs, static files and the admin api that requires a token anyway)
+    fn of(method: &Method, path: &str) -> Option<Self> {
+        let is_heavy = path.starts_with("/api/history/")
+            || path.starts_with("/api/all-time-")
+            || path.starts_with("/api/export/")
+            || path.starts_with("/feeds/")
+            || (path.starts_with("/api/leaderboard/") && path.ends_with("/diff"))
+            || (path == "/graphql" && method == Method::POST);
+
+        if is_heavy {
+            Some(RouteClass::Heavy)
+        } else if path.starts_with("/api/admin") {
+            None
+        } else if path.starts_with("/api/")
+            || path.starts_with("/badges/")
+            || path.starts_with("/html/")
+        {
+            Some(RouteClass::Cheap)
+        } else {
+            None
+        }
+    }
+}
+
+impl RateLimits {
+    pub(crate) fn new(settings: &RateLimitSettings) -> Self {
+        Self {
+            inner: Arc::new(RateLimitsInner {
+                trust_fly_client_ip: settings.trust_fly_client_ip,
+                cheap: settings
+                    .cheap_requests_per_minute
+                    .map(|n| RateLimiter::keyed(Quota::per_minute(n))),
+                heavy: settings
+                    .heavy_requests_per_minute
+                    .map(|n| RateLimiter::keyed(Quota::per_minute(n))),
+            }),
+        }
+    }
+
+    /// Drops the state of clients that didn't use up any of their quota recently to bound the memory.
+    pub(crate) async fn clean_up_periodically(self) {
+        let mut interval = tokio::time::interval(CLEAN_UP_INTERVAL);
+        loop {
+            interval.tick().await;
+            for limiter in [&self.inner.cheap, &self.inner.heavy].into_iter().flatten() {
+                limiter.retain_recent();
+                limiter.shrink_to_fit();
+            }
+        }
+    }
+
+    fn limiter(&self, route_class: RouteClass) -> Option<&DefaultKeyedRateLimiter<IpAddr>> {
+        match route_class {
+            RouteClass::Cheap => self.inner.cheap.as_ref(),
+            RouteClass::Heavy => self.inner.heavy.as_ref(),
+        }
+    }
+
+    fn client_ip(&self, request: &Request) -> Option<IpAddr> {
+        let maybe_fly_client_ip = self
+            .inner
+            .trust_fly_client_ip
+            .then(|| fly_client_ip(request.headers()))
+            .flatten();
+
+        maybe_fly_client_ip.or_else(|| {
+            request
+                .extensions()
+                .get::<ConnectInfo<SocketAddr>>()
+                .map(|ConnectInfo(address)| address.ip())
+        })
+    }
+}
+
+fn fly_client_ip(headers: &HeaderMap) -> Option<IpAddr> {
+    headers
+        .get(FLY_CLIENT_IP_HEADER)
+        .and_then(|value| value.to_str().ok())
+        .and_then(|value| value.trim().parse().ok())
+}
+
+/// Rejects requests of clients that exceeded the quota of the route class with a 429 and `Retry-After`.
+pub(crate) async fn rate_limit(
+    State(rate_limits): State<RateLimits>,
+    State(pool): State<Pool<Sqlite>>,
+    State(admin_settings): State<AdminSettings>,
+    request: Request,
+    next: Next,
+) -> Result<Response, ApiError> {
+    let Some(limiter) = RouteClass::of(request.method(), request.uri().path())
+        .and_then(|route_class| rate_limits.limiter(route_class))
+    else {
+        return Ok(next.run(request).await);
+    };
+
+    // without connect info there is nothing to key the limit on
+    let Some(client_ip) = rate_limits.client_ip(&request) else {
+        return Ok(next.run(request).await);
+    };
+
+    match limiter.check_key(&client_ip) {
+        Ok(()) => Ok(next.run(request).await),
+        Err(not_until) => {
+            // the token only gets looked up once the limit is exceeded - that keeps the hashing
+            // and the db lookup off the hot path
+            if let Some(token) = bearer_token(request.headers()) {
+                if is_valid_api_token(&pool, admin_settings.admin_token.as_deref(), token).await? {
+                    return Ok(next.run(request).await);
+                }
+            }
+
+            let wait_time = not_until.wait_time_from(DefaultClock::default().now());
+            let retry_after_seconds = wait_time.as_secs() + u64::from(wait_time.subsec_nanos() > 0);
+            event!(
+                Level::DEBUG,
+                "Rate limited {client_ip} on {} for {retry_after_seconds}s",
+                request.uri().path()
+            );
+            Err(ApiError::TooManyRequests(
+                "rate limit exceeded".to_string(),
+                retry_after_seconds,
+            ))
+        }
+    }
+}
@@ -1,11 +1,16 @@
 use axum::http::Extensions;
 use std::sync::Arc;
+use std::time::Instant;
 
 use governor::{DefaultDirectRateLimiter, Quota, RateLimiter};
 use reqwest::{Client, Request};
 use reqwest_middleware::{ClientBuilder, ClientWithMiddleware, Middleware, Next};
 use reqwest_retry::policies::ExponentialBackoff;
 use reqwest_retry::RetryTransientMiddleware;
+use tracing::field::Empty;
+use tracing::{debug_span, Instrument};
+
+use crate::app_metrics::{record_spacetraders_rate_limiter_wait, record_spacetraders_request};
 
 pub(crate) fn create_client() -> ClientWithMiddleware {
     let reqwest_client = Client::builder().build().unwrap();
@@ -22,6 +27,8 @@ pub(crate) fn create_client() -> ClientWithMiddleware {
     let client = ClientBuilder::new(reqwest_client)
         .with(RetryTransientMiddleware::new_with_policy(retry_policy))
         .with(rate_limiting_middleware)
+        .with(MetricsMiddleware)
+        .with(TracingMiddleware)
         .build();
     client
 }
@@ -39,7 +46,9 @@ impl Middleware for RateLimitingMiddleware {
         next: Next<'_>,
     ) -> reqwest_middleware::Result<reqwest::Response> {
         // println!("checking rate_limiting availability");
+        let start = Instant::now();
         self.limiter.until_ready().await;
+        record_spacetraders_rate_limiter_wait(start.elapsed());
         // println!("rate_limit check ok");
 
         // println!("Request started {:?}", req);
@@ -48,3 +57,77 @@ impl Middleware for RateLimitingMiddleware {
         res
     }
 }
+
+/// Records every attempt (including retries) per endpoint template and status
+struct MetricsMiddleware;
+
+#[async_trait::async_trait]
+impl Middleware for MetricsMiddleware {
+    async fn handle(
+        &self,
+        req: Request,
+        extensions: &mut Extensions,
+        next: Next<'_>,
+    ) -> reqwest_middleware::Result<reqwest::Response> {
+        let endpoint = endpoint_template(req.url().path());
+        let start = Instant::now();
+        let res = next.run(req, extensions).await;
+        let status = match &res {
+            Ok(response) => response.status().as_u16().to_string(),
+            Err(_) => "error".to_string(),
+        };
+        record_spacetraders_request(endpoint, status, start.elapsed());
+        res
+    }
+}
+
+/// Creates a span for every attempt (including retries) with the endpoint template and status
+struct TracingMiddleware;
+
+#[async_trait::async_trait]
+impl Middleware for TracingMiddleware {
+    async fn handle(
+        &self,
+        req: Request,
+        extensions: &mut Extensions,
+        next: Next<'_>,
+    ) -> reqwest_middleware::Result<reqwest::Response> {
+        let endpoint = endpoint_template(req.url().path());
+        let span = debug_span!(
+            "spacetraders_request",
+            otel.name = format!("{} {endpoint}", req.method()),
+            otel.kind = "client",
+            http.request.method = %req.method(),
+            url.template = endpoint,
+            url.full = %req.url(),
+            http.response.status_code = Empty,
+            error = Empty,
+        );
+
+        let res = next.run(req, extensions).instrument(span.clone()).await;
+        match &res {
+            Ok(response) => span.record("http.response.status_code", response.status().as_u16()),
+            Err(err) => span.record("error", err.to_string()),
+        };
+        res
+    }
+}
+
+/// Replaces the symbols in the path to keep the number of label values small,
+/// e.g. `/v2/systems/X1-AB12/waypoints/X1-AB12-I56/construction` -> `/v2/systems/{systemSymbol}/waypoints/{waypointSymbol}/construction`
+fn endpoint_template(path: &str) -> String {
+    let mut previous_segment = "";
+    path.split('/')
+        .map(|segment| {
+            let template = match previous_segment {
+                "agents" => "{agentSymbol}",
+                "systems" => "{systemSymbol}",
+                "waypoints" => "{waypointSymbol}",
+                _ => segment,
+            };
+            previous_segment = segment;
+            template
+        })
+        .collect::<Vec<_>>()
+        .join("/")
+}
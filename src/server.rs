@@ -1,16 +1,30 @@
+use std::collections::HashMap;
+use std::future::Future;
 use std::io::Error;
+use std::marker::PhantomData;
+use std::net::SocketAddr;
 use std::ops::RangeInclusive;
-use std::path::PathBuf;
+use std::path::{Path, PathBuf};
 use std::str::FromStr;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::{Arc, PoisonError, RwLock};
 use std::time::Duration;
 
-use axum::{response::Result, routing, Router};
-use chrono::TimeDelta;
+use axum::extract::FromRef;
+use axum::http::header;
+use axum::response::{IntoResponse, Redirect, Response};
+use axum::{middleware, response::Result, routing, Router};
+use bytes::Bytes;
+use chrono::{NaiveDate, NaiveDateTime, TimeDelta};
 use futures::TryFutureExt;
+use itertools::Itertools;
+use serde::Serialize;
 use sqlx::{Pool, Sqlite};
 use tokio::net::TcpListener;
+use tokio::sync::broadcast;
 use tower_http::classify::ServerErrorsFailureClass;
 use tower_http::cors::CorsLayer;
+use tower_http::request_id::{MakeRequestUuid, PropagateRequestIdLayer, SetRequestIdLayer};
 use tower_http::services::{ServeDir, ServeFile};
 use tower_http::trace::TraceLayer;
 use tracing::{event, Level, Span};
@@ -19,43 +33,344 @@ use utoipa_rapidoc::RapiDoc;
 use utoipa_redoc::{Redoc, Servable};
 use utoipa_swagger_ui::SwaggerUi;
 
+use crate::admin::{admin_router, AdminSettings};
+use crate::api_error::ApiError;
+use crate::app_metrics::{metrics_router, track_http_requests, MetricsExporter};
+use crate::badges::badges_router;
+use crate::collector_control::CollectorControl;
 use crate::db::{
     DbAgentHistoryEntry, DbAllTimePerformanceEntry, DbConstructionLeaderboardEntry,
-    DbConstructionMaterialHistoryEntry, DbConstructionMaterialMostRecentStatus,
+    DbConstructionMaterialHistoryEntry, DbConstructionMaterialMostRecentStatus, DbJobRunRef,
     DbJumpGateConstructionEventOverviewEntry, ResetDate,
 };
+use crate::feeds::{feeds_router, FeedSettings};
+use crate::graphql::{graphql_router, LeaderboardSchema};
+use crate::health::{health_router, HealthSettings};
+use crate::html::html_router;
+use crate::http_cache::conditional_get;
+use crate::leaderboard_collector::TickCompleted;
+use crate::live::{live_router, LiveUpdates};
 use crate::model::WaypointSymbol;
+use crate::rate_limit::{rate_limit, RateLimits};
 use crate::server::leaderboard::{
     ApiAgentHistoryEntry, ApiAgentSymbol, ApiAllTimeConstructionLeaderboardEntry,
     ApiAllTimePerformanceEntry, ApiConstructionMaterialHistoryEntry,
-    ApiConstructionMaterialMostRecentProgressEntry,
-    ApiGetJumpGateConstructionEventOverviewResponse, ApiJumpGateConstructionEventOverviewEntry,
-    ApiResetAgentPeriodFilterBody, ApiResetDate, ApiTradeSymbol, ApiWaypointSymbol,
-    RangeSelectionMode,
+    ApiConstructionMaterialMostRecentProgressEntry, ApiJobRunRef,
+    ApiJumpGateConstructionEventOverviewEntry, ApiResetAgentPeriodFilterBody, ApiResetDate,
+    ApiTradeSymbol, ApiWaypointSymbol,
 };
+use crate::telemetry::{make_http_request_span, record_http_response};
+
+#[derive(Clone)]
+pub(crate) struct AppState {
+    pub(crate) pool: Pool<Sqlite>,
+    pub(crate) admin_settings: AdminSettings,
+    pub(crate) query_cache: QueryCache,
+    pub(crate) live_updates: LiveUpdates,
+    pub(crate) graphql_schema: LeaderboardSchema,
+    pub(crate) collector_control: CollectorControl,
+    pub(crate) rate_limits: RateLimits,
+    pub(crate) metrics_exporter: MetricsExporter,
+    pub(crate) health_settings: HealthSettings,
+    pub(crate) feed_settings: FeedSettings,
+}
+
+impl FromRef<AppState> for Pool<Sqlite> {
+    fn from_ref(state: &AppState) -> Self {
+        state.pool.clone()
+    }
+}
+
+impl FromRef<AppState> for AdminSettings {
+    fn from_ref(state: &AppState) -> Self {
+        state.admin_settings.clone()
+    }
+}
+
+impl FromRef<AppState> for QueryCache {
+    fn from_ref(state: &AppState) -> Self {
+        state.query_cache.clone()
+    }
+}
+
+impl FromRef<AppState> for LeaderboardSchema {
+    fn from_ref(state: &AppState) -> Self {
+        state.graphql_schema.clone()
+    }
+}
+
+impl FromRef<AppState> for CollectorControl {
+    fn from_ref(state: &AppState) -> Self {
+        state.collector_control.clone()
+    }
+}
+
+impl FromRef<AppState> for RateLimits {
+    fn from_ref(state: &AppState) -> Self {
+        state.rate_limits.clone()
+    }
+}
+
+impl FromRef<AppState> for MetricsExporter {
+    fn from_ref(state: &AppState) -> Self {
+        state.metrics_exporter.clone()
+    }
+}
+
+impl FromRef<AppState> for HealthSettings {
+    fn from_ref(state: &AppState) -> Self {
+        state.health_settings.clone()
+    }
+}
+
+impl FromRef<AppState> for FeedSettings {
+    fn from_ref(state: &AppState) -> Self {
+        state.feed_settings.clone()
+    }
+}
+
+impl FromRef<AppState> for LiveUpdates {
+    fn from_ref(state: &AppState) -> Self {
+        state.live_updates.clone()
+    }
+}
+
+/// keys include the query parameters - this bounds the memory used by arbitrary filter combinations
+const QUERY_CACHE_MAX_ENTRIES: usize = 2000;
+/// number of least recently used entries dropped when the cache is full
+const QUERY_CACHE_EVICTION_BATCH: usize = QUERY_CACHE_MAX_ENTRIES / 10;
+
+/// In-memory cache for the serialized responses of the read endpoints.
+///
+/// Entries are scoped to a reset (or to all resets for the aggregating endpoints). A completed tick
+/// invalidates the entries of its reset and the aggregated ones - entries of closed resets stay
+/// until they get evicted as least recently used.
+#[derive(Clone, Default)]
+pub(crate) struct QueryCache {
+    inner: Arc<RwLock<QueryCacheEntries>>,
+    stats: Arc<QueryCacheStats>,
+}
+
+#[derive(Default)]
+struct QueryCacheEntries {
+    entries: HashMap<QueryCacheKey, QueryCacheEntry>,
+    /// logical clock for the last access of the entries
+    clock: AtomicU64,
+    /// bumped on every invalidation, so that a result loaded before a tick doesn't end up in the cache afterwards
+    generations: HashMap<Option<NaiveDate>, u64>,
+    /// bumped when the whole cache gets cleared
+    epoch: u64,
+}
+
+struct QueryCacheEntry {
+    bytes: Bytes,
+    /// updated on hits, which only hold the read lock
+    last_used: AtomicU64,
+}
+
+impl QueryCacheEntries {
+    fn generation_of(&self, key: &QueryCacheKey) -> (u64, u64) {
+        let generation = self.generations.get(&key.reset_date).copied();
+        (self.epoch, generation.unwrap_or_default())
+    }
+
+    fn next_access(&self) -> u64 {
+        self.clock.fetch_add(1, Ordering::Relaxed)
+    }
+
+    fn evict_least_recently_used(&mut self, num_entries: usize) -> usize {
+        let keys: Vec<QueryCacheKey> = self
+            .entries
+            .iter()
+            .sorted_unstable_by_key(|(_, entry)| entry.last_used.load(Ordering::Relaxed))
+            .take(num_entries)
+            .map(|(key, _)| key.clone())
+            .collect();
+        for key in keys.iter() {
+            self.entries.remove(key);
+        }
+        keys.len()
+    }
+}
+
+#[derive(Default)]
+struct QueryCacheStats {
+    hits: AtomicU64,
+    misses: AtomicU64,
+    invalidated_entries: AtomicU64,
+    evicted_entries: AtomicU64,
+}
+
+#[derive(Serialize, Debug, Clone, Copy)]
+#[serde(rename_all = "camelCase")]
+pub(crate) struct QueryCacheStatsSnapshot {
+    pub(crate) hits: u64,
+    pub(crate) misses: u64,
+    pub(crate) invalidated_entries: u64,
+    pub(crate) evicted_entries: u64,
+    pub(crate) entries: usize,
+}
+
+#[derive(Hash, Eq, PartialEq, Clone, Debug)]
+pub(crate) struct QueryCacheKey {
+    /// None for endpoints that aggregate over all resets
+    reset_date: Option<NaiveDate>,
+    query: String,
+}
+
+impl QueryCacheKey {
+    pub(crate) fn for_reset(reset_date: NaiveDate, query: impl Into<String>) -> Self {
+        Self {
+            reset_date: Some(reset_date),
+            query: query.into(),
+        }
+    }
+
+    pub(crate) fn all_resets(query: impl Into<String>) -> Self {
+        Self {
+            reset_date: None,
+            query: query.into(),
+        }
+    }
+}
+
+/// Already serialized json body served from the [QueryCache]
+pub(crate) struct CachedJson<T>(Bytes, PhantomData<T>);
+
+impl<T> IntoResponse for CachedJson<T> {
+    fn into_response(self) -> Response {
+        ([(header::CONTENT_TYPE, "application/json")], self.0).into_response()
+    }
+}
+
+impl QueryCache {
+    /// Returns the cached response or runs `load` and caches its result. Errors are not cached.
+    pub(crate) async fn get_or_load<T, F, Fut>(
+        &self,
+        key: QueryCacheKey,
+        load: F,
+    ) -> Result<CachedJson<T>, ApiError>
+    where
+        T: Serialize,
+        F: FnOnce() -> Fut,
+        Fut: Future<Output = Result<T, ApiError>>,
+    {
+        let generation = {
+            let inner = self.inner.read().unwrap_or_else(PoisonError::into_inner);
+            if let Some(entry) = inner.entries.get(&key) {
+                entry
+                    .last_used
+                    .store(inner.next_access(), Ordering::Relaxed);
+                self.stats.hits.fetch_add(1, Ordering::Relaxed);
+                return Ok(CachedJson(entry.bytes.clone(), PhantomData));
+            }
+            inner.generation_of(&key)
+        };
+        self.stats.misses.fetch_add(1, Ordering::Relaxed);
+
+        let value = load().await?;
+        let bytes = Bytes::from(
+            serde_json::to_vec(&value).map_err(|err| ApiError::Internal(err.to_string()))?,
+        );
+
+        let mut inner = self.inner.write().unwrap_or_else(PoisonError::into_inner);
+        if inner.generation_of(&key) == generation {
+            if inner.entries.len() >= QUERY_CACHE_MAX_ENTRIES {
+                let num_evicted = inner.evict_least_recently_used(QUERY_CACHE_EVICTION_BATCH);
+                self.stats
+                    .evicted_entries
+                    .fetch_add(num_evicted as u64, Ordering::Relaxed);
+            }
+            let entry = QueryCacheEntry {
+                bytes: bytes.clone(),
+                last_used: AtomicU64::new(inner.next_access()),
+            };
+            inner.entries.insert(key, entry);
+        }
+
+        Ok(CachedJson(bytes, PhantomData))
+    }
+
+    /// Drops the entries of the reset and the ones aggregating over all resets.
+    pub(crate) fn invalidate_reset(&self, reset_date: NaiveDate) -> usize {
+        let mut inner = self.inner.write().unwrap_or_else(PoisonError::into_inner);
+        for scope in [Some(reset_date), None] {
+            *inner.generations.entry(scope).or_default() += 1;
+        }
+
+        let num_entries_before = inner.entries.len();
+        inner
+            .entries
+            .retain(|key, _| key.reset_date.is_some_and(|d| d != reset_date));
+        let num_removed = num_entries_before - inner.entries.len();
+
+        self.stats
+            .invalidated_entries
+            .fetch_add(num_removed as u64, Ordering::Relaxed);
+        num_removed
+    }
+
+    pub(crate) fn stats(&self) -> QueryCacheStatsSnapshot {
+        QueryCacheStatsSnapshot {
+            hits: self.stats.hits.load(Ordering::Relaxed),
+            misses: self.stats.misses.load(Ordering::Relaxed),
+            invalidated_entries: self.stats.invalidated_entries.load(Ordering::Relaxed),
+            evicted_entries: self.stats.evicted_entries.load(Ordering::Relaxed),
+            entries: self
+                .inner
+                .read()
+                .unwrap_or_else(PoisonError::into_inner)
+                .entries
+                .len(),
+        }
+    }
+
+    /// Listens for completed ticks of the collector and invalidates the affected entries.
+    pub(crate) async fn invalidate_on_tick(self, mut receiver: broadcast::Receiver<TickCompleted>) {
+        loop {
+            match receiver.recv().await {
+                Ok(tick) => {
+                    let num_removed = self.invalidate_reset(tick.reset_date);
+                    event!(
+                        Level::DEBUG,
+                        "Invalidated {num_removed} query cache entries after tick {} for reset {}",
+                        tick.query_time,
+                        tick.reset_date
+                    );
+                }
+                Err(broadcast::error::RecvError::Lagged(_)) => {
+                    // we don't know which resets we missed - start over
+                    self.clear();
+                }
+                Err(broadcast::error::RecvError::Closed) => return,
+            }
+        }
+    }
+
+    pub(crate) fn clear(&self) {
+        let mut inner = self.inner.write().unwrap_or_else(PoisonError::into_inner);
+        inner.epoch += 1;
+        let num_removed = inner.entries.len();
+        inner.entries.clear();
+        self.stats
+            .invalidated_entries
+            .fetch_add(num_removed as u64, Ordering::Relaxed);
+    }
+}
 
-pub fn with_static_file_server(router: Router, _serve_dir: ServeDir) -> Router {
-    // FIXME: ???
-    router
+/// Serves the react app from the asset dir. Unknown paths get its index.html for client-side routing.
+pub fn with_static_file_server(router: Router, asset_dir: &Path) -> Router {
+    let serve_dir = ServeDir::new(asset_dir).fallback(ServeFile::new(asset_dir.join("index.html")));
+    router.fallback_service(serve_dir)
 }
 
-pub async fn http_server(
-    db: Pool<Sqlite>,
+pub(crate) async fn http_server(
+    state: AppState,
     address: String,
     maybe_asset_dir: Option<PathBuf>,
 ) -> Result<(), Error> {
-    let app = Router::new()
-        .merge(
-            SwaggerUi::new("/docs/swagger-ui")
-                .url("/api-docs/openapi.json", leaderboard::ApiDoc::openapi()),
-        )
-        .merge(Redoc::with_url(
-            "/docs/redoc",
-            leaderboard::ApiDoc::openapi(),
-        ))
-        // There is no need to create `RapiDoc::with_openapi` because the OpenApi is served
-        // via SwaggerUi instead we only make rapidoc to point to the existing doc.
-        .merge(RapiDoc::new("/api-docs/openapi.json").path("/rapidoc"))
+    // read endpoints get ETags based on the latest job_run - see http_cache
+    let api_routes = Router::new()
         .route(
             "/api/reset-dates",
             routing::get(leaderboard::get_reset_dates),
@@ -72,6 +387,10 @@ pub async fn http_server(
             "/api/leaderboard/:reset_date",
             routing::get(leaderboard::get_leaderboard),
         )
+        .route(
+            "/api/leaderboard/:reset_date/diff",
+            routing::get(leaderboard::get_leaderboard_diff),
+        )
         .route(
             "/api/jump-gate-assignment/:reset_date",
             routing::get(leaderboard::get_jump_gate_agents_assignment),
@@ -86,26 +405,70 @@ pub async fn http_server(
         )
         .route(
             "/api/history/:reset_date",
-            routing::post(leaderboard::get_history_data_for_reset),
+            routing::post(leaderboard::get_history_data_for_reset)
+                .get(leaderboard::get_history_data_for_reset_by_query),
         )
-        .layer(CorsLayer::very_permissive())
-        .layer(TraceLayer::new_for_http().on_failure(
-            |_error: ServerErrorsFailureClass, _latency: Duration, _span: &Span| {
-                tracing::debug!("something went wrong")
-            },
+        .route(
+            "/api/export/:reset_date",
+            routing::get(leaderboard::get_reset_export),
+        )
+        .route_layer(middleware::from_fn_with_state(
+            state.clone(),
+            conditional_get,
+        ));
+
+    let app = Router::new()
+        .merge(
+            SwaggerUi::new("/docs/swagger-ui")
+                .url("/api-docs/openapi.json", leaderboard::ApiDoc::openapi()),
+        )
+        .merge(Redoc::with_url(
+            "/docs/redoc",
+            leaderboard::ApiDoc::openapi(),
         ))
-        .with_state(db);
+        // There is no need to create `RapiDoc::with_openapi` because the OpenApi is served
+        // via SwaggerUi instead we only make rapidoc to point to the existing doc.
+        .merge(RapiDoc::new("/api-docs/openapi.json").path("/rapidoc"))
+        .merge(api_routes)
+        .merge(live_router())
+        .merge(graphql_router())
+        .merge(admin_router())
+        .merge(metrics_router())
+        .merge(health_router())
+        .merge(feeds_router().route_layer(middleware::from_fn_with_state(
+            state.clone(),
+            conditional_get,
+        )))
+        .merge(badges_router().route_layer(middleware::from_fn_with_state(
+            state.clone(),
+            conditional_get,
+        )))
+        .merge(html_router().route_layer(middleware::from_fn_with_state(
+            state.clone(),
+            conditional_get,
+        )))
+        .layer(middleware::from_fn_with_state(state.clone(), rate_limit))
+        .layer(middleware::from_fn(track_http_requests))
+        .layer(CorsLayer::very_permissive())
+        .layer(
+            TraceLayer::new_for_http()
+                .make_span_with(make_http_request_span)
+                .on_response(record_http_response)
+                .on_failure(
+                    |_error: ServerErrorsFailureClass, _latency: Duration, _span: &Span| {
+                        tracing::debug!("something went wrong")
+                    },
+                ),
+        )
+        // the id is generated outside the trace layer to be part of the request span
+        .layer(PropagateRequestIdLayer::x_request_id())
+        .layer(SetRequestIdLayer::x_request_id(MakeRequestUuid))
+        .with_state(state);
 
     let app = match maybe_asset_dir {
-        None => app,
-        Some(asset_dir) => {
-            let serve_dir_with_fallback = Router::new().nest_service(
-                "/",
-                ServeDir::new(asset_dir.clone())
-                    .fallback(ServeFile::new(asset_dir.clone().join("index.html"))),
-            );
-            app.fallback_service(serve_dir_with_fallback)
-        }
+        // without the react app the server-rendered pages are the frontend
+        None => app.route("/", routing::get(|| async { Redirect::to("/html/resets") })),
+        Some(asset_dir) => with_static_file_server(app, &asset_dir),
     };
 
     let listener = TcpListener::bind(address).await?;
@@ -115,41 +478,67 @@ pub async fn http_server(
         listener.local_addr().unwrap()
     );
 
-    axum::serve(listener, app.into_make_service()).await
+    // the peer address is the fallback for the client ip of the rate limiter
+    axum::serve(
+        listener,
+        app.into_make_service_with_connect_info::<SocketAddr>(),
+    )
+    .await
 }
 
 pub mod leaderboard {
-    use axum::extract::{Path, State};
+    use std::collections::{HashMap, HashSet};
+    use std::fmt::{Display, Formatter};
+
+    use axum::extract::State;
+    use axum::http::header;
+    use axum::response::{IntoResponse, Response};
     use axum::Json;
     use chrono::format::StrftimeItems;
-    use chrono::{NaiveDate, NaiveDateTime, TimeDelta};
+    use chrono::{DateTime, NaiveDate, NaiveDateTime};
     use itertools::{process_results, Itertools};
     use serde::{Deserialize, Serialize};
     use sqlx::{Pool, Sqlite};
     use tracing::{event, Level};
-    use utoipa::{OpenApi, ToSchema};
+    use utoipa::{IntoParams, OpenApi, ToSchema};
 
+    use crate::api_error::{
+        ApiError, ApiErrorResponses, ApiJson, ApiPath, ApiQuery, ProblemDetails,
+        QueryApiErrorResponses, ResetApiErrorResponses,
+    };
     use crate::db::{
-        load_leaderboard_for_reset, load_reset_date, load_reset_dates, select_agent_history,
-        select_all_time_construction_leaderboard, select_all_time_performance,
-        select_construction_progress_for_reset, select_jump_gate_agent_assignment_for_reset,
+        load_leaderboard_for_job_run, load_reset_date, load_reset_dates, select_agent_history,
+        select_all_time_construction_leaderboard_page, select_all_time_performance_page,
+        select_construction_progress_for_reset, select_job_run_of_reset_at,
+        select_jump_gate_agent_assignment_for_reset,
         select_jump_gate_construction_event_overview_for_reset,
-        select_most_recent_construction_progress_for_reset, ResetDate,
+        select_leaderboard_page_for_job_run, select_most_recent_construction_progress_for_reset,
+        DbJobRunRef, DbListQuery, LeaderboardEntry, ResetDate,
     };
+    use crate::export::{load_reset_export, ExportFormat};
+    use crate::list_params::{check_limit, SortDirection};
+    use crate::live::{LiveConstructionProgressEntry, LiveLeaderboardEntry, LiveTickEvent};
     use crate::model::WaypointSymbol;
-    use crate::server::{extract_reset_period_from_filter, ResetPeriodFilter};
+    use crate::server::{
+        extract_reset_period_from_filter, reset_num_minutes, CachedJson, QueryCache, QueryCacheKey,
+        ResetPeriodFilter, ResetWindow,
+    };
 
     #[derive(OpenApi)]
     #[openapi(
         paths(
             get_reset_dates,
             get_leaderboard,
+            get_leaderboard_diff,
             get_jump_gate_agents_assignment,
             get_history_data_for_reset,
+            get_history_data_for_reset_by_query,
             get_jump_gate_most_recent_progress,
             get_jump_gate_construction_event_overview,
             get_all_time_performance,
             get_all_time_construction_leaderboard,
+            get_reset_export,
+            crate::live::get_live,
         ),
         components(
             schemas(ApiAgentHistoryEntry),
@@ -161,21 +550,34 @@ pub mod leaderboard {
             schemas(ApiJumpGateAssignmentEntry),
             schemas(ApiJumpGateAssignmentEntry),
             schemas(ApiJumpGateConstructionEventOverviewEntry),
+            schemas(ApiJobRunRef),
+            schemas(ApiLeaderboardDiffEntry),
             schemas(ApiLeaderboardEntry),
             schemas(ApiResetAgentPeriodFilterBody),
             schemas(ApiResetDate),
             schemas(ApiResetDateMeta),
             schemas(ApiTradeSymbol),
             schemas(ApiWaypointSymbol),
+            schemas(AllTimePerformanceSortField),
+            schemas(ConstructionLeaderboardSortField),
+            schemas(ExportFormat),
             schemas(GetAllTimeConstructionLeaderboardResult),
             schemas(ApiAllTimeConstructionLeaderboardEntry),
             schemas(GetAllTimePerformanceResult),
             schemas(GetHistoryDataForResetResponseContent),
             schemas(GetJumpGateAgentsAssignmentForResetResponseContent),
             schemas(GetJumpGateMostRecentProgressForResetResponseContent),
+            schemas(GetLeaderboardDiffResponseContent),
             schemas(GetLeaderboardForResetResponseContent),
+            schemas(LeaderboardSortField),
             schemas(ListResetDatesResponseContent),
+            schemas(LiveConstructionProgressEntry),
+            schemas(LiveLeaderboardEntry),
+            schemas(LiveTickEvent),
+            schemas(ProblemDetails),
+            schemas(ApiResetEvent),
             schemas(RangeSelectionMode),
+            schemas(SortDirection),
         )
     )]
     pub(crate) struct ApiDoc;
@@ -200,7 +602,11 @@ pub mod leaderboard {
     #[serde(rename_all = "camelCase")]
     pub(crate) struct GetLeaderboardForResetResponseContent {
         reset_date: ApiResetDate,
+        /// the job run the standings are taken from
+        as_of: ApiJobRunRef,
         leaderboard_entries: Vec<ApiLeaderboardEntry>,
+        /// number of entries matching the filters, before limit and offset are applied
+        total_count: u32,
     }
 
     #[derive(Serialize, Deserialize, ToSchema)]
@@ -214,12 +620,16 @@ pub mod leaderboard {
     #[serde(rename_all = "camelCase")]
     pub(crate) struct GetAllTimePerformanceResult {
         entries: Vec<ApiAllTimePerformanceEntry>,
+        /// number of entries matching the filters, before limit and offset are applied
+        total_count: u32,
     }
 
     #[derive(Serialize, Deserialize, ToSchema)]
     #[serde(rename_all = "camelCase")]
     pub(crate) struct GetAllTimeConstructionLeaderboardResult {
         entries: Vec<ApiAllTimeConstructionLeaderboardEntry>,
+        /// number of entries matching the filters, before limit and offset are applied
+        total_count: u32,
     }
 
     #[derive(Serialize, Deserialize, ToSchema)]
@@ -297,8 +707,11 @@ pub mod leaderboard {
     #[derive(Serialize, Deserialize, ToSchema)]
     #[serde(rename_all = "camelCase")]
     pub(crate) struct ApiLeaderboardEntry {
+        /// 1-based rank by credits - independent of the filters and the sort order
+        rank: u32,
         agent_symbol: ApiAgentSymbol,
         jump_gate_waypoint_symbol: ApiWaypointSymbol,
+        starting_faction: String,
         credits: i64,
         ship_count: i64,
     }
@@ -326,6 +739,7 @@ pub mod leaderboard {
     pub(crate) struct ApiAllTimePerformanceEntry {
         pub(crate) reset: ApiResetDate,
         pub(crate) agent_symbol: ApiAgentSymbol,
+        pub(crate) starting_faction: String,
         pub(crate) credits: i64,
         pub(crate) rank: u32,
     }
@@ -348,177 +762,618 @@ pub mod leaderboard {
     }
 
     /// List all reset-dates
-    #[utoipa::path(get, path = "/api/reset-dates", responses((status = 200, body = ListResetDatesResponseContent)))]
+    #[utoipa::path(get, path = "/api/reset-dates", responses((status = 200, body = ListResetDatesResponseContent), ApiErrorResponses))]
     pub(crate) async fn get_reset_dates(
         State(pool): State<Pool<Sqlite>>,
-    ) -> Json<ListResetDatesResponseContent> {
-        let fmt = StrftimeItems::new("%Y-%m-%d");
-        let reset_dates = load_reset_dates(&pool).await.unwrap();
-        let response = reset_dates
-            .iter()
-            .map(|r| ApiResetDateMeta {
-                reset_date: ApiResetDate(r.reset.format_with_items(fmt.clone()).to_string()),
-                first_ts: r.first_ts,
-                latest_ts: r.latest_ts,
-                duration_minutes: (r.latest_ts - r.first_ts).num_minutes().abs() as u32,
-                is_ongoing: r.is_ongoing,
+        State(query_cache): State<QueryCache>,
+    ) -> Result<CachedJson<ListResetDatesResponseContent>, ApiError> {
+        query_cache
+            .get_or_load(QueryCacheKey::all_resets("reset-dates"), || async {
+                let fmt = StrftimeItems::new("%Y-%m-%d");
+                let reset_dates = load_reset_dates(&pool).await?;
+                let response = reset_dates
+                    .iter()
+                    .map(|r| ApiResetDateMeta {
+                        reset_date: ApiResetDate(
+                            r.reset.format_with_items(fmt.clone()).to_string(),
+                        ),
+                        first_ts: r.first_ts,
+                        latest_ts: r.latest_ts,
+                        duration_minutes: (r.latest_ts - r.first_ts).num_minutes().unsigned_abs()
+                            as u32,
+                        is_ongoing: r.is_ongoing,
+                    })
+                    .collect();
+
+                Ok(ListResetDatesResponseContent {
+                    reset_dates: response,
+                })
             })
-            .collect();
+            .await
+    }
 
-        Json(ListResetDatesResponseContent {
-            reset_dates: response,
-        })
+    #[derive(Deserialize, ToSchema, Debug, Clone, Copy)]
+    #[serde(rename_all = "camelCase")]
+    pub(crate) enum LeaderboardSortField {
+        Credits,
+        ShipCount,
+        AgentSymbol,
+    }
+
+    /// Pagination, sorting and filters of the leaderboard
+    #[derive(Deserialize, IntoParams, Debug)]
+    #[into_params(parameter_in = Query)]
+    #[serde(rename_all = "camelCase")]
+    pub(crate) struct LeaderboardParams {
+        /// maximum number of entries (at most 1000). Returns all entries if missing
+        limit: Option<u32>,
+        /// number of entries to skip
+        offset: Option<u32>,
+        /// defaults to credits
+        sort: Option<LeaderboardSortField>,
+        /// defaults to desc for credits and ship count and to asc for the agent symbol
+        direction: Option<SortDirection>,
+        /// only agents of this starting faction
+        faction: Option<String>,
+        /// only agents with at least this many credits
+        min_credits: Option<i64>,
+        /// only agents whose symbol contains this value (case-insensitive)
+        agent_symbol: Option<String>,
+        /// standings as of this point in time instead of the latest ones.
+        /// Either minutes since the start of the reset or a timestamp (e.g. `2024-03-24T12:00:00Z`)
+        #[param(value_type = Option<String>)]
+        at: Option<ApiPointInTime>,
+    }
+
+    /// A point in time within a reset - minutes since the start of the reset or a timestamp
+    #[derive(Deserialize, Debug, Clone, Copy)]
+    #[serde(try_from = "String")]
+    pub(crate) enum ApiPointInTime {
+        EventTimeMinutes(u32),
+        Timestamp(NaiveDateTime),
+    }
+
+    impl TryFrom<String> for ApiPointInTime {
+        type Error = String;
+
+        fn try_from(value: String) -> Result<Self, Self::Error> {
+            if let Ok(event_time_minutes) = value.parse::<u32>() {
+                return Ok(ApiPointInTime::EventTimeMinutes(event_time_minutes));
+            }
+            if let Ok(date_time) = DateTime::parse_from_rfc3339(&value) {
+                return Ok(ApiPointInTime::Timestamp(date_time.naive_utc()));
+            }
+            NaiveDateTime::parse_from_str(&value, "%Y-%m-%dT%H:%M:%S")
+                .map(ApiPointInTime::Timestamp)
+                .map_err(|_| format!("'{value}' is neither a number of minutes nor a timestamp"))
+        }
+    }
+
+    impl Display for ApiPointInTime {
+        fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+            match self {
+                ApiPointInTime::EventTimeMinutes(minutes) => write!(f, "minute {minutes}"),
+                ApiPointInTime::Timestamp(ts) => write!(f, "{ts}"),
+            }
+        }
+    }
+
+    #[derive(Serialize, Deserialize, ToSchema, Debug)]
+    #[serde(rename_all = "camelCase")]
+    pub(crate) struct ApiJobRunRef {
+        pub(crate) query_time: NaiveDateTime,
+        pub(crate) event_time_minutes: u32,
+    }
+
+    /// Resolves the job run at or before the given point in time - the latest one if `at` is None.
+    async fn load_job_run_at(
+        pool: &Pool<Sqlite>,
+        reset_date: NaiveDate,
+        at: Option<ApiPointInTime>,
+    ) -> Result<DbJobRunRef, ApiError> {
+        let (query_time_lte, event_time_minutes_lte) = match at {
+            None => (None, None),
+            Some(ApiPointInTime::Timestamp(ts)) => (Some(ts), None),
+            Some(ApiPointInTime::EventTimeMinutes(minutes)) => (None, Some(minutes.into())),
+        };
+        select_job_run_of_reset_at(pool, reset_date, query_time_lte, event_time_minutes_lte)
+            .await?
+            .ok_or_else(|| match at {
+                Some(at) => {
+                    ApiError::NotFound(format!("no data for reset {reset_date} at or before {at}"))
+                }
+                None => ApiError::NotFound(format!("no data for reset {reset_date}")),
+            })
     }
 
     /// Get the leaderboard for a reset.
     #[utoipa::path(
     get,
     path = "/api/leaderboard/{resetDate}",
-    responses((status = 200, body = GetLeaderboardForResetResponseContent)),
+    responses((status = 200, body = GetLeaderboardForResetResponseContent), ResetApiErrorResponses),
     params(
         ("resetDate" = NaiveDate, Path, description = "The reset date"),
+        LeaderboardParams,
     )
     )]
     pub(crate) async fn get_leaderboard(
         State(pool): State<Pool<Sqlite>>,
-        Path(reset_date): Path<NaiveDate>,
-    ) -> Json<GetLeaderboardForResetResponseContent> {
-        let reset_dates = load_leaderboard_for_reset(&pool, reset_date).await.unwrap();
-        let response = reset_dates
+        State(query_cache): State<QueryCache>,
+        ApiPath(reset_date): ApiPath<NaiveDate>,
+        ApiQuery(params): ApiQuery<LeaderboardParams>,
+    ) -> Result<CachedJson<GetLeaderboardForResetResponseContent>, ApiError> {
+        query_cache
+            .get_or_load(
+                QueryCacheKey::for_reset(reset_date, format!("leaderboard {params:?}")),
+                || async {
+                    load_existing_reset(&pool, reset_date).await?;
+                    let job_run = load_job_run_at(&pool, reset_date, params.at).await?;
+                    let sort = params.sort.unwrap_or(LeaderboardSortField::Credits);
+                    let direction = params.direction.unwrap_or(match sort {
+                        LeaderboardSortField::AgentSymbol => SortDirection::Asc,
+                        _ => SortDirection::Desc,
+                    });
+                    let leaderboard_page = select_leaderboard_page_for_job_run(
+                        &pool,
+                        job_run.job_run_id,
+                        &DbListQuery {
+                            faction: params.faction.as_deref(),
+                            agent_symbol: params.agent_symbol.as_deref(),
+                            min_credits: params.min_credits,
+                            sort_column: Some(match sort {
+                                LeaderboardSortField::Credits => "credits",
+                                LeaderboardSortField::ShipCount => "ship_count",
+                                LeaderboardSortField::AgentSymbol => "agent_symbol",
+                            }),
+                            direction: direction.as_sql(),
+                            limit: check_limit(params.limit)?,
+                            offset: params.offset.unwrap_or(0),
+                        },
+                    )
+                    .await?;
+
+                    let total_count = leaderboard_page.total_count;
+                    let response = leaderboard_page
+                        .entries
+                        .into_iter()
+                        .map(|r| ApiLeaderboardEntry {
+                            rank: r.rank as u32,
+                            agent_symbol: ApiAgentSymbol(r.entry.agent_symbol),
+                            starting_faction: r.entry.starting_faction,
+                            credits: r.entry.credits,
+                            ship_count: r.entry.ship_count,
+                            jump_gate_waypoint_symbol: ApiWaypointSymbol(
+                                r.entry.jump_gate_waypoint_symbol,
+                            ),
+                        })
+                        .collect();
+
+                    Ok(GetLeaderboardForResetResponseContent {
+                        reset_date: ApiResetDate(reset_date.format("%Y-%m-%d").to_string()),
+                        as_of: job_run.try_into()?,
+                        leaderboard_entries: response,
+                        total_count,
+                    })
+                },
+            )
+            .await
+    }
+
+    /// Points in time to compare
+    #[derive(Deserialize, IntoParams, Debug)]
+    #[into_params(parameter_in = Query)]
+    #[serde(rename_all = "camelCase")]
+    pub(crate) struct LeaderboardDiffParams {
+        /// earlier point in time - minutes since the start of the reset or a timestamp
+        #[param(value_type = String)]
+        from: ApiPointInTime,
+        /// later point in time. Defaults to the latest standings
+        #[param(value_type = Option<String>)]
+        to: Option<ApiPointInTime>,
+    }
+
+    #[derive(Serialize, Deserialize, ToSchema)]
+    #[serde(rename_all = "camelCase")]
+    pub(crate) struct GetLeaderboardDiffResponseContent {
+        reset_date: ApiResetDate,
+        from: ApiJobRunRef,
+        to: ApiJobRunRef,
+        /// ordered by the rank at `to`. Agents that are only present at `from` come last
+        entries: Vec<ApiLeaderboardDiffEntry>,
+    }
+
+    #[derive(Serialize, Deserialize, ToSchema, Debug)]
+    #[serde(rename_all = "camelCase")]
+    pub(crate) struct ApiLeaderboardDiffEntry {
+        pub(crate) agent_symbol: ApiAgentSymbol,
+        pub(crate) rank_from: Option<u32>,
+        pub(crate) rank_to: Option<u32>,
+        /// positive if the agent moved up
+        pub(crate) rank_change: Option<i64>,
+        pub(crate) credits_from: Option<i64>,
+        pub(crate) credits_to: Option<i64>,
+        /// missing if the agent is only on one of the two leaderboards
+        pub(crate) credits_gained: Option<i64>,
+        /// the agent wasn't on the leaderboard at `from`
+        pub(crate) is_new_entrant: bool,
+    }
+
+    /// Compare the leaderboard of a reset at two points in time.
+    #[utoipa::path(
+    get,
+    path = "/api/leaderboard/{resetDate}/diff",
+    responses((status = 200, body = GetLeaderboardDiffResponseContent), ResetApiErrorResponses),
+    params(
+        ("resetDate" = NaiveDate, Path, description = "The reset date"),
+        LeaderboardDiffParams,
+    )
+    )]
+    pub(crate) async fn get_leaderboard_diff(
+        State(pool): State<Pool<Sqlite>>,
+        State(query_cache): State<QueryCache>,
+        ApiPath(reset_date): ApiPath<NaiveDate>,
+        ApiQuery(params): ApiQuery<LeaderboardDiffParams>,
+    ) -> Result<CachedJson<GetLeaderboardDiffResponseContent>, ApiError> {
+        query_cache
+            .get_or_load(
+                QueryCacheKey::for_reset(reset_date, format!("leaderboard-diff {params:?}")),
+                || async {
+                    load_existing_reset(&pool, reset_date).await?;
+                    let from_job_run =
+                        load_job_run_at(&pool, reset_date, Some(params.from)).await?;
+                    let to_job_run = load_job_run_at(&pool, reset_date, params.to).await?;
+                    if from_job_run.query_time > to_job_run.query_time {
+                        return Err(ApiError::BadRequest(format!(
+                            "from ({}) must not be later than to ({})",
+                            from_job_run.query_time, to_job_run.query_time
+                        )));
+                    }
+
+                    let from_entries =
+                        load_leaderboard_for_job_run(&pool, from_job_run.job_run_id).await?;
+                    let to_entries =
+                        load_leaderboard_for_job_run(&pool, to_job_run.job_run_id).await?;
+
+                    Ok(GetLeaderboardDiffResponseContent {
+                        reset_date: ApiResetDate(reset_date.format("%Y-%m-%d").to_string()),
+                        from: from_job_run.try_into()?,
+                        to: to_job_run.try_into()?,
+                        entries: diff_leaderboards(&from_entries, &to_entries),
+                    })
+                },
+            )
+            .await
+    }
+
+    /// both leaderboards are ordered by rank
+    pub(crate) fn diff_leaderboards(
+        from_entries: &[LeaderboardEntry],
+        to_entries: &[LeaderboardEntry],
+    ) -> Vec<ApiLeaderboardDiffEntry> {
+        let from_ranks: HashMap<&str, (u32, &LeaderboardEntry)> = from_entries
             .iter()
-            .map(|r| ApiLeaderboardEntry {
-                agent_symbol: ApiAgentSymbol(r.agent_symbol.clone()),
-                credits: r.credits,
-                ship_count: r.ship_count,
-                jump_gate_waypoint_symbol: ApiWaypointSymbol(r.jump_gate_waypoint_symbol.clone()),
-            })
+            .enumerate()
+            .map(|(idx, e)| (e.agent_symbol.as_str(), (idx as u32 + 1, e)))
             .collect();
+        let to_symbols: HashSet<&str> =
+            to_entries.iter().map(|e| e.agent_symbol.as_str()).collect();
+
+        let current = to_entries.iter().enumerate().map(|(idx, to)| {
+            let rank_to = idx as u32 + 1;
+            let maybe_from = from_ranks.get(to.agent_symbol.as_str());
+            ApiLeaderboardDiffEntry {
+                agent_symbol: ApiAgentSymbol(to.agent_symbol.clone()),
+                rank_from: maybe_from.map(|(rank, _)| *rank),
+                rank_to: Some(rank_to),
+                rank_change: maybe_from
+                    .map(|(rank_from, _)| i64::from(*rank_from) - i64::from(rank_to)),
+                credits_from: maybe_from.map(|(_, from)| from.credits),
+                credits_to: Some(to.credits),
+                credits_gained: maybe_from.map(|(_, from)| to.credits - from.credits),
+                is_new_entrant: maybe_from.is_none(),
+            }
+        });
 
-        Json(GetLeaderboardForResetResponseContent {
-            reset_date: ApiResetDate(reset_date.format("%Y-%m-%d").to_string()),
-            leaderboard_entries: response,
-        })
+        let dropped_out = from_entries
+            .iter()
+            .enumerate()
+            .filter(|(_, from)| !to_symbols.contains(from.agent_symbol.as_str()))
+            .map(|(idx, from)| ApiLeaderboardDiffEntry {
+                agent_symbol: ApiAgentSymbol(from.agent_symbol.clone()),
+                rank_from: Some(idx as u32 + 1),
+                rank_to: None,
+                rank_change: None,
+                credits_from: Some(from.credits),
+                credits_to: None,
+                credits_gained: None,
+                is_new_entrant: false,
+            });
+
+        current.chain(dropped_out).collect()
+    }
+
+    #[derive(Deserialize, ToSchema, Debug, Clone, Copy)]
+    #[serde(rename_all = "camelCase")]
+    pub(crate) enum AllTimePerformanceSortField {
+        Reset,
+        Rank,
+        Credits,
+        AgentSymbol,
+    }
+
+    /// Pagination, sorting and filters of the all-time performance
+    #[derive(Deserialize, IntoParams, Debug)]
+    #[into_params(parameter_in = Query)]
+    #[serde(rename_all = "camelCase")]
+    pub(crate) struct AllTimePerformanceParams {
+        /// maximum number of entries (at most 1000). Returns all entries if missing
+        limit: Option<u32>,
+        /// number of entries to skip
+        offset: Option<u32>,
+        /// defaults to reset, then rank
+        sort: Option<AllTimePerformanceSortField>,
+        /// defaults to desc for credits and to asc for everything else
+        direction: Option<SortDirection>,
+        /// only agents of this starting faction
+        faction: Option<String>,
+        /// only entries with at least this many credits
+        min_credits: Option<i64>,
+        /// only agents whose symbol contains this value (case-insensitive)
+        agent_symbol: Option<String>,
     }
 
     /// Get the ranked agents entries for all resets.
     #[utoipa::path(
     get,
     path = "/api/all-time-performance",
-    responses((status = 200, body = GetAllTimePerformanceResult)),
+    responses((status = 200, body = GetAllTimePerformanceResult), QueryApiErrorResponses),
+    params(AllTimePerformanceParams),
     )]
     pub(crate) async fn get_all_time_performance(
         State(pool): State<Pool<Sqlite>>,
-    ) -> Json<GetAllTimePerformanceResult> {
-        let performance_entries = select_all_time_performance(&pool).await.unwrap();
+        State(query_cache): State<QueryCache>,
+        ApiQuery(params): ApiQuery<AllTimePerformanceParams>,
+    ) -> Result<CachedJson<GetAllTimePerformanceResult>, ApiError> {
+        query_cache
+            .get_or_load(
+                QueryCacheKey::all_resets(format!("all-time-performance {params:?}")),
+                || async {
+                    let sort = params.sort.unwrap_or(AllTimePerformanceSortField::Reset);
+                    let direction = params.direction.unwrap_or(match sort {
+                        AllTimePerformanceSortField::Credits => SortDirection::Desc,
+                        _ => SortDirection::Asc,
+                    });
+                    // ties are ordered by reset and rank
+                    let performance_page = select_all_time_performance_page(
+                        &pool,
+                        &DbListQuery {
+                            faction: params.faction.as_deref(),
+                            agent_symbol: params.agent_symbol.as_deref(),
+                            min_credits: params.min_credits,
+                            sort_column: Some(match sort {
+                                AllTimePerformanceSortField::Reset => "reset",
+                                AllTimePerformanceSortField::Rank => "rank",
+                                AllTimePerformanceSortField::Credits => "credits",
+                                AllTimePerformanceSortField::AgentSymbol => "agent_symbol",
+                            }),
+                            direction: direction.as_sql(),
+                            limit: check_limit(params.limit)?,
+                            offset: params.offset.unwrap_or(0),
+                        },
+                    )
+                    .await?;
+
+                    Ok(GetAllTimePerformanceResult {
+                        total_count: performance_page.total_count,
+                        entries: performance_page
+                            .entries
+                            .into_iter()
+                            .map(|e| e.try_into())
+                            .collect::<Result<_, _>>()?,
+                    })
+                },
+            )
+            .await
+    }
 
-        Json(GetAllTimePerformanceResult {
-            entries: performance_entries
-                .into_iter()
-                .map(|e| e.try_into().unwrap())
-                .collect(),
-        })
+    #[derive(Deserialize, ToSchema, Debug, Clone, Copy)]
+    #[serde(rename_all = "camelCase")]
+    pub(crate) enum ConstructionLeaderboardSortField {
+        Reset,
+        RankJumpGateConstruction,
+        RankStartFortnightFinishJumpGateConstruction,
+        DurationMinutesJumpGateConstruction,
+    }
+
+    /// Pagination, sorting and filters of the all-time construction leaderboard
+    #[derive(Deserialize, IntoParams, Debug)]
+    #[into_params(parameter_in = Query)]
+    #[serde(rename_all = "camelCase")]
+    pub(crate) struct ConstructionLeaderboardParams {
+        /// maximum number of entries (at most 1000). Returns all entries if missing
+        limit: Option<u32>,
+        /// number of entries to skip
+        offset: Option<u32>,
+        /// defaults to reset, then rank. Unfinished jump gates are sorted last.
+        sort: Option<ConstructionLeaderboardSortField>,
+        /// defaults to asc
+        direction: Option<SortDirection>,
+        /// only jump gates with an agent in the system whose symbol contains this value (case-insensitive)
+        agent_symbol: Option<String>,
     }
 
     /// Get the ranked construction performance for all resets.
     #[utoipa::path(
     get,
     path = "/api/all-time-construction-leaderboard",
-    responses((status = 200, body = GetAllTimeConstructionLeaderboardResult)),
+    responses((status = 200, body = GetAllTimeConstructionLeaderboardResult), QueryApiErrorResponses),
+    params(ConstructionLeaderboardParams),
     )]
     pub(crate) async fn get_all_time_construction_leaderboard(
         State(pool): State<Pool<Sqlite>>,
-    ) -> Json<GetAllTimeConstructionLeaderboardResult> {
-        let performance_entries = select_all_time_construction_leaderboard(&pool)
+        State(query_cache): State<QueryCache>,
+        ApiQuery(params): ApiQuery<ConstructionLeaderboardParams>,
+    ) -> Result<CachedJson<GetAllTimeConstructionLeaderboardResult>, ApiError> {
+        query_cache
+            .get_or_load(
+                QueryCacheKey::all_resets(format!(
+                    "all-time-construction-leaderboard {params:?}"
+                )),
+                || async {
+                    let construction_page = select_all_time_construction_leaderboard_page(
+                        &pool,
+                        &DbListQuery {
+                            agent_symbol: params.agent_symbol.as_deref(),
+                            sort_column: params.sort.map(|sort| match sort {
+                                ConstructionLeaderboardSortField::Reset => "reset_date",
+                                ConstructionLeaderboardSortField::RankJumpGateConstruction => {
+                                    "rank__jump_gate_construction"
+                                }
+                                ConstructionLeaderboardSortField::RankStartFortnightFinishJumpGateConstruction => {
+                                    "rank__start_fortnight__finish_jump_gate_construction"
+                                }
+                                ConstructionLeaderboardSortField::DurationMinutesJumpGateConstruction => {
+                                    "duration_minutes__jump_gate_construction"
+                                }
+                            }),
+                            direction: params.direction.unwrap_or(SortDirection::Asc).as_sql(),
+                            limit: check_limit(params.limit)?,
+                            offset: params.offset.unwrap_or(0),
+                            ..Default::default()
+                        },
+                    )
+                    .await?;
+
+                    Ok(GetAllTimeConstructionLeaderboardResult {
+                        total_count: construction_page.total_count,
+                        entries: construction_page
+                            .entries
+                            .into_iter()
+                            .map(|e| e.try_into())
+                            .collect::<Result<_, _>>()?,
+                    })
+                },
+            )
             .await
-            .unwrap();
-
-        Json(GetAllTimeConstructionLeaderboardResult {
-            entries: performance_entries
-                .into_iter()
-                .map(|e| e.try_into().unwrap())
-                .collect(),
-        })
     }
 
     /// Get the jump-gate to agents assignment for a reset.
     #[utoipa::path(
     get,
     path = "/api/jump-gate-assignment/{resetDate}",
-    responses((status = 200, body = GetJumpGateAgentsAssignmentForResetResponseContent)),
+    responses((status = 200, body = GetJumpGateAgentsAssignmentForResetResponseContent), ResetApiErrorResponses),
     params(
         ("resetDate" = NaiveDate, Path, description = "The reset date"),
     )
     )]
     pub(crate) async fn get_jump_gate_agents_assignment(
         State(pool): State<Pool<Sqlite>>,
-        Path(reset_date): Path<NaiveDate>,
-    ) -> Json<GetJumpGateAgentsAssignmentForResetResponseContent> {
-        let jump_gate_assignments = load_jump_gate_assignments(&pool, reset_date).await;
-
-        Json(GetJumpGateAgentsAssignmentForResetResponseContent {
-            reset_date: ApiResetDate(reset_date.format("%Y-%m-%d").to_string()),
-            jump_gate_assignment_entries: jump_gate_assignments,
-        })
+        State(query_cache): State<QueryCache>,
+        ApiPath(reset_date): ApiPath<NaiveDate>,
+    ) -> Result<CachedJson<GetJumpGateAgentsAssignmentForResetResponseContent>, ApiError> {
+        query_cache
+            .get_or_load(
+                QueryCacheKey::for_reset(reset_date, "jump-gate-assignment"),
+                || async {
+                    load_existing_reset(&pool, reset_date).await?;
+                    let jump_gate_assignments =
+                        load_jump_gate_assignments(&pool, reset_date).await?;
+
+                    Ok(GetJumpGateAgentsAssignmentForResetResponseContent {
+                        reset_date: ApiResetDate(reset_date.format("%Y-%m-%d").to_string()),
+                        jump_gate_assignment_entries: jump_gate_assignments,
+                    })
+                },
+            )
+            .await
     }
 
     /// Get the jump-gate to agents assignment for a reset.
     #[utoipa::path(
     get,
     path = "/api/jump-gate-most-recent-progress/{resetDate}",
-    responses((status = 200, body = GetJumpGateMostRecentProgressForResetResponseContent)),
+    responses((status = 200, body = GetJumpGateMostRecentProgressForResetResponseContent), ResetApiErrorResponses),
     params(
         ("resetDate" = NaiveDate, Path, description = "The reset date"),
     )
     )]
     pub(crate) async fn get_jump_gate_most_recent_progress(
         State(pool): State<Pool<Sqlite>>,
-        Path(reset_date): Path<NaiveDate>,
-    ) -> Json<GetJumpGateMostRecentProgressForResetResponseContent> {
-        let progress_entries = load_jump_gate_most_recent_progress(&pool, reset_date).await;
-
-        Json(GetJumpGateMostRecentProgressForResetResponseContent {
-            reset_date: ApiResetDate(reset_date.format("%Y-%m-%d").to_string()),
-            progress_entries,
-        })
+        State(query_cache): State<QueryCache>,
+        ApiPath(reset_date): ApiPath<NaiveDate>,
+    ) -> Result<CachedJson<GetJumpGateMostRecentProgressForResetResponseContent>, ApiError> {
+        query_cache
+            .get_or_load(
+                QueryCacheKey::for_reset(reset_date, "jump-gate-most-recent-progress"),
+                || async {
+                    load_existing_reset(&pool, reset_date).await?;
+                    let progress_entries =
+                        load_jump_gate_most_recent_progress(&pool, reset_date).await?;
+
+                    Ok(GetJumpGateMostRecentProgressForResetResponseContent {
+                        reset_date: ApiResetDate(reset_date.format("%Y-%m-%d").to_string()),
+                        progress_entries,
+                    })
+                },
+            )
+            .await
     }
 
     /// Get the jump-gate to agents assignment for a reset.
     #[utoipa::path(
     get,
     path = "/api/jump-gate-construction-event-overview/{resetDate}",
-    responses((status = 200, body = ApiGetJumpGateConstructionEventOverviewResponse)),
+    responses((status = 200, body = ApiGetJumpGateConstructionEventOverviewResponse), ResetApiErrorResponses),
     params(
         ("resetDate" = NaiveDate, Path, description = "The reset date"),
     )
     )]
     pub(crate) async fn get_jump_gate_construction_event_overview(
         State(pool): State<Pool<Sqlite>>,
-        Path(reset_date): Path<NaiveDate>,
-    ) -> Json<ApiGetJumpGateConstructionEventOverviewResponse> {
-        let db_progress_entries =
-            select_jump_gate_construction_event_overview_for_reset(&pool, reset_date)
-                .await
-                .unwrap();
-
-        let progress_entries: Vec<ApiJumpGateConstructionEventOverviewEntry> = db_progress_entries
-            .iter()
-            .map(|e| e.clone().try_into().unwrap())
-            .collect();
+        State(query_cache): State<QueryCache>,
+        ApiPath(reset_date): ApiPath<NaiveDate>,
+    ) -> Result<CachedJson<ApiGetJumpGateConstructionEventOverviewResponse>, ApiError> {
+        query_cache
+            .get_or_load(
+                QueryCacheKey::for_reset(reset_date, "jump-gate-construction-event-overview"),
+                || async {
+                    load_existing_reset(&pool, reset_date).await?;
+                    let db_progress_entries =
+                        select_jump_gate_construction_event_overview_for_reset(&pool, reset_date)
+                            .await?;
+
+                    let progress_entries: Vec<ApiJumpGateConstructionEventOverviewEntry> =
+                        db_progress_entries
+                            .iter()
+                            .map(|e| e.clone().try_into())
+                            .collect::<Result<_, _>>()?;
+
+                    Ok(ApiGetJumpGateConstructionEventOverviewResponse {
+                        reset_date: ApiResetDate(reset_date.format("%Y-%m-%d").to_string()),
+                        event_entries: progress_entries,
+                    })
+                },
+            )
+            .await
+    }
 
-        Json(ApiGetJumpGateConstructionEventOverviewResponse {
-            reset_date: ApiResetDate(reset_date.format("%Y-%m-%d").to_string()),
-            event_entries: progress_entries,
-        })
+    /// Loads the reset or fails with 404. Endpoints for a single reset call this first to
+    /// distinguish an unknown reset from a reset without data.
+    pub(crate) async fn load_existing_reset(
+        pool: &Pool<Sqlite>,
+        reset_date: NaiveDate,
+    ) -> Result<ResetDate, ApiError> {
+        load_reset_date(pool, reset_date)
+            .await?
+            .ok_or_else(|| ApiError::NotFound(format!("unknown reset date {reset_date}")))
     }
 
     async fn load_jump_gate_assignments(
         pool: &Pool<Sqlite>,
         reset_date: NaiveDate,
-    ) -> Vec<ApiJumpGateAssignmentEntry> {
+    ) -> Result<Vec<ApiJumpGateAssignmentEntry>, ApiError> {
         let db_jump_gate_assignment_entries =
-            select_jump_gate_agent_assignment_for_reset(&pool, reset_date)
-                .await
-                .unwrap();
+            select_jump_gate_agent_assignment_for_reset(pool, reset_date).await?;
 
         let jump_gate_assignments = db_jump_gate_assignment_entries
             .iter()
@@ -534,30 +1389,109 @@ pub mod leaderboard {
                     .collect(),
             })
             .collect();
-        jump_gate_assignments
+        Ok(jump_gate_assignments)
     }
 
     async fn load_jump_gate_most_recent_progress(
         pool: &Pool<Sqlite>,
         reset_date: NaiveDate,
-    ) -> Vec<ApiConstructionMaterialMostRecentProgressEntry> {
+    ) -> Result<Vec<ApiConstructionMaterialMostRecentProgressEntry>, ApiError> {
         let db_progress_entries =
-            select_most_recent_construction_progress_for_reset(&pool, reset_date)
-                .await
-                .unwrap();
+            select_most_recent_construction_progress_for_reset(pool, reset_date).await?;
 
-        let jump_gate_assignments = db_progress_entries
+        db_progress_entries
             .iter()
-            .map(|r| r.clone().try_into().unwrap())
-            .collect();
-        jump_gate_assignments
+            .map(|r| r.clone().try_into())
+            .collect()
+    }
+
+    #[derive(Deserialize, IntoParams)]
+    #[into_params(parameter_in = Query)]
+    pub(crate) struct ExportParams {
+        /// file format of the tables. Defaults to jsonl
+        format: Option<ExportFormat>,
+    }
+
+    /// Export all data of a reset as zip archive with a manifest.json describing the tables
+    #[utoipa::path(
+    get,
+    path = "/api/export/{resetDate}",
+    responses((status = 200, description = "zip archive with manifest.json and one file per table", content_type = "application/zip", body = Vec<u8>), ResetApiErrorResponses),
+    params(
+        ("resetDate" = NaiveDate, Path, description = "The reset date"),
+        ExportParams,
+    )
+    )]
+    pub(crate) async fn get_reset_export(
+        State(pool): State<Pool<Sqlite>>,
+        ApiPath(reset_date): ApiPath<NaiveDate>,
+        ApiQuery(params): ApiQuery<ExportParams>,
+    ) -> Result<Response, ApiError> {
+        let format = params.format.unwrap_or(ExportFormat::Jsonl);
+
+        let export = load_reset_export(&pool, reset_date)
+            .await?
+            .ok_or_else(|| ApiError::NotFound(format!("unknown reset date {reset_date}")))?;
+
+        // encoding parquet is cpu-heavy - keep it off the async workers
+        let zip_content = tokio::task::spawn_blocking(move || export.to_zip(format))
+            .await
+            .map_err(|err| ApiError::Internal(err.to_string()))??;
+
+        let file_name = format!(
+            "flwi-leaderboard-reset-{}-{}.zip",
+            reset_date.format("%Y-%m-%d"),
+            format.file_extension()
+        );
+
+        Ok((
+            [
+                (header::CONTENT_TYPE, "application/zip".to_string()),
+                (
+                    header::CONTENT_DISPOSITION,
+                    format!("attachment; filename=\"{file_name}\""),
+                ),
+            ],
+            zip_content,
+        )
+            .into_response())
     }
 
     #[derive(Deserialize, ToSchema, Debug, Clone)]
     #[serde(rename_all = "camelCase")]
     pub(crate) enum RangeSelectionMode {
+        /// from `eventTimeMinutesGte` (default 0) to `eventTimeMinutesLte` minutes after the start of the reset
         First,
+        /// the last `eventTimeMinutesLte` minutes of the reset
         Last,
+        /// from `fromTimestamp` to `toTimestamp`
+        Absolute,
+        /// `eventTimeMinutesLte` minutes before and after `aroundEvent`
+        AroundEvent,
+        /// the event times of the last `eventTimeMinutesLte` minutes of `referenceResetDate`.
+        /// Compares e.g. the current day of the ongoing reset with the same day of previous resets.
+        SameWindowAsReset,
+    }
+
+    /// Event of a reset a window can be centered on
+    #[derive(Deserialize, ToSchema, Debug, Clone, Copy)]
+    #[serde(rename_all = "camelCase")]
+    pub(crate) enum ApiResetEvent {
+        JumpGateConstructionStarted,
+        JumpGateConstructionCompleted,
+    }
+
+    impl Display for ApiResetEvent {
+        fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+            match self {
+                ApiResetEvent::JumpGateConstructionStarted => {
+                    write!(f, "start of the jump gate construction")
+                }
+                ApiResetEvent::JumpGateConstructionCompleted => {
+                    write!(f, "completion of the jump gate construction")
+                }
+            }
+        }
     }
 
     /// Filter for period and agent symbols
@@ -568,13 +1502,103 @@ pub mod leaderboard {
         pub(crate) event_time_minutes_lte: u32,
         pub(crate) event_time_minutes_gte: Option<u32>,
         pub(crate) selection_mode: RangeSelectionMode,
+        /// overrides the resolution chosen from the length of the period.
+        /// Multiple of 5 between 5 and 1440 minutes
+        pub(crate) resolution_minutes: Option<u32>,
+        /// start of the period for selection mode `absolute`
+        pub(crate) from_timestamp: Option<NaiveDateTime>,
+        /// end of the period for selection mode `absolute`
+        pub(crate) to_timestamp: Option<NaiveDateTime>,
+        /// event for selection mode `aroundEvent`
+        pub(crate) around_event: Option<ApiResetEvent>,
+        /// jump gate of `aroundEvent`. Defaults to the jump gate of the first agent of `agentSymbols`
+        pub(crate) jump_gate_waypoint_symbol: Option<String>,
+        /// reset for selection mode `sameWindowAsReset`
+        pub(crate) reference_reset_date: Option<NaiveDate>,
+    }
+
+    /// Query parameters of the GET variant of the history endpoint
+    #[derive(Deserialize, IntoParams, Debug)]
+    #[into_params(parameter_in = Query)]
+    #[serde(rename_all = "camelCase")]
+    pub(crate) struct HistoryParams {
+        /// comma-separated agent symbols
+        agent_symbols: String,
+        event_time_minutes_lte: u32,
+        event_time_minutes_gte: Option<u32>,
+        #[param(inline)]
+        selection_mode: RangeSelectionMode,
+        /// overrides the resolution chosen from the length of the period.
+        /// Multiple of 5 between 5 and 1440 minutes
+        resolution_minutes: Option<u32>,
+        /// start of the period for selection mode `absolute`
+        from_timestamp: Option<NaiveDateTime>,
+        /// end of the period for selection mode `absolute`
+        to_timestamp: Option<NaiveDateTime>,
+        /// event for selection mode `aroundEvent`
+        #[param(inline)]
+        around_event: Option<ApiResetEvent>,
+        /// jump gate of `aroundEvent`. Defaults to the jump gate of the first agent of `agentSymbols`
+        jump_gate_waypoint_symbol: Option<String>,
+        /// reset for selection mode `sameWindowAsReset`
+        reference_reset_date: Option<NaiveDate>,
+    }
+
+    impl From<HistoryParams> for ApiResetAgentPeriodFilterBody {
+        fn from(params: HistoryParams) -> Self {
+            ApiResetAgentPeriodFilterBody {
+                agent_symbols: params
+                    .agent_symbols
+                    .split(',')
+                    .map(|s| s.trim().to_string())
+                    .filter(|s| !s.is_empty())
+                    .collect(),
+                event_time_minutes_lte: params.event_time_minutes_lte,
+                event_time_minutes_gte: params.event_time_minutes_gte,
+                selection_mode: params.selection_mode,
+                resolution_minutes: params.resolution_minutes,
+                from_timestamp: params.from_timestamp,
+                to_timestamp: params.to_timestamp,
+                around_event: params.around_event,
+                jump_gate_waypoint_symbol: params.jump_gate_waypoint_symbol,
+                reference_reset_date: params.reference_reset_date,
+            }
+        }
+    }
+
+    /// Get the history data for a reset. Same as the POST variant, but cacheable and shareable.
+    #[utoipa::path(
+    get,
+    path = "/api/history/{resetDate}",
+    responses((status = 200, body = GetHistoryDataForResetResponseContent), ResetApiErrorResponses),
+    params(
+        ("resetDate" = NaiveDate, Path, description = "The reset date"),
+        HistoryParams,
+    )
+    )]
+    pub(crate) async fn get_history_data_for_reset_by_query(
+        State(pool): State<Pool<Sqlite>>,
+        State(query_cache): State<QueryCache>,
+        ApiPath(reset_date): ApiPath<NaiveDate>,
+        ApiQuery(params): ApiQuery<HistoryParams>,
+    ) -> Result<CachedJson<GetHistoryDataForResetResponseContent>, ApiError> {
+        let query = format!("history {reset_date} {params:?}");
+        // the window of a reference reset moves with each tick of the ongoing reset
+        let key = if params.reference_reset_date.is_some() {
+            QueryCacheKey::all_resets(query)
+        } else {
+            QueryCacheKey::for_reset(reset_date, query)
+        };
+        query_cache
+            .get_or_load(key, || load_history_data(&pool, reset_date, params.into()))
+            .await
     }
 
     /// Get the history data for a reset
     #[utoipa::path(
     post,
     path = "/api/history/{resetDate}",
-    responses((status = 200, body = GetHistoryDataForResetResponseContent)),
+    responses((status = 200, body = GetHistoryDataForResetResponseContent), ResetApiErrorResponses),
     params(
         ("resetDate" = NaiveDate, Path, description = "The reset date"),
     ),
@@ -582,18 +1606,28 @@ pub mod leaderboard {
     )]
     pub(crate) async fn get_history_data_for_reset(
         State(pool): State<Pool<Sqlite>>,
-        Path(reset_date): Path<NaiveDate>,
-        Json(filter): Json<ApiResetAgentPeriodFilterBody>,
-    ) -> Json<GetHistoryDataForResetResponseContent> {
-        let jump_gate_assignments = load_jump_gate_assignments(&pool, reset_date).await;
+        ApiPath(reset_date): ApiPath<NaiveDate>,
+        ApiJson(filter): ApiJson<ApiResetAgentPeriodFilterBody>,
+    ) -> Result<Json<GetHistoryDataForResetResponseContent>, ApiError> {
+        load_history_data(&pool, reset_date, filter).await.map(Json)
+    }
+
+    async fn load_history_data(
+        pool: &Pool<Sqlite>,
+        reset_date: NaiveDate,
+        filter: ApiResetAgentPeriodFilterBody,
+    ) -> Result<GetHistoryDataForResetResponseContent, ApiError> {
+        let reset_infos = load_existing_reset(pool, reset_date).await?;
 
-        let reset_infos = load_reset_date(&pool, reset_date).await.unwrap().unwrap();
+        let jump_gate_assignments = load_jump_gate_assignments(pool, reset_date).await?;
 
+        let window =
+            resolve_reset_window(pool, &filter, &reset_infos, &jump_gate_assignments).await?;
         let ResetPeriodFilter {
             from_event_time_minutes,
             to_event_time_minutes,
             resolution_minutes,
-        } = extract_reset_period_from_filter(&filter, reset_infos);
+        } = extract_reset_period_from_filter(window, &filter, &reset_infos)?;
 
         let agent_symbols = filter.agent_symbols; //.unwrap_or(vec![]);
 
@@ -609,63 +1643,56 @@ pub mod leaderboard {
             .collect();
 
         let construction_material_progress = select_construction_progress_for_reset(
-            &pool,
+            pool,
             reset_date,
             from_event_time_minutes.into(),
             to_event_time_minutes.into(),
             resolution_minutes,
             jump_gate_symbols.clone(),
         )
-        .await
-        .unwrap();
+        .await?;
 
         let agent_history_progress = select_agent_history(
-            &pool,
+            pool,
             reset_date,
             from_event_time_minutes.into(),
             to_event_time_minutes.into(),
             resolution_minutes,
             agent_symbols.clone(),
         )
-        .await
-        .unwrap();
+        .await?;
 
         let api_construction_progress: Vec<_> = construction_material_progress
             .iter()
-            .map(|cmp| ApiConstructionMaterialHistoryEntry::try_from(cmp.clone()).unwrap())
-            .collect();
+            .map(|cmp| ApiConstructionMaterialHistoryEntry::try_from(cmp.clone()))
+            .collect::<Result<_, _>>()?;
 
         let api_agent_history_progress: Vec<_> = agent_history_progress
             .iter()
-            .map(|cmp| ApiAgentHistoryEntry::try_from(cmp.clone()).unwrap())
-            .collect();
+            .map(|cmp| ApiAgentHistoryEntry::try_from(cmp.clone()))
+            .collect::<Result<_, _>>()?;
 
         let num_jump_gates = jump_gate_symbols.len();
         let num_agents = agent_symbols.len();
 
         event!(
             Level::DEBUG,
-            "Done collecting history agent data for {num_agents}. from_event_time_minutes: {from_event_time_minutes}; to_event_time_minutes: {to_event_time_minutes}; selection_mode: {selection_mode:?}",
-            selection_mode=filter.selection_mode
-        );
-        event!(
-            Level::DEBUG,
-            "Agent Symbols are {}",
-            agent_symbols.join(", ")
-        );
-        //dbg!(agent_history_progress);
-        event!(
-            Level::DEBUG,
-            "Done collecting history construction data for {num_jump_gates} jump gates",
+            reset = %reset_date,
+            num_agents,
+            num_jump_gates,
+            from_event_time_minutes,
+            to_event_time_minutes,
+            resolution_minutes,
+            selection_mode = ?filter.selection_mode,
+            "Done collecting history data"
         );
         event!(
-            Level::DEBUG,
-            "Jump Gate symbols are {}",
-            jump_gate_symbols.join(", ")
+            Level::TRACE,
+            agent_symbols = agent_symbols.join(", "),
+            jump_gate_symbols = jump_gate_symbols.join(", "),
+            "Symbols of the history data"
         );
 
-        //dbg!(construction_material_progress);
-
         let response = GetHistoryDataForResetResponseContent {
             requested_agents: agent_symbols
                 .iter()
@@ -677,14 +1704,132 @@ pub mod leaderboard {
             resolution_minutes,
         };
 
-        Json(response)
+        Ok(response)
     }
+
+    /// Resolves the selection mode of the filter into the window to select. Loads the event or the
+    /// reference reset the window depends on.
+    async fn resolve_reset_window(
+        pool: &Pool<Sqlite>,
+        filter: &ApiResetAgentPeriodFilterBody,
+        reset_infos: &ResetDate,
+        jump_gate_assignments: &[ApiJumpGateAssignmentEntry],
+    ) -> Result<ResetWindow, ApiError> {
+        let event_time_minutes_lte = filter.event_time_minutes_lte;
+        match filter.selection_mode {
+            RangeSelectionMode::First => Ok(ResetWindow::First {
+                event_time_minutes_gte: filter.event_time_minutes_gte,
+                event_time_minutes_lte,
+            }),
+            RangeSelectionMode::Last => Ok(ResetWindow::Last {
+                event_time_minutes_lte,
+            }),
+            RangeSelectionMode::Absolute => match (filter.from_timestamp, filter.to_timestamp) {
+                (Some(from), Some(to)) => Ok(ResetWindow::Absolute {
+                    first_ts: reset_infos.first_ts,
+                    from,
+                    to,
+                }),
+                _ => Err(ApiError::BadRequest(
+                    "selectionMode absolute requires fromTimestamp and toTimestamp".to_string(),
+                )),
+            },
+            RangeSelectionMode::AroundEvent => {
+                let around_event = filter.around_event.ok_or_else(|| {
+                    ApiError::BadRequest(
+                        "selectionMode aroundEvent requires aroundEvent".to_string(),
+                    )
+                })?;
+                let jump_gate_waypoint_symbol = match &filter.jump_gate_waypoint_symbol {
+                    Some(symbol) => symbol.clone(),
+                    None => jump_gate_assignments
+                        .iter()
+                        .find(|j| {
+                            j.agents_in_system
+                                .iter()
+                                .any(|a| filter.agent_symbols.first() == Some(&a.0))
+                        })
+                        .map(|j| j.jump_gate_waypoint_symbol.0.clone())
+                        .ok_or_else(|| {
+                            ApiError::BadRequest(
+                                "selectionMode aroundEvent requires jumpGateWaypointSymbol or a known agent in agentSymbols".to_string(),
+                            )
+                        })?,
+                };
+
+                let construction_events =
+                    select_jump_gate_construction_event_overview_for_reset(pool, reset_infos.reset)
+                        .await?;
+                let materials = construction_events
+                    .iter()
+                    .filter(|e| e.jump_gate_waypoint_symbol == jump_gate_waypoint_symbol);
+                let maybe_event_ts = match around_event {
+                    ApiResetEvent::JumpGateConstructionStarted => {
+                        materials.map(|m| m.ts_first_construction_event).min()
+                    }
+                    ApiResetEvent::JumpGateConstructionCompleted => materials
+                        .filter(|m| m.is_jump_gate_complete == Some(true))
+                        .filter_map(|m| m.ts_last_construction_event)
+                        .max(),
+                };
+                let event_ts = maybe_event_ts.ok_or_else(|| {
+                    ApiError::NotFound(format!(
+                        "no {around_event} of {jump_gate_waypoint_symbol} in reset {}",
+                        reset_infos.reset
+                    ))
+                })?;
+
+                Ok(ResetWindow::AroundEvent {
+                    event_time_minutes: (event_ts - reset_infos.first_ts).num_minutes().max(0)
+                        as u32,
+                    window_minutes: event_time_minutes_lte,
+                })
+            }
+            RangeSelectionMode::SameWindowAsReset => {
+                let reference_reset_date = filter.reference_reset_date.ok_or_else(|| {
+                    ApiError::BadRequest(
+                        "selectionMode sameWindowAsReset requires referenceResetDate".to_string(),
+                    )
+                })?;
+                let reference = load_existing_reset(pool, reference_reset_date).await?;
+                Ok(ResetWindow::SameWindowAsReference {
+                    reference_num_minutes: reset_num_minutes(&reference),
+                    event_time_minutes_lte,
+                })
+            }
+        }
+    }
+}
+
+/// Window of a reset, resolved from the selection mode of a filter
+#[derive(Debug, Clone)]
+pub(crate) enum ResetWindow {
+    First {
+        event_time_minutes_gte: Option<u32>,
+        event_time_minutes_lte: u32,
+    },
+    Last {
+        event_time_minutes_lte: u32,
+    },
+    Absolute {
+        first_ts: NaiveDateTime,
+        from: NaiveDateTime,
+        to: NaiveDateTime,
+    },
+    AroundEvent {
+        event_time_minutes: u32,
+        window_minutes: u32,
+    },
+    SameWindowAsReference {
+        reference_num_minutes: u32,
+        event_time_minutes_lte: u32,
+    },
 }
 
-struct ResetPeriodFilter {
-    from_event_time_minutes: i64,
-    to_event_time_minutes: i64,
-    resolution_minutes: i64,
+pub(crate) struct ResetPeriodFilter {
+    pub(crate) from_event_time_minutes: i64,
+    pub(crate) to_event_time_minutes: i64,
+    pub(crate) resolution_minutes: i64,
 }
 
 fn safe_range(v1: u32, v2: u32) -> RangeInclusive<u32> {
@@ -695,46 +1840,94 @@ fn safe_range(v1: u32, v2: u32) -> RangeInclusive<u32> {
     }
 }
 
+/// bounds for an explicitly requested resolution. Data is collected every 5 minutes
+const MIN_RESOLUTION_MINUTES: u32 = 5;
+const MAX_RESOLUTION_MINUTES: u32 = 24 * 60;
+
+fn reset_num_minutes(reset_infos: &ResetDate) -> u32 {
+    (reset_infos.latest_ts - reset_infos.first_ts)
+        .num_minutes()
+        .unsigned_abs() as u32
+}
+
 fn extract_reset_period_from_filter(
+    window: ResetWindow,
     filter: &ApiResetAgentPeriodFilterBody,
-    reset_infos: ResetDate,
-) -> ResetPeriodFilter {
-    let event_time_minutes_gte = filter.event_time_minutes_gte;
-    let event_time_minutes_lte = filter.event_time_minutes_lte;
-    let num_minutes = (reset_infos.latest_ts - reset_infos.first_ts)
-        .num_minutes()
-        .abs() as u32;
+    reset_infos: &ResetDate,
+) -> Result<ResetPeriodFilter, ApiError> {
+    let period = extract_reset_period(window, reset_num_minutes(reset_infos));
+
+    match filter.resolution_minutes {
+        None => Ok(period),
+        Some(resolution_minutes)
+            if (MIN_RESOLUTION_MINUTES..=MAX_RESOLUTION_MINUTES).contains(&resolution_minutes)
+                && resolution_minutes % MIN_RESOLUTION_MINUTES == 0 =>
+        {
+            Ok(ResetPeriodFilter {
+                resolution_minutes: resolution_minutes.into(),
+                ..period
+            })
+        }
+        Some(resolution_minutes) => Err(ApiError::BadRequest(format!(
+            "resolutionMinutes must be a multiple of {MIN_RESOLUTION_MINUTES} between {MIN_RESOLUTION_MINUTES} and {MAX_RESOLUTION_MINUTES}, got {resolution_minutes}"
+        ))),
+    }
+}
 
-    extract_reset_period(
-        filter.selection_mode.clone(),
-        event_time_minutes_gte,
-        event_time_minutes_lte,
-        num_minutes,
-    )
+fn last_minutes_range(event_time_minutes_lte: u32, num_minutes: u32) -> RangeInclusive<u32> {
+    if event_time_minutes_lte > num_minutes {
+        safe_range(0, num_minutes)
+    } else {
+        // event_time_minutes_lte <= num_minutes
+        // reset is 14 days old
+        // asks for last 7 days
+        // [14d-7d ... 14 d]
+        safe_range(num_minutes - event_time_minutes_lte, num_minutes)
+    }
 }
 
-fn extract_reset_period(
-    selection_mode: RangeSelectionMode,
-    event_time_minutes_gte: Option<u32>,
-    event_time_minutes_lte: u32,
-    num_minutes: u32,
-) -> ResetPeriodFilter {
-    let event_time_minutes = match selection_mode {
-        RangeSelectionMode::First => {
-            safe_range(event_time_minutes_gte.unwrap_or(0), event_time_minutes_lte)
+pub(crate) fn extract_reset_period(window: ResetWindow, num_minutes: u32) -> ResetPeriodFilter {
+    let event_time_minutes = match window {
+        ResetWindow::First {
+            event_time_minutes_gte,
+            event_time_minutes_lte,
+        } => safe_range(event_time_minutes_gte.unwrap_or(0), event_time_minutes_lte),
+        ResetWindow::Last {
+            event_time_minutes_lte,
+        } => {
+            event!(
+                Level::TRACE,
+                num_minutes,
+                "Selecting the last minutes of the reset"
+            );
+            last_minutes_range(event_time_minutes_lte, num_minutes)
         }
-        RangeSelectionMode::Last => {
-            event!(Level::DEBUG, "num_minutes {}", num_minutes);
-
-            if event_time_minutes_lte > num_minutes {
-                safe_range(0, num_minutes)
-            } else {
-                // event_time_minutes_lte <= num_minutes
-                // reset is 14 days old
-                // asks for last 7 days
-                // [14d-7d ... 14 d]
-                safe_range(num_minutes - event_time_minutes_lte, num_minutes)
-            }
+        ResetWindow::Absolute { first_ts, from, to } => {
+            // timestamps outside of the reset select its first or last entries
+            let to_event_time_minutes = |ts: NaiveDateTime| {
+                (ts - first_ts).num_minutes().clamp(0, num_minutes.into()) as u32
+            };
+            safe_range(to_event_time_minutes(from), to_event_time_minutes(to))
+        }
+        ResetWindow::AroundEvent {
+            event_time_minutes,
+            window_minutes,
+        } => safe_range(
+            event_time_minutes.saturating_sub(window_minutes),
+            event_time_minutes
+                .saturating_add(window_minutes)
+                .min(num_minutes.max(event_time_minutes)),
+        ),
+        ResetWindow::SameWindowAsReference {
+            reference_num_minutes,
+            event_time_minutes_lte,
+        } => {
+            let reference = last_minutes_range(event_time_minutes_lte, reference_num_minutes);
+            // a reset that is shorter than the reference only has the beginning of the window
+            safe_range(
+                (*reference.start()).min(num_minutes),
+                (*reference.end()).min(num_minutes),
+            )
         }
     };
 
@@ -757,14 +1950,19 @@ fn extract_reset_period(
     }
 }
 
-fn parse_csv<T: FromStr>(s: &str) -> Vec<T> {
+pub(crate) fn parse_csv<T: FromStr>(s: &str) -> Vec<T> {
     s.split(',')
         .filter_map(|item| item.trim().parse::<T>().ok())
         .collect()
 }
 
+fn to_u32(value: i64, field_name: &str) -> Result<u32, ApiError> {
+    u32::try_from(value)
+        .map_err(|_| ApiError::Internal(format!("{field_name} is out of range: {value}")))
+}
+
 impl TryFrom<DbConstructionMaterialHistoryEntry> for ApiConstructionMaterialHistoryEntry {
-    type Error = ();
+    type Error = ApiError;
     fn try_from(cmp: DbConstructionMaterialHistoryEntry) -> Result<Self, Self::Error> {
         Ok(ApiConstructionMaterialHistoryEntry {
             jump_gate_waypoint_symbol: ApiWaypointSymbol(cmp.jump_gate_waypoint_symbol),
@@ -784,13 +1982,13 @@ impl TryFrom<DbConstructionMaterialHistoryEntry> for ApiConstructionMaterialHist
 }
 
 impl TryFrom<DbAgentHistoryEntry> for ApiAgentHistoryEntry {
-    type Error = ();
+    type Error = ApiError;
     fn try_from(db: DbAgentHistoryEntry) -> Result<Self, Self::Error> {
         Ok(ApiAgentHistoryEntry {
             agent_symbol: ApiAgentSymbol(db.agent_symbol),
-            event_times_minutes: db.event_times_minutes.unwrap().0,
-            credits_timeline: db.credits_timeline.unwrap().0,
-            ship_count_timeline: db.ship_count_timeline.unwrap().0,
+            event_times_minutes: db.event_times_minutes.map(|j| j.0).unwrap_or_default(),
+            credits_timeline: db.credits_timeline.map(|j| j.0).unwrap_or_default(),
+            ship_count_timeline: db.ship_count_timeline.map(|j| j.0).unwrap_or_default(),
         })
     }
 }
@@ -798,12 +1996,12 @@ impl TryFrom<DbAgentHistoryEntry> for ApiAgentHistoryEntry {
 impl TryFrom<DbConstructionMaterialMostRecentStatus>
     for ApiConstructionMaterialMostRecentProgressEntry
 {
-    type Error = ();
+    type Error = ApiError;
     fn try_from(db: DbConstructionMaterialMostRecentStatus) -> Result<Self, Self::Error> {
         Ok(ApiConstructionMaterialMostRecentProgressEntry {
             trade_symbol: ApiTradeSymbol(db.trade_symbol),
-            fulfilled: u32::try_from(db.fulfilled).unwrap(),
-            required: u32::try_from(db.required).unwrap(),
+            fulfilled: to_u32(db.fulfilled, "fulfilled")?,
+            required: to_u32(db.required, "required")?,
             jump_gate_waypoint_symbol: ApiWaypointSymbol(db.jump_gate_waypoint_symbol),
             is_jump_gate_complete: db.is_jump_gate_complete,
         })
@@ -813,13 +2011,13 @@ impl TryFrom<DbConstructionMaterialMostRecentStatus>
 impl TryFrom<DbJumpGateConstructionEventOverviewEntry>
     for ApiJumpGateConstructionEventOverviewEntry
 {
-    type Error = ();
+    type Error = ApiError;
     fn try_from(db: DbJumpGateConstructionEventOverviewEntry) -> Result<Self, Self::Error> {
         Ok(ApiJumpGateConstructionEventOverviewEntry {
             ts_start_of_reset: db.ts_start_of_reset,
             trade_symbol: ApiTradeSymbol(db.trade_symbol),
-            fulfilled: u32::try_from(db.fulfilled.unwrap_or(0)).unwrap(),
-            required: u32::try_from(db.required).unwrap(),
+            fulfilled: to_u32(db.fulfilled.unwrap_or(0), "fulfilled")?,
+            required: to_u32(db.required, "required")?,
             jump_gate_waypoint_symbol: ApiWaypointSymbol(db.jump_gate_waypoint_symbol),
             ts_first_construction_event: db.ts_first_construction_event,
             ts_last_construction_event: db.ts_last_construction_event,
@@ -828,20 +2026,31 @@ impl TryFrom<DbJumpGateConstructionEventOverviewEntry>
     }
 }
 
+impl TryFrom<DbJobRunRef> for ApiJobRunRef {
+    type Error = ApiError;
+    fn try_from(db: DbJobRunRef) -> Result<Self, Self::Error> {
+        Ok(ApiJobRunRef {
+            query_time: db.query_time,
+            event_time_minutes: to_u32(db.event_time_minutes, "event_time_minutes")?,
+        })
+    }
+}
+
 impl TryFrom<DbAllTimePerformanceEntry> for ApiAllTimePerformanceEntry {
-    type Error = ();
+    type Error = ApiError;
     fn try_from(db: DbAllTimePerformanceEntry) -> Result<Self, Self::Error> {
         Ok(ApiAllTimePerformanceEntry {
             reset: ApiResetDate(db.reset.format("%Y-%m-%d").to_string()),
             agent_symbol: ApiAgentSymbol(db.agent_symbol),
+            starting_faction: db.starting_faction,
             credits: db.credits,
-            rank: u32::try_from(db.rank).unwrap(),
+            rank: to_u32(db.rank, "rank")?,
         })
     }
 }
 
 impl TryFrom<DbConstructionLeaderboardEntry> for ApiAllTimeConstructionLeaderboardEntry {
-    type Error = ();
+    type Error = ApiError;
     fn try_from(db: DbConstructionLeaderboardEntry) -> Result<Self, Self::Error> {
         Ok(ApiAllTimeConstructionLeaderboardEntry {
             reset: ApiResetDate(db.reset_date.format("%Y-%m-%d").to_string()),
@@ -854,25 +2063,28 @@ impl TryFrom<DbConstructionLeaderboardEntry> for ApiAllTimeConstructionLeaderboa
                 .collect(),
             ts_start_jump_gate_construction: db.ts_start_jump_gate_construction,
             ts_finish_jump_gate_construction: db.ts_finish_jump_gate_construction,
-            duration_minutes_start_fortnight_start_jump_gate_construction: u32::try_from(
+            duration_minutes_start_fortnight_start_jump_gate_construction: to_u32(
                 db.duration_minutes_start_fortnight_start_jump_gate_construction,
-            )
-            .unwrap(),
+                "duration_minutes_start_fortnight_start_jump_gate_construction",
+            )?,
             duration_minutes_start_fortnight_finish_jump_gate_construction: db
                 .duration_minutes_start_fortnight_finish_jump_gate_construction
                 .and_then(|x| x.try_into().ok()),
             duration_minutes_jump_gate_construction: db
                 .duration_minutes_jump_gate_construction
                 .and_then(|x| x.try_into().ok()),
-            rank_jump_gate_construction: u32::try_from(db.rank_jump_gate_construction).unwrap(),
-            rank_start_fortnight_start_jump_gate_construction: u32::try_from(
+            rank_jump_gate_construction: to_u32(
+                db.rank_jump_gate_construction,
+                "rank_jump_gate_construction",
+            )?,
+            rank_start_fortnight_start_jump_gate_construction: to_u32(
                 db.rank_start_fortnight_start_jump_gate_construction,
-            )
-            .unwrap(),
-            rank_start_fortnight_finish_jump_gate_construction: u32::try_from(
+                "rank_start_fortnight_start_jump_gate_construction",
+            )?,
+            rank_start_fortnight_finish_jump_gate_construction: to_u32(
                 db.rank_start_fortnight_finish_jump_gate_construction,
-            )
-            .unwrap(),
+                "rank_start_fortnight_finish_jump_gate_construction",
+            )?,
         })
     }
 }
@@ -882,12 +2094,20 @@ mod tests {
     use std::ops::Add;
 
     use super::*;
+    use crate::db::LeaderboardEntry;
+    use crate::server::leaderboard::{diff_leaderboards, RangeSelectionMode};
 
     const LAST_WEEK_TEST_DATA: ApiResetAgentPeriodFilterBody = ApiResetAgentPeriodFilterBody {
         agent_symbols: vec![],
         event_time_minutes_lte: TimeDelta::weeks(1).num_minutes() as u32,
         event_time_minutes_gte: None,
         selection_mode: RangeSelectionMode::Last,
+        resolution_minutes: None,
+        from_timestamp: None,
+        to_timestamp: None,
+        around_event: None,
+        jump_gate_waypoint_symbol: None,
+        reference_reset_date: None,
     };
 
     const LAST_DAY_TEST_DATA: ApiResetAgentPeriodFilterBody = ApiResetAgentPeriodFilterBody {
@@ -895,15 +2115,21 @@ mod tests {
         event_time_minutes_lte: TimeDelta::days(1).num_minutes() as u32,
         event_time_minutes_gte: None,
         selection_mode: RangeSelectionMode::Last,
+        resolution_minutes: None,
+        from_timestamp: None,
+        to_timestamp: None,
+        around_event: None,
+        jump_gate_waypoint_symbol: None,
+        reference_reset_date: None,
     };
 
     #[test]
     fn test_last_week_of_reset_when_reset_is_less_than_one_week_old() {
         let age_of_reset = TimeDelta::days(4).add(TimeDelta::hours(19));
         let actual = extract_reset_period(
-            RangeSelectionMode::Last,
-            LAST_WEEK_TEST_DATA.event_time_minutes_gte,
-            LAST_WEEK_TEST_DATA.event_time_minutes_lte,
+            ResetWindow::Last {
+                event_time_minutes_lte: LAST_WEEK_TEST_DATA.event_time_minutes_lte,
+            },
             age_of_reset.num_minutes() as u32,
         );
         assert_eq!(actual.resolution_minutes, 60);
@@ -915,9 +2141,9 @@ mod tests {
     fn test_last_day_of_reset_when_reset_is_one_week_old() {
         let age_of_reset = TimeDelta::days(7);
         let actual = extract_reset_period(
-            RangeSelectionMode::Last,
-            LAST_DAY_TEST_DATA.event_time_minutes_gte,
-            LAST_DAY_TEST_DATA.event_time_minutes_lte,
+            ResetWindow::Last {
+                event_time_minutes_lte: LAST_DAY_TEST_DATA.event_time_minutes_lte,
+            },
             age_of_reset.num_minutes() as u32,
         );
         assert_eq!(actual.resolution_minutes, 60);
@@ -927,4 +2153,225 @@ mod tests {
         );
         assert_eq!(actual.to_event_time_minutes, age_of_reset.num_minutes());
     }
+
+    #[test]
+    fn test_absolute_range_is_clamped_to_reset() {
+        let first_ts = NaiveDate::from_ymd_opt(2024, 3, 24)
+            .unwrap()
+            .and_hms_opt(12, 0, 0)
+            .unwrap();
+        let age_of_reset = TimeDelta::days(2);
+        let actual = extract_reset_period(
+            ResetWindow::Absolute {
+                first_ts,
+                from: first_ts.add(TimeDelta::days(1)),
+                to: first_ts.add(TimeDelta::days(3)),
+            },
+            age_of_reset.num_minutes() as u32,
+        );
+        assert_eq!(actual.resolution_minutes, 60);
+        assert_eq!(
+            actual.from_event_time_minutes,
+            TimeDelta::days(1).num_minutes()
+        );
+        assert_eq!(actual.to_event_time_minutes, age_of_reset.num_minutes());
+    }
+
+    #[test]
+    fn test_around_event_at_start_of_reset() {
+        let age_of_reset = TimeDelta::days(7);
+        let actual = extract_reset_period(
+            ResetWindow::AroundEvent {
+                event_time_minutes: TimeDelta::hours(2).num_minutes() as u32,
+                window_minutes: TimeDelta::hours(6).num_minutes() as u32,
+            },
+            age_of_reset.num_minutes() as u32,
+        );
+        assert_eq!(actual.resolution_minutes, 15);
+        assert_eq!(actual.from_event_time_minutes, 0);
+        assert_eq!(
+            actual.to_event_time_minutes,
+            TimeDelta::hours(8).num_minutes()
+        );
+    }
+
+    #[test]
+    fn test_same_window_as_ongoing_reset() {
+        let age_of_ongoing_reset = TimeDelta::days(3);
+        let actual = extract_reset_period(
+            ResetWindow::SameWindowAsReference {
+                reference_num_minutes: age_of_ongoing_reset.num_minutes() as u32,
+                event_time_minutes_lte: LAST_DAY_TEST_DATA.event_time_minutes_lte,
+            },
+            TimeDelta::days(14).num_minutes() as u32,
+        );
+        assert_eq!(actual.resolution_minutes, 60);
+        assert_eq!(
+            actual.from_event_time_minutes,
+            TimeDelta::days(2).num_minutes()
+        );
+        assert_eq!(
+            actual.to_event_time_minutes,
+            age_of_ongoing_reset.num_minutes()
+        );
+    }
+
+    #[test]
+    fn test_same_window_as_reference_reset_that_is_longer() {
+        let age_of_reset = TimeDelta::days(2).add(TimeDelta::hours(12));
+        let actual = extract_reset_period(
+            ResetWindow::SameWindowAsReference {
+                reference_num_minutes: TimeDelta::days(3).num_minutes() as u32,
+                event_time_minutes_lte: LAST_DAY_TEST_DATA.event_time_minutes_lte,
+            },
+            age_of_reset.num_minutes() as u32,
+        );
+        assert_eq!(actual.resolution_minutes, 15);
+        assert_eq!(
+            actual.from_event_time_minutes,
+            TimeDelta::days(2).num_minutes()
+        );
+        assert_eq!(actual.to_event_time_minutes, age_of_reset.num_minutes());
+    }
+
+    fn resolution_override(resolution_minutes: u32) -> Result<ResetPeriodFilter, ApiError> {
+        let first_ts = NaiveDate::from_ymd_opt(2024, 3, 10)
+            .unwrap()
+            .and_hms_opt(0, 0, 0)
+            .unwrap();
+        let reset_infos = ResetDate {
+            reset_id: 1,
+            reset: first_ts.date(),
+            first_ts,
+            latest_ts: first_ts.add(TimeDelta::days(3)),
+            is_ongoing: true,
+        };
+        extract_reset_period_from_filter(
+            ResetWindow::Last {
+                event_time_minutes_lte: LAST_DAY_TEST_DATA.event_time_minutes_lte,
+            },
+            &ApiResetAgentPeriodFilterBody {
+                resolution_minutes: Some(resolution_minutes),
+                ..LAST_DAY_TEST_DATA
+            },
+            &reset_infos,
+        )
+    }
+
+    #[test]
+    fn test_resolution_override_within_bounds() {
+        let actual = resolution_override(15).unwrap();
+        assert_eq!(actual.resolution_minutes, 15);
+        assert_eq!(
+            actual.from_event_time_minutes,
+            TimeDelta::days(2).num_minutes()
+        );
+    }
+
+    #[test]
+    fn test_resolution_override_out_of_bounds_is_rejected() {
+        // below the minimum, above the maximum and not a multiple of 5
+        for resolution_minutes in [3, 1445, 7] {
+            assert!(
+                matches!(
+                    resolution_override(resolution_minutes),
+                    Err(ApiError::BadRequest(_))
+                ),
+                "resolutionMinutes={resolution_minutes} should be rejected"
+            );
+        }
+    }
+
+    #[tokio::test]
+    async fn test_query_cache_evicts_least_recently_used_entries() {
+        let query_cache = QueryCache::default();
+        let reset_date = NaiveDate::from_ymd_opt(2024, 3, 10).unwrap();
+        let get = |idx: usize| {
+            query_cache.get_or_load(
+                QueryCacheKey::for_reset(reset_date, format!("query {idx}")),
+                move || async move { Ok::<_, ApiError>(idx) },
+            )
+        };
+
+        for idx in 0..QUERY_CACHE_MAX_ENTRIES {
+            get(idx).await.unwrap();
+        }
+        // the oldest entry was used recently
+        get(0).await.unwrap();
+        get(QUERY_CACHE_MAX_ENTRIES).await.unwrap();
+
+        let stats = query_cache.stats();
+        assert_eq!(stats.hits, 1);
+        assert_eq!(stats.evicted_entries, QUERY_CACHE_EVICTION_BATCH as u64);
+        assert_eq!(
+            stats.entries,
+            QUERY_CACHE_MAX_ENTRIES - QUERY_CACHE_EVICTION_BATCH + 1
+        );
+
+        get(0).await.unwrap();
+        get(1).await.unwrap();
+        let stats = query_cache.stats();
+        assert_eq!(stats.hits, 2, "entry 0 is still cached");
+        assert_eq!(
+            stats.misses,
+            QUERY_CACHE_MAX_ENTRIES as u64 + 2,
+            "entry 1 got evicted"
+        );
+    }
+
+    fn leaderboard_entry(agent_symbol: &str, credits: i64) -> LeaderboardEntry {
+        LeaderboardEntry {
+            agent_symbol: agent_symbol.to_string(),
+            credits,
+            ship_count: 2,
+            agent_headquarters_waypoint_symbol: "X1-A1-HQ".to_string(),
+            jump_gate_waypoint_symbol: "X1-A1-JG".to_string(),
+            starting_faction: "COSMIC".to_string(),
+        }
+    }
+
+    #[test]
+    fn test_diff_leaderboards() {
+        let from = vec![
+            leaderboard_entry("AGENT-A", 300),
+            leaderboard_entry("AGENT-B", 200),
+            leaderboard_entry("AGENT-C", 100),
+        ];
+        let to = vec![
+            leaderboard_entry("AGENT-B", 500),
+            leaderboard_entry("AGENT-A", 400),
+            leaderboard_entry("AGENT-D", 50),
+        ];
+
+        let actual = diff_leaderboards(&from, &to);
+        let summary: Vec<_> = actual
+            .iter()
+            .map(|e| {
+                (
+                    e.agent_symbol.0.as_str(),
+                    e.rank_from,
+                    e.rank_to,
+                    e.rank_change,
+                    e.credits_gained,
+                    e.is_new_entrant,
+                )
+            })
+            .collect();
+
+        assert_eq!(
+            summary,
+            vec![
+                ("AGENT-B", Some(2), Some(1), Some(1), Some(300), false),
+                ("AGENT-A", Some(1), Some(2), Some(-1), Some(100), false),
+                // new entrant
+                ("AGENT-D", None, Some(3), None, None, true),
+                // dropped out
+                ("AGENT-C", Some(3), None, None, None, false),
+            ]
+        );
+        assert_eq!(actual[2].credits_from, None);
+        assert_eq!(actual[2].credits_to, Some(50));
+        assert_eq!(actual[3].credits_from, Some(100));
+        assert_eq!(actual[3].credits_to, None);
+    }
 }
@@ -0,0 +1,156 @@
+use std::time::Duration;
+
+use anyhow::Result;
+use axum::body::Body;
+use axum::extract::MatchedPath;
+use axum::http::{Request, Response};
+use opentelemetry::trace::TracerProvider as _;
+use opentelemetry::{global, KeyValue};
+use opentelemetry_http::HeaderExtractor;
+use opentelemetry_otlp::{SpanExporter, WithExportConfig};
+use opentelemetry_sdk::propagation::TraceContextPropagator;
+use opentelemetry_sdk::trace::TracerProvider;
+use opentelemetry_sdk::{runtime, Resource};
+use reqwest::Url;
+use tracing::field::Empty;
+use tracing::{event, info_span, Level, Span};
+use tracing_opentelemetry::OpenTelemetrySpanExt;
+use tracing_subscriber::{fmt, prelude::*, EnvFilter};
+
+const SERVICE_NAME: &str = "flwi-spacetraders-leaderboard";
+
+const REQUEST_ID_HEADER: &str = "x-request-id";
+
+#[derive(clap::ValueEnum, Debug, Clone, Copy, PartialEq, Eq)]
+pub(crate) enum LogFormat {
+    /// human-readable lines
+    Text,
+    /// one json object per line including the fields of the enclosing spans (e.g. the request id)
+    Json,
+}
+
+#[derive(Debug, Clone)]
+pub(crate) struct OtlpSettings {
+    /// base url of the otlp/http collector, e.g. `http://localhost:4318`
+    pub(crate) endpoint: Url,
+    /// filter directives of the exported spans - independent of `RUST_LOG`
+    pub(crate) filter: String,
+}
+
+/// Flushes the spans that haven't been exported yet on drop.
+pub(crate) struct TelemetryGuard {
+    maybe_tracer_provider: Option<TracerProvider>,
+}
+
+impl Drop for TelemetryGuard {
+    fn drop(&mut self) {
+        if let Some(tracer_provider) = self.maybe_tracer_provider.take() {
+            if let Err(err) = tracer_provider.shutdown() {
+                eprintln!("Failed to flush the otlp exporter: {err}");
+            }
+        }
+    }
+}
+
+/// Logs to stdout (filtered by `RUST_LOG`) and optionally exports the spans via otlp.
+pub(crate) fn init_tracing(
+    log_format: LogFormat,
+    maybe_otlp_settings: Option<&OtlpSettings>,
+) -> Result<TelemetryGuard> {
+    let maybe_tracer_provider = maybe_otlp_settings
+        .map(|settings| {
+            let exporter = SpanExporter::builder()
+                .with_http()
+                .with_endpoint(settings.endpoint.join("v1/traces")?.to_string())
+                .build()?;
+            anyhow::Ok(
+                TracerProvider::builder()
+                    .with_batch_exporter(exporter, runtime::Tokio)
+                    .with_resource(Resource::new([KeyValue::new("service.name", SERVICE_NAME)]))
+                    .build(),
+            )
+        })
+        .transpose()?;
+
+    let otel_layer = match (maybe_otlp_settings, &maybe_tracer_provider) {
+        (Some(settings), Some(tracer_provider)) => Some(
+            tracing_opentelemetry::layer()
+                .with_tracer(tracer_provider.tracer(SERVICE_NAME))
+                .with_filter(EnvFilter::try_new(&settings.filter)?),
+        ),
+        _ => None,
+    };
+
+    global::set_text_map_propagator(TraceContextPropagator::new());
+
+    let fmt_layer = match log_format {
+        LogFormat::Text => fmt::layer().boxed(),
+        LogFormat::Json => fmt::layer()
+            .json()
+            .flatten_event(true)
+            .with_current_span(false)
+            .with_span_list(true)
+            .boxed(),
+    };
+
+    tracing_subscriber::registry()
+        .with(fmt_layer.with_filter(EnvFilter::from_default_env()))
+        .with(otel_layer)
+        .init();
+
+    if let Some(settings) = maybe_otlp_settings {
+        event!(
+            Level::INFO,
+            "Exporting traces to {} with filter '{}'",
+            settings.endpoint,
+            settings.filter
+        );
+    }
+
+    Ok(TelemetryGuard {
+        maybe_tracer_provider,
+    })
+}
+
+/// Span of an http request to the api. Continues the trace of the caller if it sent a `traceparent` header.
+/// On info level to attach the request id to all log lines of the request.
+pub(crate) fn make_http_request_span(request: &Request<Body>) -> Span {
+    let route = request
+        .extensions()
+        .get::<MatchedPath>()
+        .map(|path| path.as_str())
+        .unwrap_or("unmatched");
+    let method = request.method();
+    let request_id = request
+        .headers()
+        .get(REQUEST_ID_HEADER)
+        .and_then(|value| value.to_str().ok())
+        .unwrap_or_default();
+
+    let span = info_span!(
+        "http_request",
+        request_id,
+        otel.name = format!("{method} {route}"),
+        otel.kind = "server",
+        http.request.method = %method,
+        http.route = route,
+        url.path = request.uri().path(),
+        http.response.status_code = Empty,
+    );
+
+    let parent_context = global::get_text_map_propagator(|propagator| {
+        propagator.extract(&HeaderExtractor(request.headers()))
+    });
+    span.set_parent(parent_context);
+    span
+}
+
+pub(crate) fn record_http_response<B>(response: &Response<B>, latency: Duration, span: &Span) {
+    span.record("http.response.status_code", response.status().as_u16());
+    event!(
+        Level::INFO,
+        latency_ms = latency.as_millis() as u64,
+        status = response.status().as_u16(),
+        "finished processing request"
+    );
+}
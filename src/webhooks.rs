@@ -0,0 +1,398 @@
+use std::str::FromStr;
+use std::sync::Arc;
+use std::time::Duration;
+
+use chrono::Utc;
+use reqwest::{header, Client, StatusCode, Url};
+use serde_json::json;
+use sqlx::{Pool, Sqlite};
+use tokio::sync::broadcast;
+use tracing::{event, Level};
+
+use crate::app_metrics::record_webhook_delivery;
+use crate::db::{
+    insert_webhook_delivery, select_pending_webhook_deliveries, update_webhook_delivery,
+};
+use crate::leaderboard_collector::TickCompleted;
+use crate::leaderboard_events::{detect_events, DetectedEvent};
+
+const MAX_DELIVERY_ATTEMPTS: u32 = 5;
+const INITIAL_RETRY_DELAY: Duration = Duration::from_secs(2);
+/// upper bound of the `Retry-After` we honor - a longer wait would delay the events of the next tick
+const MAX_RETRY_DELAY: Duration = Duration::from_secs(60);
+const REQUEST_TIMEOUT: Duration = Duration::from_secs(10);
+
+#[derive(clap::ValueEnum, Debug, Clone, Copy, PartialEq, Eq)]
+pub(crate) enum WebhookFormat {
+    /// `{"message": ..., "event": {...}}` with the full event
+    Json,
+    Discord,
+    Slack,
+}
+
+/// Configured as `<name>:<format>:<url>`, e.g. `alerts:discord:https://discord.com/api/webhooks/...`.
+/// The name identifies the webhook in the delivery log - the url might contain a secret.
+#[derive(Debug, Clone)]
+pub(crate) struct WebhookConfig {
+    pub(crate) name: String,
+    pub(crate) format: WebhookFormat,
+    pub(crate) url: Url,
+}
+
+impl FromStr for WebhookConfig {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let (name, rest) = s
+            .split_once(':')
+            .ok_or_else(|| format!("expected <name>:<format>:<url>, got '{s}'"))?;
+        let (format, url) = rest
+            .split_once(':')
+            .ok_or_else(|| format!("expected <name>:<format>:<url>, got '{s}'"))?;
+        if name.is_empty() {
+            return Err("the name of a webhook must not be empty".to_string());
+        }
+
+        Ok(Self {
+            name: name.to_string(),
+            format: <WebhookFormat as clap::ValueEnum>::from_str(format, true)?,
+            url: Url::parse(url).map_err(|e| e.to_string())?,
+        })
+    }
+}
+
+/// Delivers the events detected after each tick to the configured webhooks.
+/// Every delivery is recorded in the `webhook_delivery` table.
+#[derive(Clone)]
+pub(crate) struct Webhooks {
+    inner: Arc<WebhooksInner>,
+}
+
+struct WebhooksInner {
+    configs: Vec<WebhookConfig>,
+    client: Client,
+}
+
+enum DeliveryError {
+    /// worth another attempt - e.g. a timeout, 429 or 5xx
+    Transient {
+        http_status: Option<StatusCode>,
+        detail: String,
+        retry_after: Option<Duration>,
+    },
+    Permanent {
+        http_status: Option<StatusCode>,
+        detail: String,
+    },
+}
+
+impl Webhooks {
+    pub(crate) fn new(configs: Vec<WebhookConfig>) -> Self {
+        Self {
+            inner: Arc::new(WebhooksInner {
+                configs,
+                client: Client::builder().timeout(REQUEST_TIMEOUT).build().unwrap(),
+            }),
+        }
+    }
+
+    /// Listens for completed ticks of the collector. Does nothing if no webhook is configured.
+    /// Deliveries that were still pending when the server stopped are retried first.
+    pub(crate) async fn deliver_on_tick(
+        self,
+        pool: Pool<Sqlite>,
+        mut receiver: broadcast::Receiver<TickCompleted>,
+    ) {
+        if self.inner.configs.is_empty() {
+            return;
+        }
+
+        let pending_deliveries = self
+            .inner
+            .configs
+            .iter()
+            .map(|config| self.deliver_pending(&pool, config));
+        futures::future::join_all(pending_deliveries).await;
+
+        loop {
+            let tick = match receiver.recv().await {
+                Ok(tick) => tick,
+                Err(broadcast::error::RecvError::Lagged(num_skipped)) => {
+                    event!(
+                        Level::WARN,
+                        num_skipped,
+                        "Webhooks lagged behind - events of the skipped ticks are not delivered"
+                    );
+                    continue;
+                }
+                Err(broadcast::error::RecvError::Closed) => return,
+            };
+
+            let events = match detect_events(&pool, tick).await {
+                Ok(events) => events,
+                Err(err) => {
+                    event!(Level::ERROR, reset = %tick.reset_date, "Error detecting leaderboard events: {err:?}");
+                    continue;
+                }
+            };
+            event!(Level::DEBUG, reset = %tick.reset_date, num_events = events.len(), "Detected leaderboard events");
+
+            let deliveries = self
+                .inner
+                .configs
+                .iter()
+                .map(|config| self.deliver_all(&pool, config, &events));
+            futures::future::join_all(deliveries).await;
+        }
+    }
+
+    async fn deliver_pending(&self, pool: &Pool<Sqlite>, config: &WebhookConfig) {
+        let pending = match select_pending_webhook_deliveries(pool, &config.name).await {
+            Ok(pending) => pending,
+            Err(err) => {
+                event!(
+                    Level::ERROR,
+                    webhook = config.name,
+                    "Error loading pending webhook deliveries: {err:?}"
+                );
+                return;
+            }
+        };
+        if !pending.is_empty() {
+            event!(
+                Level::INFO,
+                webhook = config.name,
+                num_pending = pending.len(),
+                "Retrying pending webhook deliveries"
+            );
+        }
+
+        for delivery in pending {
+            self.deliver_logged(
+                pool,
+                config,
+                delivery.id,
+                &delivery.event_key,
+                &delivery.payload,
+            )
+            .await;
+        }
+    }
+
+    /// Delivers the events in order. Events that are already in the delivery log are skipped.
+    async fn deliver_all(
+        &self,
+        pool: &Pool<Sqlite>,
+        config: &WebhookConfig,
+        events: &[DetectedEvent],
+    ) {
+        for detected in events {
+            let payload = render_payload(config.format, detected).to_string();
+            let delivery_id = match insert_webhook_delivery(
+                pool,
+                &config.name,
+                &detected.key,
+                detected.event.kind(),
+                &payload,
+                Utc::now().naive_utc(),
+            )
+            .await
+            {
+                Ok(Some(id)) => id,
+                Ok(None) => continue,
+                Err(err) => {
+                    event!(
+                        Level::ERROR,
+                        webhook = config.name,
+                        "Error logging webhook delivery: {err:?}"
+                    );
+                    continue;
+                }
+            };
+
+            self.deliver_logged(pool, config, delivery_id, &detected.key, &payload)
+                .await;
+        }
+    }
+
+    /// Delivers a payload of the delivery log and records the outcome
+    async fn deliver_logged(
+        &self,
+        pool: &Pool<Sqlite>,
+        config: &WebhookConfig,
+        delivery_id: i64,
+        event_key: &str,
+        payload: &str,
+    ) {
+        let (status, attempts, last_http_status, last_error) =
+            self.deliver_with_retries(config, payload).await;
+        record_webhook_delivery(&config.name, status);
+        if status == "failed" {
+            event!(
+                Level::WARN,
+                webhook = config.name,
+                event_key,
+                attempts,
+                error = last_error.as_deref().unwrap_or_default(),
+                "Webhook delivery failed"
+            );
+        }
+
+        if let Err(err) = update_webhook_delivery(
+            pool,
+            delivery_id,
+            status,
+            i64::from(attempts),
+            last_http_status.map(|s| i64::from(s.as_u16())),
+            last_error.as_deref(),
+            Utc::now().naive_utc(),
+        )
+        .await
+        {
+            event!(
+                Level::ERROR,
+                webhook = config.name,
+                "Error logging webhook delivery: {err:?}"
+            );
+        }
+    }
+
+    /// Returns status, number of attempts, http status and error of the last attempt
+    async fn deliver_with_retries(
+        &self,
+        config: &WebhookConfig,
+        payload: &str,
+    ) -> (&'static str, u32, Option<StatusCode>, Option<String>) {
+        let mut delay = INITIAL_RETRY_DELAY;
+        let mut attempt = 1;
+        loop {
+            match self.deliver(config, payload).await {
+                Ok(http_status) => return ("delivered", attempt, Some(http_status), None),
+                Err(DeliveryError::Permanent {
+                    http_status,
+                    detail,
+                }) => return ("failed", attempt, http_status, Some(detail)),
+                Err(DeliveryError::Transient {
+                    http_status,
+                    detail,
+                    retry_after,
+                }) => {
+                    if attempt >= MAX_DELIVERY_ATTEMPTS {
+                        return ("failed", attempt, http_status, Some(detail));
+                    }
+                    event!(
+                        Level::DEBUG,
+                        webhook = config.name,
+                        attempt,
+                        error = detail,
+                        "Webhook delivery failed - retrying"
+                    );
+                    tokio::time::sleep(retry_after.unwrap_or(delay).min(MAX_RETRY_DELAY)).await;
+                    delay *= 2;
+                    attempt += 1;
+                }
+            }
+        }
+    }
+
+    async fn deliver(
+        &self,
+        config: &WebhookConfig,
+        payload: &str,
+    ) -> Result<StatusCode, DeliveryError> {
+        let response = self
+            .inner
+            .client
+            .post(config.url.clone())
+            .header(header::CONTENT_TYPE, "application/json")
+            .body(payload.to_string())
+            .send()
+            .await
+            .map_err(|err| DeliveryError::Transient {
+                http_status: None,
+                detail: err.to_string(),
+                retry_after: None,
+            })?;
+
+        let http_status = response.status();
+        if http_status.is_success() {
+            return Ok(http_status);
+        }
+
+        let retry_after = response
+            .headers()
+            .get(header::RETRY_AFTER)
+            .and_then(|value| value.to_str().ok())
+            .and_then(parse_retry_after);
+        let detail = format!(
+            "{http_status}: {}",
+            response.text().await.unwrap_or_default()
+        );
+
+        if http_status == StatusCode::TOO_MANY_REQUESTS || http_status.is_server_error() {
+            Err(DeliveryError::Transient {
+                http_status: Some(http_status),
+                detail,
+                retry_after,
+            })
+        } else {
+            Err(DeliveryError::Permanent {
+                http_status: Some(http_status),
+                detail,
+            })
+        }
+    }
+}
+
+/// Seconds of a `Retry-After` header, clamped to [MAX_RETRY_DELAY]. `None` for anything that
+/// isn't a finite, non-negative number - the endpoint is not trusted to send a sane value.
+fn parse_retry_after(value: &str) -> Option<Duration> {
+    let seconds = value
+        .trim()
+        .parse::<f64>()
+        .ok()
+        .filter(|seconds| seconds.is_finite())?;
+    Duration::try_from_secs_f64(seconds.min(MAX_RETRY_DELAY.as_secs_f64())).ok()
+}
+
+fn render_payload(format: WebhookFormat, detected: &DetectedEvent) -> serde_json::Value {
+    let message = detected.event.message();
+    match format {
+        WebhookFormat::Json => json!({
+            "message": message,
+            "key": detected.key,
+            "queryTime": detected.query_time,
+            "event": detected.event,
+        }),
+        WebhookFormat::Discord => json!({ "content": message }),
+        WebhookFormat::Slack => json!({ "text": message }),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn retry_after_is_clamped() {
+        assert_eq!(parse_retry_after("2"), Some(Duration::from_secs(2)));
+        assert_eq!(parse_retry_after(" 0.5 "), Some(Duration::from_millis(500)));
+        assert_eq!(parse_retry_after("3600"), Some(MAX_RETRY_DELAY));
+        assert_eq!(parse_retry_after("1e30"), Some(MAX_RETRY_DELAY));
+    }
+
+    #[test]
+    fn bad_retry_after_values_are_ignored() {
+        for value in [
+            "-1",
+            "NaN",
+            "inf",
+            "-inf",
+            "",
+            "soon",
+            "Wed, 21 Oct 2026 07:28:00 GMT",
+        ] {
+            assert_eq!(parse_retry_after(value), None, "Retry-After: {value}");
+        }
+    }
+}